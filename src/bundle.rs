@@ -0,0 +1,410 @@
+use crate::config::{ConfigLayer, Environment};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Format identifier of bundle files
+pub const BUNDLE_FORMAT: &str = "hostctl-bundle/1";
+
+/// A public key whose signatures are accepted on import
+///
+/// # Example
+/// ```yaml
+/// trusted_keys:
+///   - name: platform-team
+///     key: 3n0a4dk5cPLY0Y2lB1ZbYpQ4xM2Vh2Zr8QzB3h0u1cE=
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedKey {
+    /// Name shown when a bundle signed with the key is imported
+    pub name: String,
+    /// Base64-encoded Ed25519 public key
+    pub key: String,
+    /// Layer this key was configured in (not stored in the file)
+    #[serde(skip)]
+    pub layer: ConfigLayer,
+}
+
+/// Signature of a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSignature {
+    /// Base64-encoded public key of the signer
+    pub key: String,
+    /// Base64-encoded Ed25519 signature of the payload
+    pub value: String,
+}
+
+/// A self-contained, optionally signed export of one environment
+///
+/// The environment is stored as a JSON string so the signed bytes are exactly the
+/// bytes in the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    /// Format identifier, always `BUNDLE_FORMAT`
+    pub format: String,
+    /// The exported environment, serialized as JSON
+    pub payload: String,
+    /// Signature of `payload`, if the bundle was signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<BundleSignature>,
+}
+
+/// Contents of a bundle payload
+#[derive(Debug, Serialize, Deserialize)]
+struct Payload {
+    exported_at: DateTime<Utc>,
+    environment: Environment,
+}
+
+/// Outcome of checking a bundle's signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// Validly signed by a trusted key
+    Trusted {
+        /// Name of the trusted key
+        name: String,
+    },
+    /// Not signed at all
+    Unsigned,
+    /// Validly signed, but by a key that is not trusted
+    UntrustedKey {
+        /// Base64-encoded public key of the signer
+        key: String,
+    },
+    /// The signature does not match the contents: the bundle was modified or corrupted
+    Invalid,
+}
+
+impl Verification {
+    /// Check whether the bundle may be imported without an override
+    #[must_use]
+    pub fn is_trusted(&self) -> bool {
+        matches!(self, Self::Trusted { .. })
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trusted { name } => write!(f, "signed by trusted key '{name}'"),
+            Self::Unsigned => write!(f, "bundle is not signed"),
+            Self::UntrustedKey { key } => write!(f, "bundle is signed by untrusted key {key}"),
+            Self::Invalid => write!(
+                f,
+                "bundle signature is invalid; the bundle was modified after signing"
+            ),
+        }
+    }
+}
+
+/// Bytes covered by a bundle signature
+fn signed_message(payload: &str) -> Vec<u8> {
+    [BUNDLE_FORMAT.as_bytes(), b"\n", payload.as_bytes()].concat()
+}
+
+/// Decode a base64-encoded public key
+///
+/// # Arguments
+/// * `key` - The encoded key
+///
+/// # Errors
+/// Returns an error if the key is not a valid Ed25519 public key.
+pub fn decode_public_key(key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("Invalid public key: {key}"))?;
+    VerifyingKey::from_bytes(&bytes).with_context(|| format!("Invalid public key: {key}"))
+}
+
+/// Encode a public key as base64
+#[must_use]
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.to_bytes())
+}
+
+/// Generate a new signing key and write it to a file
+///
+/// The secret key is written to `path` (readable only by the owner on Unix) and the
+/// public key to `path` with an added `.pub` extension.
+///
+/// # Arguments
+/// * `path` - Path of the secret key file
+///
+/// # Returns
+/// Returns the base64-encoded public key
+///
+/// # Errors
+/// Returns an error if a key file already exists or cannot be written.
+pub fn generate_key(path: &Path) -> Result<String> {
+    let public_path = path.with_added_extension("pub");
+    for file in [path, public_path.as_path()] {
+        if file.exists() {
+            anyhow::bail!("Key file already exists: {}", file.display());
+        }
+    }
+
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed)
+        .map_err(|e| anyhow::anyhow!("Failed to generate a random key: {e}"))?;
+    let key = SigningKey::from_bytes(&seed);
+    let public = encode_public_key(&key.verifying_key());
+
+    write_secret(path, &format!("{}\n", STANDARD.encode(seed)))?;
+    fs::write(&public_path, format!("{public}\n"))
+        .with_context(|| format!("Failed to write public key: {}", public_path.display()))?;
+    Ok(public)
+}
+
+/// Write a secret key file that only its owner can read
+fn write_secret(path: &Path, content: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .with_context(|| format!("Failed to write secret key: {}", path.display()))
+}
+
+/// Read a secret key file written by `generate_key`
+///
+/// # Arguments
+/// * `path` - Path of the secret key file
+///
+/// # Errors
+/// Returns an error if the file cannot be read or does not contain a key.
+pub fn load_signing_key(path: &Path) -> Result<SigningKey> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read secret key: {}", path.display()))?;
+    let seed: [u8; 32] = STANDARD
+        .decode(content.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("Invalid secret key file: {}", path.display()))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+impl Bundle {
+    /// Create a bundle of an environment
+    ///
+    /// # Arguments
+    /// * `env` - The environment to export
+    /// * `key` - Key to sign the bundle with, if any
+    ///
+    /// # Errors
+    /// Returns an error if the environment cannot be serialized.
+    pub fn create(env: &Environment, key: Option<&SigningKey>) -> Result<Self> {
+        let payload = serde_json::to_string(&Payload {
+            exported_at: Utc::now(),
+            environment: env.clone(),
+        })?;
+        let signature = key.map(|key| BundleSignature {
+            key: encode_public_key(&key.verifying_key()),
+            value: STANDARD.encode(key.sign(&signed_message(&payload)).to_bytes()),
+        });
+
+        Ok(Self {
+            format: BUNDLE_FORMAT.to_string(),
+            payload,
+            signature,
+        })
+    }
+
+    /// Parse a bundle file
+    ///
+    /// # Arguments
+    /// * `content` - The file contents
+    ///
+    /// # Errors
+    /// Returns an error if the contents are not a bundle of a supported format.
+    pub fn parse(content: &str) -> Result<Self> {
+        let bundle: Self = serde_json::from_str(content).context("Invalid bundle file")?;
+        if bundle.format != BUNDLE_FORMAT {
+            anyhow::bail!(
+                "Unsupported bundle format '{}' (expected {BUNDLE_FORMAT})",
+                bundle.format
+            );
+        }
+        Ok(bundle)
+    }
+
+    /// Serialize the bundle for writing to a file
+    ///
+    /// # Errors
+    /// Returns an error if the bundle cannot be serialized.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// Check the bundle's signature against the trusted keys
+    ///
+    /// # Arguments
+    /// * `trusted` - Keys whose signatures are accepted
+    #[must_use]
+    pub fn verify(&self, trusted: &[TrustedKey]) -> Verification {
+        let Some(signature) = &self.signature else {
+            return Verification::Unsigned;
+        };
+        let valid = decode_public_key(&signature.key).is_ok_and(|key| {
+            STANDARD
+                .decode(&signature.value)
+                .ok()
+                .and_then(|bytes| Signature::from_slice(&bytes).ok())
+                .is_some_and(|sig| {
+                    key.verify_strict(&signed_message(&self.payload), &sig)
+                        .is_ok()
+                })
+        });
+        if !valid {
+            return Verification::Invalid;
+        }
+
+        let signer = decode_public_key(&signature.key).ok();
+        trusted
+            .iter()
+            .find(|trusted| decode_public_key(&trusted.key).ok() == signer)
+            .map_or_else(
+                || Verification::UntrustedKey {
+                    key: signature.key.clone(),
+                },
+                |trusted| Verification::Trusted {
+                    name: trusted.name.clone(),
+                },
+            )
+    }
+
+    /// The environment contained in the bundle
+    ///
+    /// # Errors
+    /// Returns an error if the payload cannot be parsed.
+    pub fn environment(&self) -> Result<Environment> {
+        let payload: Payload =
+            serde_json::from_str(&self.payload).context("Invalid bundle payload")?;
+        Ok(payload.environment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostEntry;
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32])
+    }
+
+    fn trusted(name: &str, key: &SigningKey) -> TrustedKey {
+        TrustedKey {
+            name: name.to_string(),
+            key: encode_public_key(&key.verifying_key()),
+            layer: ConfigLayer::User,
+        }
+    }
+
+    fn environment() -> Environment {
+        let mut env = Environment::new("staging".to_string());
+        env.add_entry(HostEntry::new(
+            "10.0.0.1".parse().unwrap(),
+            "api.example.com".to_string(),
+        ));
+        env
+    }
+
+    #[test]
+    fn test_signed_bundle_round_trip() {
+        let signer = key(1);
+        let bundle = Bundle::create(&environment(), Some(&signer)).unwrap();
+        let parsed = Bundle::parse(&bundle.to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            parsed.verify(&[trusted("other", &key(2)), trusted("platform", &signer)]),
+            Verification::Trusted {
+                name: "platform".to_string()
+            }
+        );
+        let env = parsed.environment().unwrap();
+        assert_eq!(env.name, "staging");
+        assert_eq!(env.entries[0].hostname, "api.example.com");
+    }
+
+    #[test]
+    fn test_untrusted_and_unsigned_bundles() {
+        let signer = key(1);
+        let bundle = Bundle::create(&environment(), Some(&signer)).unwrap();
+        assert!(matches!(
+            bundle.verify(&[trusted("other", &key(2))]),
+            Verification::UntrustedKey { .. }
+        ));
+
+        let unsigned = Bundle::create(&environment(), None).unwrap();
+        assert_eq!(
+            unsigned.verify(&[trusted("platform", &signer)]),
+            Verification::Unsigned
+        );
+        assert!(!unsigned.to_json().unwrap().contains("signature"));
+    }
+
+    #[test]
+    fn test_tampered_bundle_is_invalid() {
+        let signer = key(1);
+        let trusted_keys = [trusted("platform", &signer)];
+        let bundle = Bundle::create(&environment(), Some(&signer)).unwrap();
+
+        let mut tampered = bundle.clone();
+        tampered.payload = tampered.payload.replace("10.0.0.1", "6.6.6.6");
+        assert_eq!(tampered.verify(&trusted_keys), Verification::Invalid);
+
+        // Replacing the signer key does not make the signature valid
+        let mut resigned = bundle.clone();
+        resigned.signature.as_mut().unwrap().key = encode_public_key(&key(2).verifying_key());
+        assert_eq!(resigned.verify(&trusted_keys), Verification::Invalid);
+
+        let mut garbled = bundle;
+        garbled.signature.as_mut().unwrap().value = "not base64!".to_string();
+        assert_eq!(garbled.verify(&trusted_keys), Verification::Invalid);
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let content = r#"{"format": "hostctl-bundle/99", "payload": "{}"}"#;
+        assert!(Bundle::parse(content).is_err());
+    }
+
+    #[test]
+    fn test_generate_and_load_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("team.key");
+
+        let public = generate_key(&path).unwrap();
+        let key = load_signing_key(&path).unwrap();
+        assert_eq!(encode_public_key(&key.verifying_key()), public);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("team.key.pub"))
+                .unwrap()
+                .trim(),
+            public
+        );
+        assert!(generate_key(&path).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
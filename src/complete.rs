@@ -0,0 +1,355 @@
+use crate::config::Config;
+use clap::{Arg, Command};
+
+/// Where the word being completed sits in a partial command line
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Names of the subcommands given, outermost first
+    pub commands: Vec<String>,
+    /// Positional arguments given to the innermost subcommand
+    pub positionals: Vec<String>,
+    /// Values of the options given, by argument ID, in command line order
+    pub options: Vec<(String, String)>,
+    /// ID of the argument whose value is being completed, if any
+    pub argument: Option<String>,
+    /// The (partial) word being completed
+    pub prefix: String,
+}
+
+impl Position {
+    /// Locate the last word of a partial command line
+    ///
+    /// Options are looked up in the command definition to tell which ones take a
+    /// value, so e.g. `switch --for 30m <TAB>` completes the environment name.
+    ///
+    /// # Arguments
+    /// * `command` - The built command definition (see [`Command::build`])
+    /// * `words` - The words after the program name; the last one is being completed
+    #[must_use]
+    pub fn parse(command: &Command, words: &[String]) -> Self {
+        let mut position = Self::default();
+        let Some((current, before)) = words.split_last() else {
+            return position;
+        };
+
+        let mut command = command;
+        let mut pending: Option<&Arg> = None;
+        let mut options_ended = false;
+        for word in before {
+            if let Some(arg) = pending.take() {
+                position.add_option(arg, word);
+            } else if options_ended || word == "-" || !word.starts_with('-') {
+                match command.find_subcommand(word) {
+                    Some(subcommand) if position.positionals.is_empty() && !options_ended => {
+                        command = subcommand;
+                        position.commands.push(subcommand.get_name().to_string());
+                    }
+                    _ => position.positionals.push(word.clone()),
+                }
+            } else if word == "--" {
+                options_ended = true;
+            } else if let Some(long) = word.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                let arg = command.get_arguments().find(|arg| {
+                    arg.get_long() == Some(name)
+                        || arg
+                            .get_all_aliases()
+                            .is_some_and(|aliases| aliases.contains(&name))
+                });
+                match (arg, value) {
+                    (Some(arg), Some(value)) => position.add_option(arg, value),
+                    (Some(arg), None) if takes_value(arg) => pending = Some(arg),
+                    _ => {}
+                }
+            } else {
+                // A cluster of short flags, the last of which may take a value
+                for (index, short) in word.char_indices().skip(1) {
+                    let Some(arg) = command
+                        .get_arguments()
+                        .find(|arg| arg.get_short() == Some(short))
+                    else {
+                        break;
+                    };
+                    if takes_value(arg) {
+                        let value = &word[index + short.len_utf8()..];
+                        if value.is_empty() {
+                            pending = Some(arg);
+                        } else {
+                            position.add_option(arg, value);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        position.prefix.clone_from(current);
+        position.argument = match pending {
+            Some(arg) => Some(arg.get_id().to_string()),
+            // Option names and subcommands are completed by the generated scripts
+            None if current.starts_with('-') && !options_ended => None,
+            None if command.has_subcommands() && position.positionals.is_empty() => None,
+            None => command
+                .get_positionals()
+                .nth(position.positionals.len())
+                .map(|arg| arg.get_id().to_string()),
+        };
+        position
+    }
+
+    /// Value of an option, if given
+    ///
+    /// # Arguments
+    /// * `id` - ID of the option's argument
+    ///
+    /// # Returns
+    /// Returns the last value given for the option
+    #[must_use]
+    pub fn option(&self, id: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == id)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn add_option(&mut self, arg: &Arg, value: &str) {
+        self.options
+            .push((arg.get_id().to_string(), value.to_string()));
+    }
+
+    /// Values from the configuration for the word being completed
+    ///
+    /// Environment names are offered for arguments naming an existing environment,
+    /// the hostnames of the environment given before for `remove-entry`, and the
+    /// names of targets and sources for their options.
+    ///
+    /// # Arguments
+    /// * `config` - The loaded configuration
+    ///
+    /// # Returns
+    /// Returns the matching candidates, sorted
+    #[must_use]
+    pub fn candidates(&self, config: &Config) -> Vec<String> {
+        let command = self.commands.last().map_or("", String::as_str);
+        let mut candidates: Vec<String> = match (command, self.argument.as_deref()) {
+            (_, None) | ("add" | "import", _) => Vec::new(),
+            (_, Some("target")) => config.targets.keys().cloned().collect(),
+            (
+                "list" | "show" | "current" | "status" | "history" | "log" | "sources",
+                Some("output"),
+            ) => ["plain", "table", "json", "yaml"]
+                .map(String::from)
+                .to_vec(),
+            ("convert", Some("to")) => ["yaml", "toml", "json"].map(String::from).to_vec(),
+            ("update", Some("name")) => config
+                .sources
+                .iter()
+                .map(|source| source.name.clone())
+                .collect(),
+            ("remove-entry", Some("hostname")) => self
+                .positionals
+                .first()
+                .and_then(|name| config.resolve_environment_name(name))
+                .and_then(|name| config.get_environment(name))
+                .map(|env| {
+                    env.entries
+                        .iter()
+                        .map(|entry| entry.hostname.clone())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            (_, Some("name" | "environment")) => config.environments.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        candidates.retain(|candidate| candidate.starts_with(&self.prefix));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// Shell code that asks `hostctl __complete` for values from the configuration
+///
+/// Appended to the script generated by `clap_complete`, whose completions are
+/// used whenever hostctl has no candidates of its own.
+///
+/// # Arguments
+/// * `shell` - The shell the script is for
+///
+/// # Returns
+/// Returns `None` for shells without dynamic completion
+#[must_use]
+pub fn dynamic_script(shell: clap_complete::Shell) -> Option<&'static str> {
+    match shell {
+        clap_complete::Shell::Bash => Some(
+            r#"
+_hostctl_dynamic() {
+    local candidates=()
+    IFS=$'\n' read -r -d '' -a candidates \
+        < <(hostctl __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null)
+    if [[ ${#candidates[@]} -gt 0 ]]; then
+        COMPREPLY=("${candidates[@]}")
+        return 0
+    fi
+    _hostctl "$@"
+}
+
+complete -F _hostctl_dynamic -o bashdefault -o default hostctl
+"#,
+        ),
+        clap_complete::Shell::Zsh => Some(
+            r#"
+_hostctl_dynamic() {
+    local -a candidates
+    candidates=(${(f)"$(hostctl __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -a candidates
+    else
+        _hostctl "$@"
+    fi
+}
+
+compdef _hostctl_dynamic hostctl
+"#,
+        ),
+        clap_complete::Shell::Fish => Some(
+            r"
+function __hostctl_candidates
+    set -l words (commandline -opc) (commandline -ct)
+    hostctl __complete -- $words[2..-1] 2>/dev/null
+end
+
+complete -c hostctl -n 'test (count (__hostctl_candidates)) -gt 0' -f -a '(__hostctl_candidates)'
+",
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Environment, HostEntry};
+    use clap::{Parser, Subcommand};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[arg(long, global = true)]
+        config: Option<String>,
+        #[command(subcommand)]
+        command: Commands,
+    }
+
+    #[derive(Subcommand)]
+    enum Commands {
+        Add {
+            name: String,
+        },
+        Switch {
+            name: String,
+            #[arg(long = "for")]
+            duration: Option<String>,
+            #[arg(long)]
+            target: Option<String>,
+            #[arg(short, long)]
+            force: bool,
+        },
+        RemoveEntry {
+            environment: String,
+            hostname: String,
+        },
+        List {
+            #[arg(short, long)]
+            output: Option<String>,
+        },
+    }
+
+    fn position(line: &str) -> Position {
+        let mut command = <Cli as clap::CommandFactory>::command();
+        command.build();
+        let mut words: Vec<String> = line.split(' ').map(String::from).collect();
+        if line.is_empty() {
+            words = vec![String::new()];
+        }
+        Position::parse(&command, &words)
+    }
+
+    fn config() -> Config {
+        let mut config = Config::new();
+        let mut dev = Environment::new("dev".to_string());
+        dev.add_entry(HostEntry::new(
+            "10.0.0.1".parse().unwrap(),
+            "api.local".to_string(),
+        ));
+        dev.add_entry(HostEntry::new(
+            "10.0.0.2".parse().unwrap(),
+            "web.local".to_string(),
+        ));
+        config.add_environment(dev);
+        config.add_environment(Environment::new("stage".to_string()));
+        config
+            .targets
+            .insert("devbox".to_string(), "/srv/devbox/etc/hosts".into());
+        config
+    }
+
+    #[test]
+    fn test_parse_position() {
+        let switch = position("--config /tmp/c.yaml switch --for 30m -f d");
+        assert_eq!(switch.commands, ["switch"]);
+        assert_eq!(switch.argument.as_deref(), Some("name"));
+        assert_eq!(switch.prefix, "d");
+        assert_eq!(switch.option("config"), Some("/tmp/c.yaml"));
+        assert_eq!(switch.option("duration"), Some("30m"));
+
+        let target = position("switch --target ");
+        assert_eq!(target.argument.as_deref(), Some("target"));
+
+        let hostname = position("remove-entry dev ");
+        assert_eq!(hostname.positionals, ["dev"]);
+        assert_eq!(hostname.argument.as_deref(), Some("hostname"));
+
+        let inline = position("--config=/tmp/c.yaml list -o");
+        assert_eq!(inline.option("config"), Some("/tmp/c.yaml"));
+        assert_eq!(inline.argument, None);
+        assert_eq!(position("list -oj").argument, None);
+        assert_eq!(position("list -o ").argument.as_deref(), Some("output"));
+
+        // Subcommands and option names are left to the generated scripts
+        assert_eq!(position("sw").argument, None);
+        assert_eq!(position("").argument, None);
+        assert_eq!(position("switch --fo").argument, None);
+        assert_eq!(position("switch dev ").argument, None);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let config = config();
+        assert_eq!(position("switch ").candidates(&config), ["dev", "stage"]);
+        assert_eq!(position("switch s").candidates(&config), ["stage"]);
+        assert_eq!(
+            position("remove-entry dev ").candidates(&config),
+            ["api.local", "web.local"]
+        );
+        assert!(
+            position("remove-entry missing ")
+                .candidates(&config)
+                .is_empty()
+        );
+        assert_eq!(
+            position("switch dev --target ").candidates(&config),
+            ["devbox"]
+        );
+        assert_eq!(position("list -o j").candidates(&config), ["json"]);
+        // New environments have no existing names to offer
+        assert!(position("add ").candidates(&config).is_empty());
+    }
+}
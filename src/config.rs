@@ -1,6 +1,130 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::net::IpAddr;
+use crate::bundle::TrustedKey;
+use crate::policy::PolicyRule;
+use crate::sources::RemoteSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where the IP address of a hosts entry comes from
+///
+/// Dynamic sources are evaluated when the environment is switched to, see
+/// [`crate::resolve::resolve_environment`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpSource {
+    /// A fixed IP address
+    Literal {
+        /// The IP address
+        ip: IpAddr,
+    },
+    /// The value of an environment variable
+    Env {
+        /// Name of the environment variable
+        name: String,
+    },
+    /// The first line printed by a shell command
+    Command {
+        /// Command line, run through the platform shell
+        command: String,
+    },
+    /// A line in a (generated) file
+    File {
+        /// Path of the file
+        path: PathBuf,
+        /// 1-based line number; defaults to the first non-empty, non-comment line
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+    },
+    /// The `IPv4` address of a local network interface, e.g. `docker0`
+    Interface {
+        /// Interface name
+        name: String,
+    },
+}
+
+impl fmt::Display for IpSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal { ip } => write!(f, "{ip}"),
+            Self::Env { name } => write!(f, "env:{name}"),
+            Self::Command { command } => write!(f, "cmd:{command}"),
+            Self::File { path, line: None } => write!(f, "file:{}", path.display()),
+            Self::File {
+                path,
+                line: Some(line),
+            } => write!(f, "file:{}#{line}", path.display()),
+            Self::Interface { name } => write!(f, "iface:{name}"),
+        }
+    }
+}
+
+impl FromStr for IpSource {
+    type Err = anyhow::Error;
+
+    /// Parse a source specification as accepted on the command line
+    ///
+    /// # Format
+    /// - `10.0.0.1` - a literal IP address
+    /// - `env:VAR` - the value of environment variable `VAR`
+    /// - `cmd:COMMAND` - the first line printed by `COMMAND`
+    /// - `file:PATH` or `file:PATH#LINE` - a line in a file
+    /// - `iface:NAME` - the `IPv4` address of network interface `NAME`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let non_empty = |value: &str, what: &str| {
+            if value.trim().is_empty() {
+                anyhow::bail!("Missing {what} in IP source '{s}'");
+            }
+            Ok(value.trim().to_string())
+        };
+
+        if let Some(name) = s.strip_prefix("env:") {
+            Ok(Self::Env {
+                name: non_empty(name, "variable name")?,
+            })
+        } else if let Some(command) = s.strip_prefix("cmd:") {
+            Ok(Self::Command {
+                command: non_empty(command, "command")?,
+            })
+        } else if let Some(spec) = s.strip_prefix("file:") {
+            let (path, line) = match spec.rsplit_once('#') {
+                Some((path, line)) => {
+                    let line: usize = line
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid line number in IP source '{s}'"))?;
+                    if line == 0 {
+                        anyhow::bail!("Line numbers start at 1 in IP source '{s}'");
+                    }
+                    (path, Some(line))
+                }
+                None => (spec, None),
+            };
+            Ok(Self::File {
+                path: PathBuf::from(non_empty(path, "file path")?),
+                line,
+            })
+        } else if let Some(name) = s.strip_prefix("iface:") {
+            Ok(Self::Interface {
+                name: non_empty(name, "interface name")?,
+            })
+        } else {
+            let ip = s.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "Invalid IP address '{s}' (expected an IP or one of env:, cmd:, file:, iface:)"
+                )
+            })?;
+            Ok(Self::Literal { ip })
+        }
+    }
+}
+
+/// Placeholder address of dynamic entries that have not been resolved yet
+fn unresolved_ip() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+}
 
 /// Represents an entry in the hosts file
 ///
@@ -8,11 +132,20 @@ use std::net::IpAddr;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HostEntry {
     /// IP address (`IPv4` or `IPv6`)
+    ///
+    /// For entries with a dynamic `source` this holds the most recently resolved address.
+    #[serde(default = "unresolved_ip")]
     pub ip: IpAddr,
     /// Hostname
     pub hostname: String,
     /// Optional comment information
     pub comment: Option<String>,
+    /// Dynamic source of the IP address, evaluated at switch time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<IpSource>,
+    /// Hostname in the same environment whose IP this entry follows (CNAME-style alias)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
 }
 
 impl HostEntry {
@@ -38,6 +171,48 @@ impl HostEntry {
             ip,
             hostname,
             comment: None,
+            source: None,
+            target: None,
+        }
+    }
+
+    /// Create an alias entry that uses the IP of another hostname in the same environment
+    ///
+    /// # Arguments
+    /// * `hostname` - Hostname of the alias
+    /// * `target` - Hostname whose IP address the alias follows
+    ///
+    /// # Example
+    /// ```
+    /// use hostctl::config::HostEntry;
+    ///
+    /// let entry = HostEntry::alias("www.dev.local".to_string(), "api.dev.local".to_string());
+    /// assert!(entry.is_alias());
+    /// ```
+    #[must_use]
+    pub fn alias(hostname: String, target: String) -> Self {
+        Self {
+            target: Some(target),
+            ..Self::new(unresolved_ip(), hostname)
+        }
+    }
+
+    /// Create a hosts entry whose IP address is taken from a source
+    ///
+    /// Literal sources produce a plain entry; other sources are resolved when the
+    /// environment is switched to.
+    ///
+    /// # Arguments
+    /// * `source` - Where the IP address comes from
+    /// * `hostname` - Hostname
+    #[must_use]
+    pub fn from_source(source: IpSource, hostname: String) -> Self {
+        match source {
+            IpSource::Literal { ip } => Self::new(ip, hostname),
+            source => Self {
+                source: Some(source),
+                ..Self::new(unresolved_ip(), hostname)
+            },
         }
     }
 
@@ -65,6 +240,58 @@ impl HostEntry {
             None => format!("{} {}", self.ip, self.hostname),
         }
     }
+
+    /// Check whether the IP address is evaluated at switch time
+    #[must_use]
+    pub fn is_dynamic(&self) -> bool {
+        self.source.is_some() || self.target.is_some()
+    }
+
+    /// Check whether the entry follows another hostname's IP
+    #[must_use]
+    pub fn is_alias(&self) -> bool {
+        self.target.is_some()
+    }
+}
+
+/// Configuration layer an environment was loaded from
+///
+/// Layers are listed from lowest to highest precedence: a user environment hides a
+/// system environment of the same name. Changes to an environment are written back
+/// to the layer it came from.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigLayer {
+    /// The machine-wide, read-only configuration maintained by administrators
+    System,
+    /// Read-only environments fetched from remote sources
+    Remote,
+    /// The per-user configuration file
+    #[default]
+    User,
+    /// A project-local `.hostctl.yaml` discovered from the working directory
+    Project,
+}
+
+impl ConfigLayer {
+    /// Check whether hostctl may write changes to this layer
+    #[must_use]
+    pub fn is_writable(self) -> bool {
+        !matches!(self, Self::System | Self::Remote)
+    }
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::System => write!(f, "system"),
+            Self::Remote => write!(f, "remote"),
+            Self::User => write!(f, "user"),
+            Self::Project => write!(f, "project"),
+        }
+    }
 }
 
 /// Represents an environment configuration
@@ -73,11 +300,23 @@ impl HostEntry {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Environment {
     /// Environment name
+    ///
+    /// May be omitted in configuration files, in which case the map key is used.
+    #[serde(default)]
     pub name: String,
     /// Environment description
     pub description: Option<String>,
     /// List of hosts entries in this environment
     pub entries: Vec<HostEntry>,
+    /// Whether switching to or modifying the environment requires confirmation
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub protected: bool,
+    /// Whether the environment rejects all modifications
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// Layer this environment was loaded from (not stored in the file)
+    #[serde(skip)]
+    pub layer: ConfigLayer,
 }
 
 impl Environment {
@@ -98,6 +337,9 @@ impl Environment {
             name,
             description: None,
             entries: Vec::new(),
+            protected: false,
+            locked: false,
+            layer: ConfigLayer::User,
         }
     }
 
@@ -148,19 +390,135 @@ impl Environment {
     pub fn find_entry(&self, hostname: &str) -> Option<&HostEntry> {
         self.entries.iter().find(|e| e.hostname == hostname)
     }
+
+    /// Copy of the environment without the cached addresses of dynamic entries
+    ///
+    /// Switching to an environment caches the addresses its sources and aliases
+    /// resolved to; comparing definitions should not see those as changes.
+    #[must_use]
+    pub fn definition(&self) -> Self {
+        let mut env = self.clone();
+        for entry in env.entries.iter_mut().filter(|entry| entry.is_dynamic()) {
+            entry.ip = unresolved_ip();
+        }
+        env
+    }
+
+    /// Find the first entry whose IP address comes from a dynamic source
+    ///
+    /// Environments from files hostctl does not own (remote sources, project files,
+    /// bundles) may not use dynamic sources, since evaluating them runs commands or
+    /// reads files on this machine. Aliases are allowed.
+    ///
+    /// # Returns
+    /// Returns a reference to the entry if found; otherwise returns `None`
+    #[must_use]
+    pub fn find_dynamic_source(&self) -> Option<&HostEntry> {
+        self.entries.iter().find(|e| {
+            e.source
+                .as_ref()
+                .is_some_and(|source| !matches!(source, IpSource::Literal { .. }))
+        })
+    }
+}
+
+/// A pending automatic switch back to a previous environment
+///
+/// Recorded by a time-boxed switch (`hostctl switch <name> --for <duration>`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRevert {
+    /// Environment that was active before the time-boxed switch (`None` if none was active)
+    pub previous_environment: Option<String>,
+    /// Point in time after which the previous environment is restored
+    pub deadline: DateTime<Utc>,
+}
+
+impl PendingRevert {
+    /// Create a new pending revert
+    ///
+    /// # Arguments
+    /// * `previous_environment` - Environment to restore when the deadline passes
+    /// * `deadline` - Point in time after which the revert is due
+    #[must_use]
+    pub fn new(previous_environment: Option<String>, deadline: DateTime<Utc>) -> Self {
+        Self {
+            previous_environment,
+            deadline,
+        }
+    }
+
+    /// Check whether the deadline has passed
+    ///
+    /// # Arguments
+    /// * `now` - The current point in time
+    #[must_use]
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.deadline
+    }
+
+    /// Time left until the deadline, or zero if it has already passed
+    ///
+    /// # Arguments
+    /// * `now` - The current point in time
+    #[must_use]
+    pub fn remaining(&self, now: DateTime<Utc>) -> chrono::Duration {
+        (self.deadline - now).max(chrono::Duration::zero())
+    }
 }
 
+/// Format version of configuration files written by this build
+///
+/// Bump this together with a new migration in `storage.rs` whenever the file
+/// format changes incompatibly.
+pub const CONFIG_VERSION: u32 = 1;
+
 /// Main configuration structure
 ///
 /// Contains all environment configurations and the currently active environment.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Format version of the file this configuration was read from
+    #[serde(default)]
+    pub version: u32,
     /// Name of the currently active environment
     pub current_environment: Option<String>,
+    /// Pending revert of a time-boxed switch, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_revert: Option<PendingRevert>,
+    /// Policy rules that environments must follow
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<PolicyRule>,
+    /// Remote files providing read-only environments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<RemoteSource>,
+    /// Public keys whose signed bundles may be imported
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Additional hosts files managed next to the system one, by target name
+    ///
+    /// Relative paths are relative to the config directory.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, PathBuf>,
+    /// Name of the environment active in each target
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub target_environments: BTreeMap<String, String>,
     /// Map of all environments, with environment names as keys
+    #[serde(serialize_with = "serialize_sorted")]
     pub environments: HashMap<String, Environment>,
 }
 
+/// Serialize a map with its keys in sorted order, so files have a stable layout
+///
+/// # Errors
+/// Returns an error if the serializer fails.
+pub fn serialize_sorted<S, V>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    V: Serialize,
+{
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self::new()
@@ -180,7 +538,14 @@ impl Config {
     #[must_use]
     pub fn new() -> Self {
         Self {
+            version: CONFIG_VERSION,
             current_environment: None,
+            pending_revert: None,
+            policies: Vec::new(),
+            sources: Vec::new(),
+            trusted_keys: Vec::new(),
+            targets: BTreeMap::new(),
+            target_environments: BTreeMap::new(),
             environments: HashMap::new(),
         }
     }
@@ -247,11 +612,112 @@ impl Config {
     pub fn environment_names(&self) -> impl Iterator<Item = &String> {
         self.environments.keys()
     }
+
+    /// Resolve a name given on the command line to an environment name
+    ///
+    /// Exact names take precedence; otherwise an unqualified name such as `dev`
+    /// matches the project environment `<project>/dev`.
+    ///
+    /// # Arguments
+    /// * `name` - Environment name, qualified or not
+    ///
+    /// # Returns
+    /// Returns the full environment name if found; otherwise returns `None`
+    #[must_use]
+    pub fn resolve_environment_name(&self, name: &str) -> Option<&str> {
+        if let Some((key, _)) = self.environments.get_key_value(name) {
+            return Some(key);
+        }
+
+        self.environments
+            .iter()
+            .find(|(key, env)| {
+                env.layer == ConfigLayer::Project
+                    && key.rsplit_once('/').is_some_and(|(_, short)| short == name)
+            })
+            .map(|(key, _)| key.as_str())
+    }
+
+    /// Copy of the configuration containing only the environments and settings of one layer
+    ///
+    /// # Arguments
+    /// * `layer` - The layer to keep
+    #[must_use]
+    pub fn layer_view(&self, layer: ConfigLayer) -> Self {
+        Self {
+            policies: self
+                .policies
+                .iter()
+                .filter(|rule| rule.layer == layer)
+                .cloned()
+                .collect(),
+            sources: self
+                .sources
+                .iter()
+                .filter(|source| source.layer == layer)
+                .cloned()
+                .collect(),
+            trusted_keys: self
+                .trusted_keys
+                .iter()
+                .filter(|key| key.layer == layer)
+                .cloned()
+                .collect(),
+            environments: self
+                .environments
+                .iter()
+                .filter(|(_, env)| env.layer == layer)
+                .map(|(name, env)| (name.clone(), env.clone()))
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Error returned when something named on the command line does not exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotFoundError {
+    /// No environment has this name
+    Environment(String),
+    /// The environment has no entry for this hostname
+    Entry {
+        /// Full environment name
+        environment: String,
+        /// Hostname that was looked up
+        hostname: String,
+    },
+    /// No target has this name
+    Target(String),
+    /// No remote source has this name
+    Source(String),
 }
 
+impl fmt::Display for NotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Environment(name) => write!(f, "Environment '{name}' not found."),
+            Self::Entry {
+                environment,
+                hostname,
+            } => write!(
+                f,
+                "Entry '{hostname}' not found in environment '{environment}'."
+            ),
+            Self::Target(name) => write!(
+                f,
+                "Target '{name}' not found; add it under 'targets:' in the config file."
+            ),
+            Self::Source(name) => write!(f, "Source '{name}' not found."),
+        }
+    }
+}
+
+impl std::error::Error for NotFoundError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_yaml_ok as serde_yaml;
     use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
@@ -295,6 +761,87 @@ mod tests {
         assert_eq!(entry.to_line(), "::1 ipv6-localhost");
     }
 
+    #[test]
+    fn test_ip_source_parsing() {
+        assert_eq!(
+            "10.0.0.1".parse::<IpSource>().unwrap(),
+            IpSource::Literal {
+                ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+            }
+        );
+        assert_eq!(
+            "env:VM_IP".parse::<IpSource>().unwrap(),
+            IpSource::Env {
+                name: "VM_IP".to_string()
+            }
+        );
+        assert_eq!(
+            "cmd:vagrant ssh-config".parse::<IpSource>().unwrap(),
+            IpSource::Command {
+                command: "vagrant ssh-config".to_string()
+            }
+        );
+        assert_eq!(
+            "file:/run/vm.ip#2".parse::<IpSource>().unwrap(),
+            IpSource::File {
+                path: PathBuf::from("/run/vm.ip"),
+                line: Some(2)
+            }
+        );
+        assert_eq!(
+            "iface:docker0".parse::<IpSource>().unwrap(),
+            IpSource::Interface {
+                name: "docker0".to_string()
+            }
+        );
+
+        assert!("env:".parse::<IpSource>().is_err());
+        assert!("file:/run/vm.ip#0".parse::<IpSource>().is_err());
+        assert!("not-an-ip".parse::<IpSource>().is_err());
+    }
+
+    #[test]
+    fn test_ip_source_display_roundtrip() {
+        for spec in [
+            "10.0.0.1",
+            "env:VM_IP",
+            "cmd:echo hi",
+            "file:/tmp/ip#3",
+            "iface:lo",
+        ] {
+            let source: IpSource = spec.parse().unwrap();
+            assert_eq!(source.to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_host_entry_from_source() {
+        let literal = HostEntry::from_source("10.0.0.1".parse().unwrap(), "api".to_string());
+        assert!(!literal.is_dynamic());
+        assert_eq!(literal.ip, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+
+        let dynamic = HostEntry::from_source("env:VM_IP".parse().unwrap(), "vm".to_string());
+        assert!(dynamic.is_dynamic());
+        assert_eq!(dynamic.ip, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+
+    #[test]
+    fn test_find_dynamic_source() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::from_source(
+            "10.0.0.1".parse().unwrap(),
+            "api".to_string(),
+        ));
+        env.add_entry(HostEntry::alias("www".to_string(), "api".to_string()));
+        assert!(env.find_dynamic_source().is_none());
+
+        env.add_entry(HostEntry::from_source(
+            "cmd:vagrant ssh-config".parse().unwrap(),
+            "vm".to_string(),
+        ));
+        assert_eq!(env.find_dynamic_source().unwrap().hostname, "vm");
+    }
+
     #[test]
     fn test_environment_creation() {
         let env = Environment::new("dev".to_string());
@@ -390,4 +937,79 @@ mod tests {
         config.remove_environment("current");
         assert_eq!(config.current_environment, None);
     }
+
+    #[test]
+    fn test_resolve_environment_name() {
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+
+        let mut project_env = Environment::new("webapp/dev".to_string());
+        project_env.layer = ConfigLayer::Project;
+        config.add_environment(project_env);
+
+        let mut project_only = Environment::new("webapp/e2e".to_string());
+        project_only.layer = ConfigLayer::Project;
+        config.add_environment(project_only);
+
+        // Exact names win over project environments with the same short name
+        assert_eq!(config.resolve_environment_name("dev"), Some("dev"));
+        assert_eq!(
+            config.resolve_environment_name("webapp/dev"),
+            Some("webapp/dev")
+        );
+        assert_eq!(config.resolve_environment_name("e2e"), Some("webapp/e2e"));
+        assert_eq!(config.resolve_environment_name("missing"), None);
+    }
+
+    #[test]
+    fn test_layer_view() {
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        let mut project_env = Environment::new("webapp/dev".to_string());
+        project_env.layer = ConfigLayer::Project;
+        config.add_environment(project_env);
+        config.current_environment = Some("webapp/dev".to_string());
+
+        let user = config.layer_view(ConfigLayer::User);
+        assert_eq!(user.environments.len(), 1);
+        assert!(user.get_environment("dev").is_some());
+        // State is kept regardless of the layer
+        assert_eq!(user.current_environment, Some("webapp/dev".to_string()));
+
+        let project = config.layer_view(ConfigLayer::Project);
+        assert!(project.get_environment("webapp/dev").is_some());
+    }
+
+    #[test]
+    fn test_pending_revert_expiry() {
+        let deadline = DateTime::parse_from_rfc3339("2026-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let pending = PendingRevert::new(Some("dev".to_string()), deadline);
+
+        let before = deadline - chrono::Duration::minutes(5);
+        assert!(!pending.is_expired(before));
+        assert_eq!(pending.remaining(before), chrono::Duration::minutes(5));
+
+        let after = deadline + chrono::Duration::seconds(1);
+        assert!(pending.is_expired(after));
+        assert_eq!(pending.remaining(after), chrono::Duration::zero());
+    }
+
+    #[test]
+    fn test_protection_flags_serialization() {
+        let env = Environment::new("dev".to_string());
+        let yaml = serde_yaml::to_string(&env).unwrap();
+        assert!(!yaml.contains("protected"));
+        assert!(!yaml.contains("locked"));
+
+        let mut env = Environment::new("prod".to_string());
+        env.protected = true;
+        let yaml = serde_yaml::to_string(&env).unwrap();
+        assert!(yaml.contains("protected: true"));
+
+        let parsed: Environment = serde_yaml::from_str(&yaml).unwrap();
+        assert!(parsed.protected);
+        assert!(!parsed.locked);
+    }
 }
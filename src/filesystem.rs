@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Filesystem operations used to read and write hosts files
+///
+/// Allows the hosts file to live somewhere other than the local disk, e.g. in
+/// memory for tests.
+pub trait FileSystem {
+    /// Read a whole file as a string
+    ///
+    /// # Errors
+    /// Returns an error if the file does not exist or cannot be read.
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// Create or replace a file with the given contents
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()>;
+
+    /// Copy a file, replacing the destination if it exists
+    ///
+    /// # Errors
+    /// Returns an error if the source cannot be read or the destination cannot be written.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+/// The local filesystem
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Filesystem kept in memory, for tests and embedding applications
+///
+/// Clones share the same files, so a clone can be handed to a `HostsManager`
+/// and the original used to inspect what was written.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileSystem {
+    files: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl MemoryFileSystem {
+    /// Create an empty in-memory filesystem
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of all files, sorted
+    #[must_use]
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.files().keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    fn files(&self) -> MutexGuard<'_, HashMap<PathBuf, String>> {
+        // A panic while holding the lock cannot leave a map half-updated
+        self.files
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Error for a file that does not exist in memory
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.files()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.files()
+            .insert(path.to_path_buf(), contents.to_string());
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files();
+        let contents = files.get(from).cloned().ok_or_else(|| not_found(from))?;
+        files.insert(to.to_path_buf(), contents);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_file_system() {
+        let fs = MemoryFileSystem::new();
+        let hosts = Path::new("/etc/hosts");
+        let backup = Path::new("/etc/hosts.bak");
+
+        assert_eq!(
+            fs.read_to_string(hosts).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(fs.copy(hosts, backup).is_err());
+
+        fs.write(hosts, "127.0.0.1 localhost\n").unwrap();
+        fs.copy(hosts, backup).unwrap();
+        fs.write(hosts, "").unwrap();
+
+        // Clones share their files
+        let clone = fs.clone();
+        assert_eq!(clone.read_to_string(hosts).unwrap(), "");
+        assert_eq!(
+            clone.read_to_string(backup).unwrap(),
+            "127.0.0.1 localhost\n"
+        );
+        assert_eq!(fs.paths(), [hosts, backup]);
+    }
+}
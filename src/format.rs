@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_yaml_ok as serde_yaml;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Serialization format of a configuration file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    /// YAML, the default format
+    #[default]
+    Yaml,
+    /// TOML
+    Toml,
+    /// JSON
+    Json,
+}
+
+impl ConfigFormat {
+    /// Determine the format of a file from its extension
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Errors
+    /// Returns an error if the extension is missing or not supported.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .with_context(|| format!("Config file has no extension: {}", path.display()))?;
+        extension
+            .parse()
+            .with_context(|| format!("Unsupported config file: {}", path.display()))
+    }
+
+    /// Preferred file extension of the format
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Json => "json",
+        }
+    }
+
+    /// Parse file contents in this format
+    ///
+    /// # Arguments
+    /// * `content` - The file contents
+    ///
+    /// # Errors
+    /// Returns an error if the contents are not valid in this format.
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> Result<T> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_str(content)?,
+            Self::Toml => toml::from_str(content)?,
+            Self::Json => serde_json::from_str(content)?,
+        })
+    }
+
+    /// Serialize a value in this format
+    ///
+    /// # Arguments
+    /// * `value` - The value to serialize
+    ///
+    /// # Errors
+    /// Returns an error if the value cannot be represented in this format.
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::to_string(value)?,
+            Self::Toml => toml::to_string_pretty(value)?,
+            Self::Json => serde_json::to_string_pretty(value)? + "\n",
+        })
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("Unknown config format '{s}' (use yaml, toml or json)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.yaml")).unwrap(),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.YML")).unwrap(),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/etc/hostctl/config.toml")).unwrap(),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.json")).unwrap(),
+            ConfigFormat::Json
+        );
+        assert!(ConfigFormat::from_path(Path::new("config.ini")).is_err());
+        assert!(ConfigFormat::from_path(Path::new("config")).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Files in the config directory that are never committed
+const IGNORED_FILES: &str = "# Local state of hostctl that is not versioned\njournal.json\nstate.yaml\nlast-good.yaml\nhostctl.lock\ncache/\n*.bak\n";
+
+/// Config files merged with the hostctl merge driver
+const MERGE_ATTRIBUTES: &str = "config.yaml merge=hostctl\nconfig.yml merge=hostctl\nconfig.toml merge=hostctl\nconfig.json merge=hostctl\nenvironments/* merge=hostctl\n";
+
+/// Separator between the fields of a formatted log line
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A commit of the configuration repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// Abbreviated commit hash
+    pub hash: String,
+    /// Commit date
+    pub timestamp: DateTime<Utc>,
+    /// First line of the commit message
+    pub message: String,
+}
+
+/// Run git in a directory and return its output if it succeeded
+fn git(dir: &Path, args: &[&str]) -> Result<Output> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git; is it installed?")?;
+    if !output.status.success() {
+        let command = args.first().copied().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => anyhow::bail!("git {command} failed"),
+            message => anyhow::bail!("git {command} failed: {message}"),
+        }
+    }
+    Ok(output)
+}
+
+/// Check whether a directory is the root of a git repository
+///
+/// # Arguments
+/// * `dir` - The directory to check
+#[must_use]
+pub fn is_repository(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Turn a directory into a git repository and commit its current contents
+///
+/// A commit identity is configured for the repository if git has none, so commits
+/// also work on machines without a global git configuration. Config files are set
+/// up to be merged with `hostctl merge-driver`.
+///
+/// # Arguments
+/// * `dir` - The config directory
+///
+/// # Returns
+/// Returns `false` if the directory already was a repository
+///
+/// # Errors
+/// Returns an error if git is not available or fails.
+pub fn init(dir: &Path) -> Result<bool> {
+    if is_repository(dir) {
+        return Ok(false);
+    }
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    git(dir, &["init", "-q"])?;
+
+    for (key, value) in [
+        ("user.name", "hostctl"),
+        ("user.email", "hostctl@localhost"),
+    ] {
+        if git(dir, &["config", key]).is_err() {
+            git(dir, &["config", key, value])?;
+        }
+    }
+
+    register_merge_driver(dir)?;
+
+    let ignore = dir.join(".gitignore");
+    if !ignore.exists() {
+        fs::write(&ignore, IGNORED_FILES)
+            .with_context(|| format!("Failed to write {}", ignore.display()))?;
+    }
+
+    commit_all(dir, "Initialize hostctl configuration")?;
+    Ok(true)
+}
+
+/// Configure a repository to merge hostctl config files semantically
+///
+/// The driver is registered in the repository's local git config, so every clone of
+/// a shared config repository needs to run this once. Existing `.gitattributes`
+/// files are left alone.
+///
+/// # Arguments
+/// * `dir` - The repository directory
+///
+/// # Errors
+/// Returns an error if git fails or `.gitattributes` cannot be written.
+pub fn register_merge_driver(dir: &Path) -> Result<()> {
+    git(
+        dir,
+        &[
+            "config",
+            "merge.hostctl.name",
+            "hostctl semantic config merge",
+        ],
+    )?;
+    git(
+        dir,
+        &[
+            "config",
+            "merge.hostctl.driver",
+            "hostctl merge-driver %O %A %B %P",
+        ],
+    )?;
+
+    let attributes = dir.join(".gitattributes");
+    if !attributes.exists() {
+        fs::write(&attributes, MERGE_ATTRIBUTES)
+            .with_context(|| format!("Failed to write {}", attributes.display()))?;
+    }
+    Ok(())
+}
+
+/// Commit all changes in the repository
+///
+/// # Arguments
+/// * `dir` - The repository directory
+/// * `message` - The commit message
+///
+/// # Returns
+/// Returns `false` if there was nothing to commit
+///
+/// # Errors
+/// Returns an error if git fails.
+pub fn commit_all(dir: &Path, message: &str) -> Result<bool> {
+    git(dir, &["add", "-A"])?;
+    let status = git(dir, &["status", "--porcelain"])?;
+    if status.stdout.is_empty() {
+        return Ok(false);
+    }
+    git(dir, &["commit", "-q", "-m", message])?;
+    Ok(true)
+}
+
+/// List the most recent commits, newest first
+///
+/// # Arguments
+/// * `dir` - The repository directory
+/// * `limit` - Maximum number of commits
+///
+/// # Errors
+/// Returns an error if git fails.
+pub fn log(dir: &Path, limit: usize) -> Result<Vec<Commit>> {
+    let output = git(
+        dir,
+        &[
+            "log",
+            &format!("--max-count={limit}"),
+            &format!("--format=%h{FIELD_SEPARATOR}%at{FIELD_SEPARATOR}%s"),
+        ],
+    )?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, FIELD_SEPARATOR);
+            Some(Commit {
+                hash: fields.next()?.to_string(),
+                timestamp: DateTime::from_timestamp(fields.next()?.parse().ok()?, 0)?,
+                message: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Restore the files of the repository to their state at a revision
+///
+/// The restored state is not committed; files added after the revision are removed.
+///
+/// # Arguments
+/// * `dir` - The repository directory
+/// * `rev` - Any revision git understands, e.g. a hash or `HEAD~2`
+///
+/// # Returns
+/// Returns the abbreviated hash of the revision
+///
+/// # Errors
+/// Returns an error if the revision does not exist or git fails.
+pub fn restore(dir: &Path, rev: &str) -> Result<String> {
+    let commit = format!("{rev}^{{commit}}");
+    let hash = git(
+        dir,
+        &["rev-parse", "--short", "--verify", "--quiet", &commit],
+    )
+    .with_context(|| format!("Unknown revision '{rev}'"))?;
+    git(
+        dir,
+        &[
+            "restore",
+            "--source",
+            &commit,
+            "--staged",
+            "--worktree",
+            "--",
+            ".",
+        ],
+    )?;
+    Ok(String::from_utf8_lossy(&hash.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_log_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let config = dir.join("config.yaml");
+
+        fs::write(&config, "environments: {}\n").unwrap();
+        assert!(init(dir).unwrap());
+        assert!(!init(dir).unwrap());
+        assert!(is_repository(dir));
+
+        fs::write(&config, "environments:\n  dev: {}\n").unwrap();
+        fs::write(dir.join("journal.json"), "{}").unwrap();
+        assert!(commit_all(dir, "add dev").unwrap());
+        assert!(!commit_all(dir, "nothing").unwrap());
+
+        fs::write(dir.join("extra.yaml"), "x: 1\n").unwrap();
+        assert!(commit_all(dir, "add extra").unwrap());
+
+        let commits = log(dir, 10).unwrap();
+        let messages: Vec<&str> = commits.iter().map(|c| c.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["add extra", "add dev", "Initialize hostctl configuration"]
+        );
+        assert_eq!(log(dir, 1).unwrap().len(), 1);
+        assert!(Utc::now() - commits[0].timestamp < chrono::Duration::minutes(1));
+
+        let hash = restore(dir, "HEAD~2").unwrap();
+        assert_eq!(hash, commits[2].hash);
+        assert_eq!(fs::read_to_string(&config).unwrap(), "environments: {}\n");
+        assert!(!dir.join("extra.yaml").exists());
+        // Ignored files are left alone
+        assert!(dir.join("journal.json").exists());
+
+        assert!(restore(dir, "no-such-rev").is_err());
+    }
+}
@@ -1,34 +1,115 @@
 use crate::config::{Environment, HostEntry};
+use crate::filesystem::{FileSystem, StdFileSystem};
 use anyhow::{Context, Result};
-use std::fs;
-use std::io::{BufRead, BufReader};
+use std::fmt;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Get the path to the system hosts file
+///
+/// Returns different paths based on the operating system:
+/// - Windows: `C:\Windows\System32\drivers\etc\hosts`
+/// - Linux/macOS: `/etc/hosts`
+#[cfg(target_os = "windows")]
+#[must_use]
+pub fn system_hosts_path() -> &'static Path {
+    Path::new(r"C:\Windows\System32\drivers\etc\hosts")
+}
+
+/// Get the path to the system hosts file
+///
+/// Returns different paths based on the operating system:
+/// - Windows: `C:\Windows\System32\drivers\etc\hosts`
+/// - Linux/macOS: `/etc/hosts`
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+#[must_use]
+pub fn system_hosts_path() -> &'static Path {
+    Path::new("/etc/hosts")
+}
 
 /// Hosts file manager
 ///
-/// Responsible for reading, parsing, and writing the system hosts file.
-pub struct HostsManager;
+/// Responsible for reading, parsing, and writing a hosts file. Use
+/// `HostsManager::system()` for the system hosts file.
+pub struct HostsManager {
+    /// Path of the hosts file
+    path: PathBuf,
+    /// Filesystem the hosts file and its backups live on
+    fs: Box<dyn FileSystem>,
+}
+
+impl fmt::Debug for HostsManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostsManager")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
 
 impl HostsManager {
-    /// Get the path to the system hosts file
+    /// Create a manager for a hosts file on the local filesystem
     ///
-    /// Returns different paths based on the operating system:
-    /// - Windows: `C:\Windows\System32\drivers\etc\hosts`
-    /// - Linux/macOS: `/etc/hosts`
-    #[cfg(target_os = "windows")]
-    fn get_hosts_path() -> &'static str {
-        r"C:\Windows\System32\drivers\etc\hosts"
+    /// # Arguments
+    /// * `path` - Path of the hosts file
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self {
+        Self::with_file_system(path, StdFileSystem)
     }
 
-    /// Get the path to the system hosts file
+    /// Create a manager for the system hosts file
+    #[must_use]
+    pub fn system() -> Self {
+        Self::new(system_hosts_path().to_path_buf())
+    }
+
+    /// Create a manager for the hosts file seen by a running process
+    ///
+    /// The file is reached through `/proc/<pid>/root`, so the hosts file of a
+    /// process in another mount namespace, e.g. a container, is edited in place.
+    ///
+    /// # Arguments
+    /// * `pid` - ID of the process
     ///
-    /// Returns different paths based on the operating system:
-    /// - Windows: `C:\Windows\System32\drivers\etc\hosts`
-    /// - Linux/macOS: `/etc/hosts`
-    #[cfg(any(target_os = "linux", target_os = "macos"))]
-    fn get_hosts_path() -> &'static str {
-        "/etc/hosts"
+    /// # Errors
+    /// Returns an error if the process does not exist or its root directory cannot
+    /// be accessed.
+    #[cfg(target_os = "linux")]
+    pub fn for_process(pid: u32) -> Result<Self> {
+        let root = PathBuf::from(format!("/proc/{pid}/root"));
+        std::fs::read_link(&root)
+            .with_context(|| format!("Cannot access the root directory of process {pid}"))?;
+        let relative = system_hosts_path()
+            .strip_prefix("/")
+            .unwrap_or_else(|_| system_hosts_path());
+        Ok(Self::new(root.join(relative)))
+    }
+
+    /// Create a manager for the hosts file seen by a running process
+    ///
+    /// # Errors
+    /// Always fails; processes can only be targeted on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn for_process(pid: u32) -> Result<Self> {
+        anyhow::bail!("Cannot edit the hosts file of process {pid}: only supported on Linux")
+    }
+
+    /// Create a manager for a hosts file on another filesystem
+    ///
+    /// # Arguments
+    /// * `path` - Path of the hosts file
+    /// * `fs` - The filesystem to read and write the hosts file on
+    #[must_use]
+    pub fn with_file_system(path: PathBuf, fs: impl FileSystem + 'static) -> Self {
+        Self {
+            path,
+            fs: Box::new(fs),
+        }
+    }
+
+    /// Path of the hosts file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
     /// Read and parse the current hosts file
@@ -38,21 +119,16 @@ impl HostsManager {
     ///
     /// # Errors
     /// Returns an error if the hosts file cannot be read.
-    pub fn read_current_hosts() -> Result<Vec<HostEntry>> {
-        let path = Self::get_hosts_path();
-        let file =
-            fs::File::open(path).with_context(|| format!("Failed to open hosts file: {path}"))?;
-
-        let reader = BufReader::new(file);
-        let mut entries = Vec::new();
-
-        for line in reader.lines().map_while(Result::ok) {
-            if let Some(entry) = Self::parse_hosts_line(&line) {
-                entries.push(entry);
-            }
-        }
+    pub fn read_current_hosts(&self) -> Result<Vec<HostEntry>> {
+        let content = self.read()?;
+        Ok(content.lines().filter_map(Self::parse_hosts_line).collect())
+    }
 
-        Ok(entries)
+    /// Read the contents of the hosts file
+    fn read(&self) -> Result<String> {
+        self.fs
+            .read_to_string(&self.path)
+            .with_context(|| format!("Failed to read hosts file: {}", self.path.display()))
     }
 
     /// Parse a line from the hosts file
@@ -97,10 +173,12 @@ impl HostsManager {
             ip,
             hostname,
             comment,
+            source: None,
+            target: None,
         })
     }
 
-    /// Apply the specified environment configuration to the system hosts file
+    /// Apply the specified environment configuration to the hosts file
     ///
     /// This operation backs up the current hosts file, then writes the new configuration.
     ///
@@ -109,14 +187,33 @@ impl HostsManager {
     ///
     /// # Errors
     /// Returns an error if the hosts file cannot be read or written.
-    pub fn apply_environment(env: &Environment) -> Result<()> {
+    pub fn apply_environment(&self, env: &Environment) -> Result<()> {
+        self.write_managed_entries(Some(env))
+    }
+
+    /// Remove all hostctl managed entries from the hosts file
+    ///
+    /// This operation backs up the current hosts file, then writes only the system entries back.
+    ///
+    /// # Errors
+    /// Returns an error if the hosts file cannot be read or written.
+    pub fn clear_environment(&self) -> Result<()> {
+        self.write_managed_entries(None)
+    }
+
+    /// Rewrite the hosts file with the system entries and, optionally, an environment's entries
+    ///
+    /// # Arguments
+    /// * `env` - The environment whose entries make up the managed section, or `None` to drop it
+    ///
+    /// # Errors
+    /// Returns an error if the hosts file cannot be read or written.
+    fn write_managed_entries(&self, env: Option<&Environment>) -> Result<()> {
         // First backup the current hosts file
-        Self::backup_hosts_file()?;
+        self.backup_hosts_file()?;
 
         // Read current hosts file content
-        let path = Self::get_hosts_path();
-        let current_content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read hosts file: {path}"))?;
+        let current_content = self.read()?;
 
         // Separate hostctl managed entries and system entries
         let (system_entries, _managed_entries) = Self::separate_entries(&current_content);
@@ -130,37 +227,46 @@ impl HostsManager {
             new_content.push('\n');
         }
 
-        // Add separator
-        new_content.push_str("\n# ===== hostctl managed entries =====\n");
+        if let Some(env) = env {
+            // Add separator
+            new_content.push_str("\n# ===== hostctl managed entries =====\n");
 
-        // Add environment entries
-        for entry in &env.entries {
-            new_content.push_str(&entry.to_line());
-            new_content.push('\n');
+            // Add environment entries
+            for entry in &env.entries {
+                new_content.push_str(&entry.to_line());
+                new_content.push('\n');
+            }
         }
 
         // Write new hosts file
-        fs::write(path, new_content)
-            .with_context(|| format!("Failed to write hosts file: {path}"))?;
+        self.fs
+            .write(&self.path, &new_content)
+            .with_context(|| format!("Failed to write hosts file: {}", self.path.display()))?;
 
         Ok(())
     }
 
     /// Backup the current hosts file
     ///
-    /// Backup file name format is `hosts.backup.YYYYMMDD_HHMMSS`
+    /// Backup file name format is `<name>.backup.YYYYMMDD_HHMMSS`, next to the hosts file
     ///
     /// # Returns
     /// Returns the path to the backup file
     ///
     /// # Errors
     /// Returns an error if the backup file cannot be created.
-    fn backup_hosts_file() -> Result<std::path::PathBuf> {
-        let path = Path::new(Self::get_hosts_path());
+    fn backup_hosts_file(&self) -> Result<PathBuf> {
         let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-        let backup_path = path.with_file_name(format!("hosts.backup.{timestamp}"));
-
-        fs::copy(path, &backup_path)
+        let name = self
+            .path
+            .file_name()
+            .map_or_else(|| "hosts".into(), |name| name.to_string_lossy());
+        let backup_path = self
+            .path
+            .with_file_name(format!("{name}.backup.{timestamp}"));
+
+        self.fs
+            .copy(&self.path, &backup_path)
             .with_context(|| format!("Failed to create backup: {}", backup_path.display()))?;
 
         Ok(backup_path)
@@ -398,4 +504,63 @@ mod tests {
         assert_eq!(entry.hostname, "localhost");
         assert_eq!(entry.comment, Some("Local host".to_string()));
     }
+
+    #[test]
+    fn test_apply_and_clear_environment() {
+        let fs = crate::filesystem::MemoryFileSystem::new();
+        let path = PathBuf::from("/etc/hosts");
+        fs.write(&path, "127.0.0.1 localhost\n").unwrap();
+        let hosts = HostsManager::with_file_system(path.clone(), fs.clone());
+
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            "api.local".to_string(),
+        ));
+        hosts.apply_environment(&env).unwrap();
+        assert_eq!(
+            fs.read_to_string(&path).unwrap(),
+            "127.0.0.1 localhost\n\n# ===== hostctl managed entries =====\n10.0.0.1 api.local\n"
+        );
+        let hostnames: Vec<String> = hosts
+            .read_current_hosts()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.hostname)
+            .collect();
+        assert_eq!(hostnames, ["localhost", "api.local"]);
+
+        hosts.clear_environment().unwrap();
+        assert_eq!(fs.read_to_string(&path).unwrap(), "127.0.0.1 localhost\n");
+
+        // Each write backs up the previous contents next to the hosts file
+        let backups = fs.paths();
+        assert!(
+            backups
+                .iter()
+                .any(|p| p.starts_with("/etc") && p.to_string_lossy().contains("hosts.backup."))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_for_process() {
+        let pid = std::process::id();
+        let hosts = HostsManager::for_process(pid).unwrap();
+        assert_eq!(
+            hosts.path(),
+            Path::new(&format!("/proc/{pid}/root/etc/hosts"))
+        );
+
+        // PIDs are limited to 2^22 on Linux
+        assert!(HostsManager::for_process(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn test_missing_hosts_file() {
+        let fs = crate::filesystem::MemoryFileSystem::new();
+        let hosts = HostsManager::with_file_system(PathBuf::from("/etc/hosts"), fs);
+        assert!(hosts.read_current_hosts().is_err());
+        assert!(hosts.clear_environment().is_err());
+    }
 }
@@ -0,0 +1,334 @@
+use crate::config::{Config, ConfigLayer, Environment};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Name of the journal file in the config directory
+pub const JOURNAL_FILE: &str = "journal.json";
+
+/// Maximum number of records kept; older records are dropped first
+pub const MAX_RECORDS: usize = 100;
+
+/// A change of one environment made by a hostctl command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// Command line that made the change
+    pub command: String,
+    /// Full name of the changed environment
+    pub environment: String,
+    /// Layer the environment is stored in
+    #[serde(default)]
+    pub layer: ConfigLayer,
+    /// The environment before the change (`None` if it was created)
+    pub before: Option<Environment>,
+    /// The environment after the change (`None` if it was removed)
+    pub after: Option<Environment>,
+}
+
+impl Record {
+    /// Create a record of a change
+    ///
+    /// # Arguments
+    /// * `command` - Command line that made the change
+    /// * `environment` - Full name of the changed environment
+    /// * `before` - The environment before the change
+    /// * `after` - The environment after the change
+    #[must_use]
+    pub fn new(
+        command: String,
+        environment: &str,
+        before: Option<&Environment>,
+        after: Option<&Environment>,
+    ) -> Self {
+        let layer = before.or(after).map(|env| env.layer).unwrap_or_default();
+        Self {
+            timestamp: Utc::now(),
+            command,
+            environment: environment.to_string(),
+            layer,
+            before: before.cloned(),
+            after: after.cloned(),
+        }
+    }
+
+    /// Check whether the record changes nothing
+    #[must_use]
+    pub fn is_noop(&self) -> bool {
+        same_environment(self.before.as_ref(), self.after.as_ref())
+    }
+}
+
+/// History of configuration changes with a cursor for undo and redo
+///
+/// Records before the cursor are applied; records from the cursor onwards have been
+/// undone and can be redone. Recording a new change discards the undone records.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Journal {
+    /// Number of applied records
+    pub cursor: usize,
+    /// Recorded changes, oldest first
+    pub records: Vec<Record>,
+}
+
+impl Journal {
+    /// Load the journal from a file
+    ///
+    /// A missing file yields an empty journal.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the journal file
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read journal: {}", path.display()))?;
+        let mut journal: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse journal: {}", path.display()))?;
+        journal.cursor = journal.cursor.min(journal.records.len());
+        Ok(journal)
+    }
+
+    /// Save the journal to a file
+    ///
+    /// # Arguments
+    /// * `path` - Path of the journal file
+    ///
+    /// # Errors
+    /// Returns an error if the journal cannot be written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let content = serde_json::to_string_pretty(self)? + "\n";
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write journal: {}", path.display()))
+    }
+
+    /// Append a change, discarding all undone records
+    ///
+    /// Records that change nothing are ignored.
+    ///
+    /// # Arguments
+    /// * `record` - The change to append
+    pub fn record(&mut self, record: Record) {
+        if record.is_noop() {
+            return;
+        }
+        self.records.truncate(self.cursor);
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            self.records.drain(..self.records.len() - MAX_RECORDS);
+        }
+        self.cursor = self.records.len();
+    }
+
+    /// Step back over the most recent applied change
+    ///
+    /// # Returns
+    /// Returns the record to revert, or `None` if there is nothing to undo
+    pub fn undo(&mut self) -> Option<&Record> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        self.records.get(self.cursor)
+    }
+
+    /// Step forward over the most recent undone change
+    ///
+    /// # Returns
+    /// Returns the record to apply again, or `None` if there is nothing to redo
+    pub fn redo(&mut self) -> Option<&Record> {
+        let record = self.records.get(self.cursor)?;
+        self.cursor += 1;
+        Some(record)
+    }
+}
+
+/// Compare two environment states, ignoring the layer they were loaded from
+///
+/// Addresses cached from dynamic sources are ignored too, since switching to an
+/// environment updates them without recording a change.
+#[must_use]
+pub fn same_environment(a: Option<&Environment>, b: Option<&Environment>) -> bool {
+    let definition =
+        |env: Option<&Environment>| serde_json::to_value(env.map(Environment::definition)).ok();
+    definition(a) == definition(b)
+}
+
+/// Set an environment of a configuration to a recorded state
+///
+/// # Arguments
+/// * `config` - The configuration to change
+/// * `record` - The record the state belongs to
+/// * `state` - The state to restore (`None` removes the environment)
+///
+/// # Returns
+/// Returns `false` if the environment no longer matched `expected`, i.e. it was
+/// changed since the record was made
+pub fn restore_environment(
+    config: &mut Config,
+    record: &Record,
+    state: Option<&Environment>,
+    expected: Option<&Environment>,
+) -> bool {
+    let unchanged = same_environment(config.get_environment(&record.environment), expected);
+    match state {
+        Some(env) => {
+            let mut env = env.clone();
+            env.name.clone_from(&record.environment);
+            env.layer = record.layer;
+            config.add_environment(env);
+        }
+        None => {
+            config.remove_environment(&record.environment);
+        }
+    }
+    unchanged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostEntry;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn env_with_entry(name: &str, hostname: &str) -> Environment {
+        let mut env = Environment::new(name.to_string());
+        env.add_entry(HostEntry::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            hostname.to_string(),
+        ));
+        env
+    }
+
+    fn record(before: Option<&Environment>, after: Option<&Environment>) -> Record {
+        Record::new("hostctl test".to_string(), "dev", before, after)
+    }
+
+    #[test]
+    fn test_undo_redo_cursor() {
+        let created = Environment::new("dev".to_string());
+        let extended = env_with_entry("dev", "app.local");
+
+        let mut journal = Journal::default();
+        journal.record(record(None, Some(&created)));
+        journal.record(record(Some(&created), Some(&extended)));
+        assert_eq!(journal.cursor, 2);
+
+        let undone = journal.undo().unwrap();
+        assert!(same_environment(undone.before.as_ref(), Some(&created)));
+        assert!(journal.undo().unwrap().before.is_none());
+        assert!(journal.undo().is_none());
+        assert_eq!(journal.cursor, 0);
+
+        assert!(journal.redo().unwrap().before.is_none());
+        assert_eq!(journal.cursor, 1);
+
+        // A new change discards the undone record
+        journal.record(record(Some(&created), None));
+        assert_eq!(journal.records.len(), 2);
+        assert!(journal.records[1].after.is_none());
+        assert!(journal.redo().is_none());
+    }
+
+    #[test]
+    fn test_noop_records_are_ignored() {
+        let env = env_with_entry("dev", "app.local");
+        let mut journal = Journal::default();
+        journal.record(record(Some(&env), Some(&env)));
+        assert!(journal.records.is_empty());
+    }
+
+    #[test]
+    fn test_journal_is_bounded() {
+        let mut journal = Journal::default();
+        let env = Environment::new("dev".to_string());
+        for i in 0..MAX_RECORDS + 5 {
+            let before = (i % 2 == 1).then_some(&env);
+            let after = (i % 2 == 0).then_some(&env);
+            journal.record(record(before, after));
+        }
+        assert_eq!(journal.records.len(), MAX_RECORDS);
+        assert_eq!(journal.cursor, MAX_RECORDS);
+    }
+
+    #[test]
+    fn test_restore_environment() {
+        let mut config = Config::new();
+        let before = env_with_entry("dev", "app.local");
+        let after = env_with_entry("dev", "api.local");
+        config.add_environment(after.clone());
+        let change = record(Some(&before), Some(&after));
+
+        assert!(restore_environment(
+            &mut config,
+            &change,
+            change.before.as_ref(),
+            change.after.as_ref()
+        ));
+        let restored = config.get_environment("dev").unwrap();
+        assert!(restored.find_entry("app.local").is_some());
+
+        // The environment no longer matches the record's after state
+        assert!(!restore_environment(
+            &mut config,
+            &change,
+            None,
+            change.after.as_ref()
+        ));
+        assert!(config.get_environment("dev").is_none());
+    }
+
+    #[test]
+    fn test_cached_addresses_are_not_changes() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::from_source(
+            "cmd:echo 10.0.0.7".parse().unwrap(),
+            "api.local".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "www.local".to_string(),
+            "api.local".to_string(),
+        ));
+        let mut resolved = env.clone();
+        for entry in &mut resolved.entries {
+            entry.ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7));
+        }
+        assert!(same_environment(Some(&env), Some(&resolved)));
+
+        // Static addresses are part of the definition
+        let before = env_with_entry("dev", "app.local");
+        let mut after = before.clone();
+        after.entries[0].ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        assert!(!same_environment(Some(&before), Some(&after)));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILE);
+        assert!(Journal::load(&path).unwrap().records.is_empty());
+
+        let mut journal = Journal::default();
+        let env = env_with_entry("dev", "app.local");
+        journal.record(record(None, Some(&env)));
+        journal.undo();
+        journal.save(&path).unwrap();
+
+        let loaded = Journal::load(&path).unwrap();
+        assert_eq!(loaded.cursor, 0);
+        assert_eq!(loaded.records.len(), 1);
+        assert_eq!(loaded.records[0].command, "hostctl test");
+        assert!(same_environment(
+            loaded.records[0].after.as_ref(),
+            Some(&env)
+        ));
+    }
+}
@@ -1,3 +1,19 @@
+pub mod bundle;
+pub mod complete;
 pub mod config;
+pub mod filesystem;
+pub mod format;
+pub mod git;
 pub mod hosts;
+pub mod journal;
+pub mod merge;
+pub mod output;
+pub mod policy;
+pub mod project;
+pub mod protect;
+pub mod resolve;
+pub mod sources;
 pub mod storage;
+pub mod sudo;
+pub mod timer;
+pub mod yaml_patch;
@@ -1,9 +1,39 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use hostctl::config::{Config, Environment, HostEntry};
+use chrono::{Local, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use hostctl::bundle::{self, Bundle, Verification};
+use hostctl::complete::{self, Position};
+use hostctl::config::{
+    Config, ConfigLayer, Environment, HostEntry, IpSource, NotFoundError, PendingRevert,
+};
+use hostctl::format::ConfigFormat;
+use hostctl::git;
 use hostctl::hosts::HostsManager;
-use hostctl::storage::ConfigStorage;
-use std::net::IpAddr;
+use hostctl::journal::{self, Journal, Record};
+use hostctl::output::{
+    self, CommitView, ConfigFileView, EnvironmentSummary, EnvironmentView, HistoryView,
+    OutputFormat, SourceView, StatusView,
+};
+use hostctl::policy::{self, Severity, Violation};
+use hostctl::project::Project;
+use hostctl::protect::{self, Action, ProtectionError};
+use hostctl::resolve;
+use hostctl::sources::{FetchOutcome, RemoteSource};
+use hostctl::storage::{
+    ConfigLayout, ConfigStorage, ConfigStore, FileConfigStore, Locations, ParseError,
+};
+use hostctl::sudo;
+use hostctl::timer;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode, Stdio};
+
+/// Exit code used when an environment's protection prevented a command
+const EXIT_PROTECTED: u8 = 3;
+
+/// Exit code used when a named environment, entry, target or source does not exist
+const EXIT_NOT_FOUND: u8 = 4;
 
 /// hostctl - A command-line tool for managing hosts files
 ///
@@ -12,6 +42,15 @@ use std::net::IpAddr;
 #[command(name = "hostctl")]
 #[command(about = "Manage hosts file with different environments", long_about = None)]
 struct Cli {
+    /// Use this user config file instead of the default one
+    #[arg(long, global = true, env = "HOSTCTL_CONFIG", value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Manage this hosts file instead of the system hosts file
+    #[arg(long, global = true, env = "HOSTCTL_HOSTS_FILE", value_name = "FILE")]
+    hosts_file: Option<PathBuf>,
+    /// Keep the hosts file and the default config files below this directory
+    #[arg(long, global = true, value_name = "DIR")]
+    root: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -20,18 +59,57 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// List all environments
-    List,
+    List {
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
     /// Show current environment
-    Current,
+    Current {
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
+    /// Show active environment and pending timed reverts
+    Status {
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
     /// Switch to specified environment
     Switch {
         /// Environment name
-        name: String,
+        #[arg(required_unless_present_any = ["cancel_timer", "refresh"])]
+        name: Option<String>,
+        /// Revert to the previous environment after this duration (e.g. 30m, 1h30m)
+        #[arg(long = "for", value_name = "DURATION")]
+        duration: Option<String>,
+        /// Cancel the pending revert and keep the current environment
+        #[arg(long, conflicts_with_all = ["name", "duration"])]
+        cancel_timer: bool,
+        /// Re-resolve dynamic IP sources of the active environment and reapply it
+        #[arg(long, conflicts_with_all = ["name", "duration", "cancel_timer"])]
+        refresh: bool,
+        /// Switch the hosts file of a configured target instead of the system one
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["duration", "cancel_timer"])]
+        target: Option<String>,
+        /// Switch the hosts file seen by a running process, e.g. a container (Linux only)
+        #[arg(
+            long,
+            conflicts_with_all = ["duration", "cancel_timer", "refresh", "target"]
+        )]
+        pid: Option<u32>,
+        /// Switch to a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
     },
     /// Show details of specified environment
     Show {
         /// Environment name
         name: String,
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
     },
     /// Create new environment
     Add {
@@ -40,23 +118,36 @@ enum Commands {
         /// Environment description
         #[arg(short, long)]
         description: Option<String>,
+        /// Require confirmation to switch to or modify the environment
+        #[arg(long)]
+        protected: bool,
+        /// Reject all modifications of the environment
+        #[arg(long)]
+        locked: bool,
     },
     /// Remove environment
     Remove {
         /// Environment name
         name: String,
+        /// Remove a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
     },
     /// Add hosts entry to environment
     AddEntry {
         /// Environment name
         environment: String,
-        /// IP address
+        /// IP address, dynamic source (env:VAR, cmd:COMMAND, file:PATH[#LINE], iface:NAME)
+        /// or alias of another hostname in the environment (target:HOSTNAME)
         ip: String,
         /// Hostname
         hostname: String,
         /// Comment
         #[arg(short, long)]
         comment: Option<String>,
+        /// Modify a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
     },
     /// Remove hosts entry from environment
     RemoveEntry {
@@ -64,35 +155,517 @@ enum Commands {
         environment: String,
         /// Hostname
         hostname: String,
+        /// Modify a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Require confirmation before switching to or modifying an environment
+    Protect {
+        /// Environment name
+        name: String,
+        /// Also reject all modifications until the environment is unprotected
+        #[arg(long)]
+        locked: bool,
+    },
+    /// Remove protection and locks from an environment
+    Unprotect {
+        /// Environment name
+        name: String,
+        /// Unprotect without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Revert the most recent configuration change
+    Undo {
+        /// Change a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Apply the most recently undone configuration change again
+    Redo {
+        /// Change a protected environment without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Show the recorded configuration changes
+    History {
+        /// Number of most recent changes to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
+    /// Show the commits of a git-backed configuration
+    Log {
+        /// Number of most recent commits to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
+    /// Restore the configuration of a git revision and commit it
+    Checkout {
+        /// Commit hash or other git revision (e.g. HEAD~2)
+        rev: String,
+        /// Change protected environments without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Three-way merge of config files, for use as a git merge driver
+    ///
+    /// Register it with `git config merge.hostctl.driver "hostctl merge-driver %O %A %B %P"`
+    /// and mark config files with `merge=hostctl` in `.gitattributes`.
+    MergeDriver {
+        /// Common ancestor version (%O)
+        base: PathBuf,
+        /// Our version, replaced by the merge result (%A)
+        ours: PathBuf,
+        /// Their version (%B)
+        theirs: PathBuf,
+        /// Path of the file in the repository, used to detect its format (%P)
+        path: Option<PathBuf>,
+    },
+    /// Export and import signed environment bundles
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommands,
+    },
+    /// Manage remote environment sources
+    Sources {
+        #[command(subcommand)]
+        command: SourcesCommands,
+    },
+    /// Evaluate policy rules
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommands,
+    },
+    /// Inspect the configuration files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Print a shell completion script
+    ///
+    /// Load it with e.g. `source <(hostctl completions bash)` in ~/.bashrc.
+    Completions {
+        /// Shell to generate the script for
+        shell: clap_complete::Shell,
+    },
+    /// Print values from the configuration for the word being completed
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// Command line words after `hostctl`, the last one being completed
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
     },
+    /// Wait for a pending timed switch to expire and revert it
+    #[command(hide = true)]
+    WatchTimer,
+}
+
+/// Policy subcommands
+#[derive(Subcommand)]
+enum PolicyCommands {
+    /// Check environments against the configured policy rules
+    Check {
+        /// Environment to check (all environments if omitted)
+        environment: Option<String>,
+        /// Resolve dynamic IP sources so their addresses are checked too
+        #[arg(long)]
+        resolve: bool,
+    },
+}
+
+/// Bundle subcommands
+#[derive(Subcommand)]
+enum BundleCommands {
+    /// Write an environment to a self-contained bundle file
+    Export {
+        /// Environment name
+        environment: String,
+        /// Secret key file to sign the bundle with (see `bundle keygen`)
+        #[arg(long, value_name = "KEY_FILE")]
+        sign: Option<PathBuf>,
+        /// Output file (stdout if omitted)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Create an environment from a bundle after verifying its signature
+    Import {
+        /// Bundle file
+        file: PathBuf,
+        /// Name of the new environment (defaults to the name in the bundle)
+        #[arg(long)]
+        name: Option<String>,
+        /// DANGEROUS: import unsigned, untrusted or tampered bundles
+        #[arg(long)]
+        allow_untrusted: bool,
+    },
+    /// Generate a signing key pair (KEY_FILE and KEY_FILE.pub)
+    Keygen {
+        /// Path of the secret key file
+        path: PathBuf,
+    },
+}
+
+/// Remote source subcommands
+#[derive(Subcommand)]
+enum SourcesCommands {
+    /// Show the configured sources and when they were last fetched
+    List {
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
+    /// Fetch sources, keeping the last good copy if a fetch fails
+    Update {
+        /// Source to update (all sources if omitted)
+        name: Option<String>,
+    },
+}
+
+/// Configuration subcommands
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Show which file every environment was loaded from
+    Sources {
+        /// Output format: plain, table, json or yaml
+        #[arg(short, long, value_name = "FORMAT", default_value_t)]
+        output: OutputFormat,
+    },
+    /// Convert the user config file to another format
+    Convert {
+        /// Target format: yaml, toml or json
+        #[arg(long)]
+        to: ConfigFormat,
+    },
+    /// Show or change how the user configuration is laid out on disk
+    Layout {
+        /// New layout: file (single config file) or directory (one file per environment)
+        layout: Option<ConfigLayout>,
+    },
+    /// Keep the config directory in a git repository that commits every change
+    Git,
+    /// Salvage the parseable environments of a damaged user config file
+    Repair {
+        /// Restore the last good copy saved by hostctl instead
+        #[arg(long)]
+        snapshot: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    let result = run();
+
+    // Files written through sudo belong to the user whose configuration it is
+    if let Some(user) = sudo::invoking_user()
+        && let Err(e) = user.restore_ownership(&ConfigStorage::get_config_dir_path())
+    {
+        eprintln!(
+            "Warning: failed to give the configuration back to {}: {e:#}",
+            user.name
+        );
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            if e.downcast_ref::<ProtectionError>().is_some() {
+                ExitCode::from(EXIT_PROTECTED)
+            } else if e.downcast_ref::<NotFoundError>().is_some() {
+                ExitCode::from(EXIT_NOT_FOUND)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
 }
 
-fn main() -> Result<()> {
+/// Parse the command line and run the selected command
+fn run() -> Result<()> {
     let cli = Cli::parse();
 
+    // Completion runs on every <TAB> and must neither wait for locks nor change anything
     match cli.command {
-        Commands::List => list_environments(),
-        Commands::Current => show_current_environment(),
-        Commands::Switch { name } => switch_environment(&name),
-        Commands::Show { name } => show_environment(&name),
-        Commands::Add { name, description } => add_environment(&name, description),
-        Commands::Remove { name } => remove_environment(&name),
+        Commands::Completions { shell } => {
+            print_completions(shell);
+            return Ok(());
+        }
+        Commands::Complete { words } => {
+            let cli_locations = Locations {
+                config_file: cli.config,
+                hosts_file: cli.hosts_file,
+                root: cli.root,
+            };
+            // Completion runs on every TAB without the lock, so it must not rewrite old files
+            let store = completion_locations(cli_locations, &words)
+                .install()
+                .and_then(|()| FileConfigStore::discover());
+            if let Ok(store) = store {
+                complete_words(&store.read_only(), &words);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    Locations {
+        config_file: cli.config,
+        hosts_file: cli.hosts_file,
+        root: cli.root,
+    }
+    .install()?;
+
+    // Serialize commands so concurrent invocations don't overwrite each other's changes
+    let files = FileConfigStore::discover()?;
+    let store: &dyn ConfigStore = &files;
+    let lock = if matches!(
+        cli.command,
+        Commands::WatchTimer | Commands::MergeDriver { .. }
+    ) {
+        None
+    } else {
+        Some(store.lock()?)
+    };
+
+    let repairing = matches!(
+        cli.command,
+        Commands::Config {
+            command: ConfigCommands::Repair { .. }
+        }
+    );
+    if lock.is_some()
+        && let (Some(user), Some(shadowed)) = (
+            sudo::invoking_user(),
+            ConfigStorage::get_shadowed_config_path(),
+        )
+    {
+        eprintln!(
+            "Warning: using the configuration of {} ({}); root's own {} is ignored",
+            user.name,
+            files.user_path().display(),
+            shadowed.display()
+        );
+    }
+    if lock.is_some() && !repairing {
+        check_config(&files)?;
+
+        // Any invocation enforces a timed switch whose deadline has passed
+        if let Err(e) = revert_expired_timer(store) {
+            eprintln!("Warning: failed to revert expired timed switch: {e:#}");
+        }
+    }
+
+    let result = match cli.command {
+        Commands::List { output } => list_environments(store, output),
+        Commands::Current { output } => show_current_environment(store, output),
+        Commands::Status { output } => show_status(store, output),
+        Commands::Switch {
+            cancel_timer: true, ..
+        } => cancel_timer(store),
+        Commands::Switch {
+            refresh: true,
+            target,
+            ..
+        } => refresh_environment(store, target.as_deref()),
+        Commands::Switch {
+            name,
+            duration,
+            target,
+            pid,
+            force,
+            ..
+        } => {
+            let name = name.context("Environment name is required")?;
+            match (target, pid, duration) {
+                (Some(target), _, _) => switch_target(store, &target, &name, force),
+                (None, Some(pid), _) => switch_process(store, pid, &name, force),
+                (None, None, Some(duration)) => {
+                    switch_environment_for(store, &name, &duration, force)
+                }
+                (None, None, None) => switch_environment(store, &name, force),
+            }
+        }
+        Commands::Show { name, output } => show_environment(store, &name, output),
+        Commands::Add {
+            name,
+            description,
+            protected,
+            locked,
+        } => add_environment(store, &name, description, protected, locked),
+        Commands::Remove { name, force } => remove_environment(store, &name, force),
         Commands::AddEntry {
             environment,
             ip,
             hostname,
             comment,
-        } => add_entry(&environment, &ip, &hostname, comment),
+            force,
+        } => add_entry(store, &environment, &ip, &hostname, comment, force),
         Commands::RemoveEntry {
             environment,
             hostname,
-        } => remove_entry(&environment, &hostname),
+            force,
+        } => remove_entry(store, &environment, &hostname, force),
+        Commands::Protect { name, locked } => protect_environment(store, &name, locked),
+        Commands::Unprotect { name, force } => unprotect_environment(store, &name, force),
+        Commands::Undo { force } => step_journal(store, true, force),
+        Commands::Redo { force } => step_journal(store, false, force),
+        Commands::History { limit, output } => show_history(limit, output),
+        Commands::Log { limit, output } => show_log(limit, output),
+        Commands::Checkout { rev, force } => checkout_config(store, &rev, force),
+        Commands::MergeDriver {
+            base,
+            ours,
+            theirs,
+            path,
+        } => {
+            // Runs inside a git merge, which must not be committed to
+            let path = path.unwrap_or_else(|| ours.clone());
+            return merge_driver(&base, &ours, &theirs, &path);
+        }
+        Commands::Bundle {
+            command:
+                BundleCommands::Export {
+                    environment,
+                    sign,
+                    output,
+                },
+        } => export_bundle(store, &environment, sign.as_deref(), output.as_deref()),
+        Commands::Bundle {
+            command:
+                BundleCommands::Import {
+                    file,
+                    name,
+                    allow_untrusted,
+                },
+        } => import_bundle(store, &file, name.as_deref(), allow_untrusted),
+        Commands::Bundle {
+            command: BundleCommands::Keygen { path },
+        } => generate_key(&path),
+        Commands::Sources {
+            command: SourcesCommands::List { output },
+        } => list_sources(store, output),
+        Commands::Sources {
+            command: SourcesCommands::Update { name },
+        } => update_sources(store, name.as_deref()),
+        Commands::Policy {
+            command:
+                PolicyCommands::Check {
+                    environment,
+                    resolve,
+                },
+        } => check_policies(store, environment.as_deref(), resolve),
+        Commands::Config {
+            command: ConfigCommands::Sources { output },
+        } => show_config_sources(output),
+        Commands::Config {
+            command: ConfigCommands::Convert { to },
+        } => convert_config(to),
+        Commands::Config {
+            command: ConfigCommands::Layout { layout },
+        } => config_layout(layout),
+        Commands::Config {
+            command: ConfigCommands::Git,
+        } => enable_git(),
+        Commands::Config {
+            command: ConfigCommands::Repair { snapshot },
+        } => repair_config(snapshot),
+        Commands::WatchTimer => watch_timer(store),
+        Commands::Completions { .. } | Commands::Complete { .. } => unreachable!(),
+    };
+
+    result?;
+    commit_changes();
+    Ok(())
+}
+
+/// Print the completion script of a shell
+fn print_completions(shell: clap_complete::Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), "hostctl", &mut io::stdout());
+    if let Some(script) = complete::dynamic_script(shell) {
+        print!("{script}");
+    }
+}
+
+/// Get the locations of the command line being completed
+///
+/// Locations given on the command line being completed take precedence over those
+/// of this invocation.
+fn completion_locations(locations: Locations, words: &[String]) -> Locations {
+    let position = completion_position(words);
+    let given =
+        |id: &str, fallback: Option<PathBuf>| position.option(id).map(PathBuf::from).or(fallback);
+    Locations {
+        config_file: given("config", locations.config_file),
+        hosts_file: given("hosts_file", locations.hosts_file),
+        root: given("root", locations.root),
+    }
+}
+
+/// Parse the command line being completed
+fn completion_position(words: &[String]) -> Position {
+    let mut command = Cli::command();
+    command.build();
+    Position::parse(&command, words)
+}
+
+/// Print the configuration values that complete the last of `words`, one per line
+///
+/// Nothing is printed if the configuration cannot be loaded.
+fn complete_words(store: &dyn ConfigStore, words: &[String]) {
+    let position = completion_position(words);
+    if let Ok(config) = store.load() {
+        for candidate in position.candidates(&config) {
+            println!("{candidate}");
+        }
     }
 }
 
 /// List all environments
-fn list_environments() -> Result<()> {
-    let config: Config = ConfigStorage::load_config()?;
+fn list_environments(store: &dyn ConfigStore, output: OutputFormat) -> Result<()> {
+    let config: Config = store.load()?;
+
+    match output {
+        OutputFormat::Plain => {}
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = EnvironmentSummary::all(&config)
+                .into_iter()
+                .map(|env| {
+                    vec![
+                        env.name,
+                        yes_or_empty(env.active),
+                        env.entry_count.to_string(),
+                        env.layer.to_string(),
+                        protection(env.protected, env.locked),
+                        env.description.unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print!(
+                "{}",
+                output::table(
+                    &[
+                        "NAME",
+                        "ACTIVE",
+                        "ENTRIES",
+                        "LAYER",
+                        "PROTECTION",
+                        "DESCRIPTION"
+                    ],
+                    &rows
+                )
+            );
+            return Ok(());
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print!("{}", output.serialize(&EnvironmentSummary::all(&config))?);
+            return Ok(());
+        }
+    }
 
     if config.environments.is_empty() {
         println!("No environments configured.");
@@ -106,15 +679,48 @@ fn list_environments() -> Result<()> {
         } else {
             ""
         };
-        println!("  - {}{}: {} entries", name, current, env.entries.len());
+        let layer = match env.layer {
+            ConfigLayer::User => String::new(),
+            layer => format!(" [{layer}]"),
+        };
+        println!(
+            "  - {}{}: {} entries{}",
+            name,
+            current,
+            env.entries.len(),
+            layer
+        );
     }
 
     Ok(())
 }
 
 /// Show current environment
-fn show_current_environment() -> Result<()> {
-    let config = ConfigStorage::load_config()?;
+fn show_current_environment(store: &dyn ConfigStore, output: OutputFormat) -> Result<()> {
+    let config = store.load()?;
+
+    if output != OutputFormat::Plain {
+        let current = config
+            .current_environment
+            .as_deref()
+            .and_then(|name| config.get_environment(name));
+        return match (output, current) {
+            (OutputFormat::Table, Some(env)) => {
+                print!("{}", entries_table(env));
+                Ok(())
+            }
+            (OutputFormat::Table, None) => {
+                println!("No environment is currently active.");
+                Ok(())
+            }
+            // No active environment is `null`
+            _ => {
+                let view = current.map(|env| EnvironmentView::new(&config, env));
+                print!("{}", output.serialize(&view)?);
+                Ok(())
+            }
+        };
+    }
 
     match &config.current_environment {
         Some(name) => {
@@ -125,7 +731,7 @@ fn show_current_environment() -> Result<()> {
                 }
                 println!("Entries:");
                 for entry in &env.entries {
-                    println!("  {}", entry.to_line());
+                    println!("  {}", format_entry(entry));
                 }
             } else {
                 println!("Current environment '{name}' not found.");
@@ -139,140 +745,1619 @@ fn show_current_environment() -> Result<()> {
     Ok(())
 }
 
-/// Switch to specified environment
-fn switch_environment(name: &str) -> Result<()> {
-    let mut config = ConfigStorage::load_config()?;
+/// Show active environment and pending timed reverts
+fn show_status(store: &dyn ConfigStore, output: OutputFormat) -> Result<()> {
+    let config = store.load()?;
 
-    if let Some(env) = config.get_environment(name) {
-        // Verify all entries in the environment
-        for entry in &env.entries {
-            if !HostsManager::is_valid_hostname(&entry.hostname) {
-                anyhow::bail!(
-                    "Invalid hostname in environment '{name}': {}",
-                    entry.hostname
-                );
+    match output {
+        OutputFormat::Plain => {}
+        OutputFormat::Table => {
+            let revert = config.pending_revert.as_ref().map(|pending| {
+                format!(
+                    "to {} at {}",
+                    pending.previous_environment.as_deref().unwrap_or("(none)"),
+                    pending
+                        .deadline
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                )
+            });
+            let status = StatusView::new(&config, |path| Locations::current().target_file(path));
+            let mut rows = vec![vec![
+                "(system)".to_string(),
+                status.active_environment.unwrap_or_default(),
+                Locations::current().hosts_file().display().to_string(),
+                revert.unwrap_or_default(),
+            ]];
+            for target in status.targets {
+                rows.push(vec![
+                    target.name,
+                    target.active_environment.unwrap_or_default(),
+                    target.path.display().to_string(),
+                ]);
             }
+            print!(
+                "{}",
+                output::table(&["TARGET", "ENVIRONMENT", "HOSTS FILE", "REVERT"], &rows)
+            );
+            return Ok(());
         }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let status = StatusView::new(&config, |path| Locations::current().target_file(path));
+            print!("{}", output.serialize(&status)?);
+            return Ok(());
+        }
+    }
 
-        // Apply environment
-        HostsManager::apply_environment(env)?;
-        config.current_environment = Some(name.to_string());
-        ConfigStorage::save_config(&config)?;
+    match &config.current_environment {
+        Some(name) => println!("Active environment: {name}"),
+        None => println!("Active environment: (none)"),
+    }
 
-        println!("Switched to environment: {name}");
-    } else {
-        anyhow::bail!("Environment '{name}' not found.");
+    match &config.pending_revert {
+        Some(pending) => {
+            let target = pending
+                .previous_environment
+                .as_deref()
+                .map_or_else(|| "no environment".to_string(), |prev| format!("'{prev}'"));
+            println!(
+                "Pending revert: back to {target} at {} (in {})",
+                pending
+                    .deadline
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+                timer::format_duration(pending.remaining(Utc::now()))
+            );
+        }
+        None => println!("Pending revert: none"),
+    }
+
+    if !config.targets.is_empty() {
+        println!("Targets:");
+        for (target, path) in &config.targets {
+            let env = config
+                .target_environments
+                .get(target)
+                .map_or("(none)", String::as_str);
+            println!(
+                "  {target}: {env} ({})",
+                Locations::current().target_file(path).display()
+            );
+        }
     }
 
     Ok(())
 }
 
-/// Show details of specified environment
-fn show_environment(name: &str) -> Result<()> {
-    let config = ConfigStorage::load_config()?;
+/// Resolve an environment name given on the command line to its full name
+fn environment_name(config: &Config, name: &str) -> Result<String> {
+    config
+        .resolve_environment_name(name)
+        .map(str::to_string)
+        .ok_or_else(|| NotFoundError::Environment(name.to_string()).into())
+}
 
-    if let Some(env) = config.get_environment(name) {
-        println!("Environment: {name}");
-        if let Some(desc) = &env.description {
-            println!("Description: {desc}");
-        }
-        println!("Entries:");
-        if env.entries.is_empty() {
-            println!("  (no entries)");
-        } else {
-            for entry in &env.entries {
-                println!("  {}", entry.to_line());
-            }
+/// Resolve a command line environment name to an environment that may be modified
+fn writable_environment_name(config: &Config, name: &str) -> Result<String> {
+    let name = environment_name(config, name)?;
+    match config.get_environment(&name).map(|env| env.layer) {
+        Some(ConfigLayer::System) => anyhow::bail!(
+            "Environment '{name}' is defined in the system configuration ({}) and is read-only.",
+            ConfigStorage::get_system_config_path().display()
+        ),
+        Some(ConfigLayer::Remote) => {
+            anyhow::bail!("Environment '{name}' is provided by a remote source and is read-only.")
         }
-    } else {
-        anyhow::bail!("Environment '{name}' not found.");
+        _ => {}
     }
+    Ok(name)
+}
+
+/// Check an environment's protection settings before performing an action on it
+///
+/// Protected environments ask for confirmation when stdin is a terminal and fail
+/// closed otherwise, unless `force` is set.
+fn authorize(config: &Config, name: &str, action: Action, force: bool) -> Result<()> {
+    let env = config
+        .get_environment(name)
+        .ok_or_else(|| NotFoundError::Environment(name.to_string()))?;
 
+    let prompt = io::stdin().is_terminal().then_some(confirm);
+    protect::authorize(env, action, force, prompt)?;
     Ok(())
 }
 
-/// Create new environment
-fn add_environment(name: &str, description: Option<String>) -> Result<()> {
-    let mut config = ConfigStorage::load_config()?;
+/// Check an environment's protection settings before replacing it with another state
+///
+/// Environments that do not exist yet are not protected.
+fn authorize_change(
+    config: &Config,
+    name: &str,
+    state: Option<&Environment>,
+    force: bool,
+) -> Result<()> {
+    let Some(current) = config.get_environment(name) else {
+        return Ok(());
+    };
+    match Action::for_change(current, state) {
+        Some(action) => authorize(config, name, action, force),
+        None => Ok(()),
+    }
+}
 
-    // Validate environment name
-    if !HostsManager::is_valid_hostname(name) {
-        anyhow::bail!("Invalid environment name: {name}");
+/// Report policy violations, failing if any of them is an error
+fn enforce_policies(violations: &[Violation]) -> Result<()> {
+    for violation in violations {
+        eprintln!("Policy {violation}");
     }
 
-    if config.get_environment(name).is_some() {
-        anyhow::bail!("Environment '{name}' already exists.");
+    let errors = violations
+        .iter()
+        .filter(|v| v.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("Rejected by {errors} policy violation(s).");
     }
+    Ok(())
+}
 
-    let mut env = Environment::new(name.to_string());
-    if let Some(desc) = description {
-        env = env.with_description(desc);
+/// Ask a yes/no question on the terminal, defaulting to no
+fn confirm(prompt: &str) -> bool {
+    eprint!("{prompt}");
+    if io::stderr().flush().is_err() {
+        return false;
     }
 
-    config.add_environment(env);
-    ConfigStorage::save_config(&config)?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// The arguments of this invocation, as used in commit messages
+///
+/// The global options locating the config and hosts files are left out: their
+/// paths are local to this machine and do not belong in a shared history.
+fn command_args() -> String {
+    let locations: Vec<String> = Cli::command()
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .filter_map(|arg| arg.get_long().map(|long| format!("--{long}")))
+        .collect();
+
+    let mut args = std::env::args().skip(1);
+    let mut kept = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            kept.push(arg);
+            kept.extend(args.by_ref());
+        } else if locations.contains(&arg) {
+            args.next();
+        } else if !locations
+            .iter()
+            .any(|long| arg.starts_with(&format!("{long}=")))
+        {
+            kept.push(arg);
+        }
+    }
+
+    kept.into_iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("'{arg}'")
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The command line of this invocation, as shown in the history
+fn command_line() -> String {
+    format!("hostctl {}", command_args())
+}
+
+/// Commit changes of the config directory if it is a git repository
+///
+/// The changes themselves have already been saved, so a failed commit only warns.
+fn commit_changes() {
+    let dir = ConfigStorage::get_config_dir_path();
+    if !git::is_repository(&dir) {
+        return;
+    }
+    if let Err(e) = git::commit_all(&dir, &command_args()) {
+        eprintln!("Warning: failed to commit configuration change: {e:#}");
+    }
+}
+
+/// Get the config directory, requiring it to be a git repository
+fn config_repository() -> Result<PathBuf> {
+    let dir = ConfigStorage::get_config_dir_path();
+    if !git::is_repository(&dir) {
+        anyhow::bail!(
+            "The config directory {} is not a git repository; enable it with 'hostctl config git'.",
+            dir.display()
+        );
+    }
+    Ok(dir)
+}
+
+/// Append a saved change of an environment to the undo journal
+///
+/// The change itself has already been written, so failing to journal it only warns.
+fn record_change(config: &Config, name: &str, before: Option<&Environment>) {
+    let record = Record::new(command_line(), name, before, config.get_environment(name));
+    let path = ConfigStorage::get_journal_path();
+    let result = Journal::load(&path).and_then(|mut journal| {
+        journal.record(record);
+        journal.save(&path)
+    });
+    if let Err(e) = result {
+        eprintln!("Warning: failed to update the undo journal: {e:#}");
+    }
+}
+
+/// Resolve, validate and write an environment to a hosts file
+///
+/// Addresses produced by dynamic sources of user environments are stored back into
+/// `config`, so the most recently resolved IPs are shown by `show` and `current`.
+fn activate_environment(config: &mut Config, name: &str, hosts: &HostsManager) -> Result<()> {
+    let env = config
+        .get_environment(name)
+        .ok_or_else(|| NotFoundError::Environment(name.to_string()))?;
+
+    // Validates hostnames and aliases, then evaluates dynamic sources
+    let resolved = resolve::resolve_environment(env)?;
+    enforce_policies(&policy::check_environment(
+        &config.policies,
+        &resolved,
+        true,
+    ))?;
+    hosts.apply_environment(&resolved)?;
+
+    // Shared configuration files are not rewritten just to cache resolved addresses
+    if resolved.layer == ConfigLayer::User {
+        config.add_environment(resolved);
+    }
 
-    println!("Environment '{name}' created successfully.");
     Ok(())
 }
 
-/// Remove environment
-fn remove_environment(name: &str) -> Result<()> {
-    let mut config = ConfigStorage::load_config()?;
+/// Switch to specified environment
+fn switch_environment(store: &dyn ConfigStore, name: &str, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let name = environment_name(&config, name)?;
+    authorize(&config, &name, Action::Switch, force)?;
 
-    if config.remove_environment(name) {
-        ConfigStorage::save_config(&config)?;
-        println!("Environment '{name}' removed successfully.");
-    } else {
-        anyhow::bail!("Environment '{name}' not found.");
+    // Apply environment
+    activate_environment(&mut config, &name, &hosts_manager())?;
+    config.current_environment = Some(name.clone());
+    // An explicit switch replaces any pending timed revert
+    config.pending_revert = None;
+    store.save(&config)?;
+
+    println!("Switched to environment: {name}");
+
+    Ok(())
+}
+
+/// Switch the hosts file of a target to the specified environment
+fn switch_target(store: &dyn ConfigStore, target: &str, name: &str, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let hosts = target_hosts_manager(&config, target)?;
+    let name = environment_name(&config, name)?;
+    authorize(&config, &name, Action::Switch, force)?;
+
+    activate_environment(&mut config, &name, &hosts)?;
+    config
+        .target_environments
+        .insert(target.to_string(), name.clone());
+    store.save(&config)?;
+
+    println!("Switched target '{target}' to environment: {name}");
+
+    Ok(())
+}
+
+/// Apply the specified environment to the hosts file seen by a running process
+///
+/// Processes come and go, so the environment is not recorded as active anywhere.
+fn switch_process(store: &dyn ConfigStore, pid: u32, name: &str, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let hosts = HostsManager::for_process(pid)?;
+    let name = environment_name(&config, name)?;
+    authorize(&config, &name, Action::Switch, force)?;
+
+    activate_environment(&mut config, &name, &hosts)?;
+    store.save(&config)?;
+
+    println!("Switched process {pid} to environment: {name}");
+
+    Ok(())
+}
+
+/// Re-resolve the active environment's dynamic sources and reapply it
+///
+/// With a target, the environment active in that target is refreshed instead.
+fn refresh_environment(store: &dyn ConfigStore, target: Option<&str>) -> Result<()> {
+    let mut config = store.load()?;
+
+    let (name, hosts) = match target {
+        Some(target) => (
+            config
+                .target_environments
+                .get(target)
+                .cloned()
+                .with_context(|| format!("No environment is active in target '{target}'."))?,
+            target_hosts_manager(&config, target)?,
+        ),
+        None => (
+            config
+                .current_environment
+                .clone()
+                .context("No environment is currently active.")?,
+            hosts_manager(),
+        ),
+    };
+    activate_environment(&mut config, &name, &hosts)?;
+    store.save(&config)?;
+
+    println!("Refreshed environment: {name}");
+
+    Ok(())
+}
+
+/// Switch to specified environment and revert to the previous one after a duration
+fn switch_environment_for(
+    store: &dyn ConfigStore,
+    name: &str,
+    duration: &str,
+    force: bool,
+) -> Result<()> {
+    let duration = timer::parse_duration(duration)?;
+    // Checked before the hosts file is touched, so a bad duration changes nothing
+    let deadline = Utc::now().checked_add_signed(duration).with_context(|| {
+        format!(
+            "Duration '{}' is too large",
+            timer::format_duration(duration)
+        )
+    })?;
+    let mut config = store.load()?;
+    let name = environment_name(&config, name)?;
+    authorize(&config, &name, Action::Switch, force)?;
+
+    activate_environment(&mut config, &name, &hosts_manager())?;
+
+    // Chained timed switches still revert to the environment active before the first one
+    let previous = match config.pending_revert.take() {
+        Some(pending) => pending.previous_environment,
+        None => config.current_environment.clone(),
+    };
+
+    config.current_environment = Some(name.clone());
+    config.pending_revert = Some(PendingRevert::new(previous.clone(), deadline));
+    store.save(&config)?;
+
+    let at = deadline.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+    println!(
+        "Switched to environment: {name} for {}",
+        timer::format_duration(duration)
+    );
+    match previous {
+        Some(prev) => println!("Will revert to '{prev}' at {at}"),
+        None => println!("Will clear managed entries at {at}"),
+    }
+
+    if let Err(e) = spawn_timer_watcher() {
+        eprintln!(
+            "Warning: failed to start background timer ({e:#}); the revert will happen on the next hostctl invocation after the deadline."
+        );
     }
 
     Ok(())
 }
 
-/// Add hosts entry to environment
-fn add_entry(environment: &str, ip: &str, hostname: &str, comment: Option<String>) -> Result<()> {
-    let mut config = ConfigStorage::load_config()?;
+/// Cancel the pending revert and keep the current environment
+fn cancel_timer(store: &dyn ConfigStore) -> Result<()> {
+    let mut config = store.load()?;
 
-    // Validate IP address
-    let ip_addr: IpAddr = ip.parse().context("Invalid IP address")?;
+    if config.pending_revert.take().is_none() {
+        anyhow::bail!("No timed switch is pending.");
+    }
+    store.save(&config)?;
 
-    // Validate hostname
-    if !HostsManager::is_valid_hostname(hostname) {
-        anyhow::bail!("Invalid hostname: {hostname}");
+    match &config.current_environment {
+        Some(name) => println!("Timer cancelled; keeping environment: {name}"),
+        None => println!("Timer cancelled."),
     }
 
-    if let Some(env) = config.get_environment_mut(environment) {
-        let mut entry = HostEntry::new(ip_addr, hostname.to_string());
-        if let Some(comment) = comment {
-            entry = entry.with_comment(comment);
-        }
+    Ok(())
+}
 
-        env.add_entry(entry);
-        ConfigStorage::save_config(&config)?;
+/// Revert a timed switch whose deadline has passed
+///
+/// Does nothing if no timed switch is pending or its deadline is still in the future.
+fn revert_expired_timer(store: &dyn ConfigStore) -> Result<()> {
+    let mut config = store.load()?;
 
-        println!("Entry added to environment '{environment}': {ip} {hostname}");
+    let Some(pending) = config.pending_revert.clone() else {
+        return Ok(());
+    };
+    if !pending.is_expired(Utc::now()) {
+        return Ok(());
+    }
+
+    let previous = pending
+        .previous_environment
+        .clone()
+        .filter(|prev| config.get_environment(prev).is_some());
+
+    if let Some(prev) = previous {
+        activate_environment(&mut config, &prev, &hosts_manager())?;
+        eprintln!("Timed switch expired; reverted to environment: {prev}");
+        config.current_environment = Some(prev);
     } else {
-        anyhow::bail!("Environment '{environment}' not found.");
+        hosts_manager().clear_environment()?;
+        match &pending.previous_environment {
+            Some(prev) => eprintln!(
+                "Timed switch expired; previous environment '{prev}' no longer exists, cleared managed entries."
+            ),
+            None => eprintln!("Timed switch expired; cleared managed entries."),
+        }
+        config.current_environment = None;
     }
 
+    config.pending_revert = None;
+    store.save(&config)?;
+
     Ok(())
 }
 
-/// Remove hosts entry from environment
-fn remove_entry(environment: &str, hostname: &str) -> Result<()> {
-    let mut config = ConfigStorage::load_config()?;
+/// The hosts file managed by this invocation
+fn hosts_manager() -> HostsManager {
+    HostsManager::new(Locations::current().hosts_file())
+}
 
-    if let Some(env) = config.get_environment_mut(environment) {
-        if env.remove_entry(hostname) {
-            ConfigStorage::save_config(&config)?;
-            println!("Entry removed from environment '{environment}': {hostname}");
+/// The hosts file of a configured target
+fn target_hosts_manager(config: &Config, target: &str) -> Result<HostsManager> {
+    let path = config
+        .targets
+        .get(target)
+        .ok_or_else(|| NotFoundError::Target(target.to_string()))?;
+    Ok(HostsManager::new(Locations::current().target_file(path)))
+}
+
+/// Reapply an environment to every target it is active in
+///
+/// Targets of an environment that no longer exists have their managed entries cleared.
+fn reapply_in_targets(config: &mut Config, name: &str) -> Result<()> {
+    let targets: Vec<String> = config
+        .target_environments
+        .iter()
+        .filter(|(_, env)| *env == name)
+        .map(|(target, _)| target.clone())
+        .collect();
+    for target in targets {
+        // Targets removed from the config are left as they are
+        let Ok(hosts) = target_hosts_manager(config, &target) else {
+            continue;
+        };
+        if config.get_environment(name).is_some() {
+            activate_environment(config, name, &hosts)?;
         } else {
-            anyhow::bail!("Entry '{hostname}' not found in environment '{environment}'.");
+            hosts.clear_environment()?;
+            config.target_environments.remove(&target);
         }
-    } else {
-        anyhow::bail!("Environment '{environment}' not found.");
+    }
+    Ok(())
+}
+
+/// Start a detached hostctl process that reverts the timed switch once it expires
+fn spawn_timer_watcher() -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate hostctl executable")?;
+    let mut command = Command::new(exe);
+    command
+        .args(Locations::current().args())
+        .arg("watch-timer")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    // Leave the terminal's process group so closing the shell does not stop the watcher
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
     }
 
+    command.spawn().context("Failed to spawn timer watcher")?;
     Ok(())
 }
+
+/// Wait for a pending timed switch to expire and revert it
+///
+/// Exits as soon as no timed switch is pending, e.g. after `switch --cancel-timer`.
+fn watch_timer(store: &dyn ConfigStore) -> Result<()> {
+    // Re-check periodically in case the timer is cancelled or replaced
+    const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+    loop {
+        let config = store.load()?;
+        let Some(pending) = &config.pending_revert else {
+            return Ok(());
+        };
+
+        let remaining = pending.remaining(Utc::now());
+        if remaining.is_zero() {
+            let _lock = store.lock()?;
+            return revert_expired_timer(store);
+        }
+
+        let wait = remaining
+            .to_std()
+            .map_or(POLL_INTERVAL, |d| d.min(POLL_INTERVAL));
+        std::thread::sleep(wait);
+    }
+}
+
+/// Show details of specified environment
+fn show_environment(store: &dyn ConfigStore, name: &str, output: OutputFormat) -> Result<()> {
+    let config = store.load()?;
+    let name = config.resolve_environment_name(name).unwrap_or(name);
+
+    match (output, config.get_environment(name)) {
+        (OutputFormat::Table, Some(env)) => {
+            print!("{}", entries_table(env));
+            return Ok(());
+        }
+        (OutputFormat::Json | OutputFormat::Yaml, Some(env)) => {
+            print!("{}", output.serialize(&EnvironmentView::new(&config, env))?);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(env) = config.get_environment(name) {
+        println!("Environment: {name}");
+        if let Some(desc) = &env.description {
+            println!("Description: {desc}");
+        }
+        if env.locked {
+            println!("Protection: locked");
+        } else if env.protected {
+            println!("Protection: protected");
+        }
+        println!("Entries:");
+        if env.entries.is_empty() {
+            println!("  (no entries)");
+        } else {
+            for entry in &env.entries {
+                println!("  {}", format_entry(entry));
+            }
+        }
+    } else {
+        return Err(NotFoundError::Environment(name.to_string()).into());
+    }
+
+    Ok(())
+}
+
+/// Create new environment
+fn add_environment(
+    store: &dyn ConfigStore,
+    name: &str,
+    description: Option<String>,
+    protected: bool,
+    locked: bool,
+) -> Result<()> {
+    let mut config = store.load()?;
+
+    // Validate environment name
+    if !HostsManager::is_valid_hostname(name) {
+        anyhow::bail!("Invalid environment name: {name}");
+    }
+
+    // A user environment may hide a system environment of the same name
+    let hides_system = match config.get_environment(name) {
+        Some(env) if env.layer == ConfigLayer::System => true,
+        Some(_) => anyhow::bail!("Environment '{name}' already exists."),
+        None => false,
+    };
+
+    let mut env = Environment::new(name.to_string());
+    if let Some(desc) = description {
+        env = env.with_description(desc);
+    }
+    env.protected = protected;
+    env.locked = locked;
+
+    let before = config
+        .get_environment(name)
+        .filter(|env| env.layer.is_writable())
+        .cloned();
+    config.add_environment(env);
+    store.save(&config)?;
+    record_change(&config, name, before.as_ref());
+
+    println!("Environment '{name}' created successfully.");
+    if hides_system {
+        println!("It hides the system environment of the same name.");
+    }
+    Ok(())
+}
+
+/// Remove environment
+fn remove_environment(store: &dyn ConfigStore, name: &str, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let name = &writable_environment_name(&config, name)?;
+    authorize(&config, name, Action::Remove, force)?;
+
+    let before = config.get_environment(name).cloned();
+    if config.remove_environment(name) {
+        store.save(&config)?;
+        record_change(&config, name, before.as_ref());
+        println!("Environment '{name}' removed successfully.");
+    } else {
+        return Err(NotFoundError::Environment(name.to_string()).into());
+    }
+
+    Ok(())
+}
+
+/// Add hosts entry to environment
+fn add_entry(
+    store: &dyn ConfigStore,
+    environment: &str,
+    ip: &str,
+    hostname: &str,
+    comment: Option<String>,
+    force: bool,
+) -> Result<()> {
+    let mut config = store.load()?;
+    let environment = &writable_environment_name(&config, environment)?;
+    authorize(&config, environment, Action::AddEntry, force)?;
+
+    // Validate hostname
+    if !HostsManager::is_valid_hostname(hostname) {
+        anyhow::bail!("Invalid hostname: {hostname}");
+    }
+
+    // Validate IP address, source specification or alias target
+    let mut entry = match ip.strip_prefix("target:") {
+        Some(target) => {
+            if !HostsManager::is_valid_hostname(target) {
+                anyhow::bail!("Invalid alias target: {target}");
+            }
+            HostEntry::alias(hostname.to_string(), target.to_string())
+        }
+        None => HostEntry::from_source(ip.parse::<IpSource>()?, hostname.to_string()),
+    };
+    let in_project = config
+        .get_environment(environment)
+        .is_some_and(|env| env.layer == ConfigLayer::Project);
+    if in_project && !matches!(entry.source, None | Some(IpSource::Literal { .. })) {
+        anyhow::bail!(
+            "Environment '{environment}' is defined in a project file, which may not use dynamic IP sources."
+        );
+    }
+    if let Some(comment) = comment {
+        entry = entry.with_comment(comment);
+    }
+
+    let before = config.get_environment(environment).cloned();
+    if let Some(env) = config.get_environment_mut(environment) {
+        let is_alias = entry.is_alias();
+        env.add_entry(entry);
+
+        // Reject aliases that would dangle or loop when the environment is switched to
+        if is_alias {
+            resolve::validate_environment(env)?;
+        }
+
+        // Only the new entry is checked; existing violations are reported on switch
+        let mut added = Environment::new(environment.clone());
+        added.entries.extend(env.entries.last().cloned());
+        enforce_policies(&policy::check_environment(&config.policies, &added, false))?;
+        store.save(&config)?;
+        record_change(&config, environment, before.as_ref());
+
+        println!("Entry added to environment '{environment}': {ip} {hostname}");
+    } else {
+        return Err(NotFoundError::Environment(environment.clone()).into());
+    }
+
+    Ok(())
+}
+
+/// Remove hosts entry from environment
+fn remove_entry(
+    store: &dyn ConfigStore,
+    environment: &str,
+    hostname: &str,
+    force: bool,
+) -> Result<()> {
+    let mut config = store.load()?;
+    let environment = &writable_environment_name(&config, environment)?;
+    authorize(&config, environment, Action::RemoveEntry, force)?;
+
+    let before = config.get_environment(environment).cloned();
+    if let Some(env) = config.get_environment_mut(environment) {
+        if env.remove_entry(hostname) {
+            if env.find_entry(hostname).is_none() {
+                for alias in env
+                    .entries
+                    .iter()
+                    .filter(|e| e.target.as_deref() == Some(hostname))
+                {
+                    eprintln!(
+                        "Warning: alias '{}' still points to '{hostname}' and must be updated before switching.",
+                        alias.hostname
+                    );
+                }
+            }
+            store.save(&config)?;
+            record_change(&config, environment, before.as_ref());
+            println!("Entry removed from environment '{environment}': {hostname}");
+        } else {
+            return Err(NotFoundError::Entry {
+                environment: environment.clone(),
+                hostname: hostname.to_string(),
+            }
+            .into());
+        }
+    } else {
+        return Err(NotFoundError::Environment(environment.clone()).into());
+    }
+
+    Ok(())
+}
+
+/// Check environments against the policy rules and report all violations
+fn check_policies(
+    store: &dyn ConfigStore,
+    environment: Option<&str>,
+    resolve_sources: bool,
+) -> Result<()> {
+    let config = store.load()?;
+
+    if config.policies.is_empty() {
+        println!("No policy rules configured.");
+        return Ok(());
+    }
+
+    let mut names: Vec<String> = match environment {
+        Some(name) => vec![environment_name(&config, name)?],
+        None => config.environments.keys().cloned().collect(),
+    };
+    names.sort();
+
+    let mut violations = Vec::new();
+    for name in &names {
+        let Some(env) = config.get_environment(name) else {
+            continue;
+        };
+        if resolve_sources {
+            let resolved = resolve::resolve_environment(env)?;
+            violations.extend(policy::check_environment(&config.policies, &resolved, true));
+        } else {
+            violations.extend(policy::check_environment(&config.policies, env, false));
+        }
+    }
+
+    for violation in &violations {
+        println!("{violation}");
+    }
+    let errors = violations
+        .iter()
+        .filter(|v| v.severity == Severity::Error)
+        .count();
+    println!(
+        "Checked {} environment(s) against {} rule(s): {errors} error(s), {} warning(s).",
+        names.len(),
+        config.policies.len(),
+        violations.len() - errors
+    );
+
+    if errors > 0 {
+        anyhow::bail!("Policy check failed.");
+    }
+    Ok(())
+}
+
+/// Mark an environment as protected, optionally locking it
+fn protect_environment(store: &dyn ConfigStore, name: &str, locked: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let name = writable_environment_name(&config, name)?;
+
+    let before = config.get_environment(&name).cloned();
+    if let Some(env) = config.get_environment_mut(&name) {
+        env.protected = true;
+        env.locked |= locked;
+    }
+    store.save(&config)?;
+    record_change(&config, &name, before.as_ref());
+
+    if locked {
+        println!("Environment '{name}' is now protected and locked.");
+    } else {
+        println!("Environment '{name}' is now protected.");
+    }
+    Ok(())
+}
+
+/// Clear the protection settings of an environment
+fn unprotect_environment(store: &dyn ConfigStore, name: &str, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+    let name = writable_environment_name(&config, name)?;
+    authorize(&config, &name, Action::Unprotect, force)?;
+
+    let before = config.get_environment(&name).cloned();
+    if let Some(env) = config.get_environment_mut(&name) {
+        env.protected = false;
+        env.locked = false;
+    }
+    store.save(&config)?;
+    record_change(&config, &name, before.as_ref());
+
+    println!("Environment '{name}' is no longer protected.");
+    Ok(())
+}
+
+/// Undo or redo one recorded change
+///
+/// The environment is set to the state recorded before (undo) or after (redo) the
+/// change. If it is the active environment, the hosts file is updated to match.
+/// The change is authorized against the environment's current protection settings.
+fn step_journal(store: &dyn ConfigStore, undo: bool, force: bool) -> Result<()> {
+    let path = ConfigStorage::get_journal_path();
+    let mut journal = Journal::load(&path)?;
+    let step = if undo { journal.undo() } else { journal.redo() };
+    let Some(record) = step.cloned() else {
+        println!("Nothing to {}.", if undo { "undo" } else { "redo" });
+        return Ok(());
+    };
+    let (state, expected) = if undo {
+        (record.before.as_ref(), record.after.as_ref())
+    } else {
+        (record.after.as_ref(), record.before.as_ref())
+    };
+
+    // Project environments can only be written while the project is discovered
+    if record.layer == ConfigLayer::Project {
+        let in_project = Project::discover_from_cwd()?.is_some_and(|project| {
+            record
+                .environment
+                .starts_with(&format!("{}/", project.name))
+        });
+        if !in_project {
+            anyhow::bail!(
+                "Environment '{}' belongs to a project; run this command from the project directory.",
+                record.environment
+            );
+        }
+    }
+
+    let mut config = store.load()?;
+    authorize_change(&config, &record.environment, state, force)?;
+    if !journal::restore_environment(&mut config, &record, state, expected) {
+        eprintln!(
+            "Warning: environment '{}' was changed since; those changes are replaced.",
+            record.environment
+        );
+    }
+
+    if config.current_environment.as_deref() == Some(record.environment.as_str()) {
+        if state.is_some() {
+            activate_environment(&mut config, &record.environment, &hosts_manager())?;
+        } else {
+            hosts_manager().clear_environment()?;
+            config.current_environment = None;
+            config.pending_revert = None;
+        }
+    }
+    reapply_in_targets(&mut config, &record.environment)?;
+    store.save(&config)?;
+    journal.save(&path)?;
+
+    let verb = if undo { "Undid" } else { "Redid" };
+    println!("{verb}: {}", record.command);
+    Ok(())
+}
+
+/// Show the most recent recorded changes, newest first
+fn show_history(limit: usize, output: OutputFormat) -> Result<()> {
+    let journal = Journal::load(&ConfigStorage::get_journal_path())?;
+
+    match output {
+        OutputFormat::Plain => {}
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = HistoryView::recent(&journal, limit)
+                .into_iter()
+                .map(|change| {
+                    vec![
+                        change.number.to_string(),
+                        change
+                            .timestamp
+                            .with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string(),
+                        change.environment,
+                        if change.undone { "undone" } else { "" }.to_string(),
+                        change.command,
+                    ]
+                })
+                .collect();
+            print!(
+                "{}",
+                output::table(&["#", "TIME", "ENVIRONMENT", "STATE", "COMMAND"], &rows)
+            );
+            return Ok(());
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print!(
+                "{}",
+                output.serialize(&HistoryView::recent(&journal, limit))?
+            );
+            return Ok(());
+        }
+    }
+
+    if journal.records.is_empty() {
+        println!("No changes recorded.");
+        return Ok(());
+    }
+
+    for (index, record) in journal.records.iter().enumerate().rev().take(limit) {
+        let undone = if index >= journal.cursor {
+            " (undone)"
+        } else {
+            ""
+        };
+        println!(
+            "{:>4}  {}  {}{undone}",
+            index + 1,
+            record
+                .timestamp
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            record.command
+        );
+    }
+
+    Ok(())
+}
+
+/// Export an environment to a bundle, optionally signed
+fn export_bundle(
+    store: &dyn ConfigStore,
+    name: &str,
+    key_file: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let config = store.load()?;
+    let name = environment_name(&config, name)?;
+    let env = config
+        .get_environment(&name)
+        .ok_or_else(|| NotFoundError::Environment(name.to_string()))?;
+
+    let key = key_file.map(bundle::load_signing_key).transpose()?;
+    let content = Bundle::create(env, key.as_ref())?.to_json()?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, content)
+                .with_context(|| format!("Failed to write bundle: {}", path.display()))?;
+            let signed = if key.is_some() { "Signed" } else { "Unsigned" };
+            eprintln!("{signed} bundle of '{name}' written to {}", path.display());
+        }
+        None => print!("{content}"),
+    }
+    Ok(())
+}
+
+/// Create an environment from a bundle whose signature is trusted
+fn import_bundle(
+    store: &dyn ConfigStore,
+    file: &Path,
+    name: Option<&str>,
+    allow_untrusted: bool,
+) -> Result<()> {
+    let content = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read bundle: {}", file.display()))?;
+    let bundle = Bundle::parse(&content)?;
+    let mut config = store.load()?;
+
+    let verification = bundle.verify(&config.trusted_keys);
+    if !verification.is_trusted() {
+        if !allow_untrusted {
+            let hint = match verification {
+                Verification::UntrustedKey { .. } => {
+                    "Add the signer's key to 'trusted_keys' in the config if you trust it"
+                }
+                _ => "Ask for a bundle signed with a trusted key",
+            };
+            anyhow::bail!(
+                "Refusing to import {}: {verification}. {hint}, or pass --allow-untrusted to import it anyway.",
+                file.display()
+            );
+        }
+        eprintln!("WARNING: {verification}.");
+        eprintln!(
+            "WARNING: Importing without a trusted signature; its entries can redirect traffic for any hostname."
+        );
+    }
+
+    let mut env = bundle.environment()?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        // Bundles of project or remote environments carry a namespaced name
+        None => env.name.rsplit('/').next().unwrap_or_default().to_string(),
+    };
+    if !HostsManager::is_valid_hostname(&name) {
+        anyhow::bail!("Invalid environment name: {name}");
+    }
+    if config.get_environment(&name).is_some() {
+        anyhow::bail!("Environment '{name}' already exists; choose another name with --name.");
+    }
+    env.name.clone_from(&name);
+    env.layer = ConfigLayer::User;
+
+    // Even a trusted signer may not make this machine run commands or read files
+    if let Some(entry) = env.find_dynamic_source() {
+        anyhow::bail!(
+            "Refusing to import {}: it uses a dynamic IP source for '{}', which bundles may not do.",
+            file.display(),
+            entry.hostname
+        );
+    }
+    resolve::validate_environment(&env)?;
+    enforce_policies(&policy::check_environment(&config.policies, &env, false))?;
+
+    config.add_environment(env);
+    store.save(&config)?;
+    record_change(&config, &name, None);
+
+    match verification {
+        Verification::Trusted { name: key } => {
+            println!("Environment '{name}' imported (signed by '{key}').");
+        }
+        _ => println!("Environment '{name}' imported WITHOUT a trusted signature."),
+    }
+    Ok(())
+}
+
+/// Generate a key pair for signing bundles
+fn generate_key(path: &Path) -> Result<()> {
+    let public = bundle::generate_key(path)?;
+    println!(
+        "Secret key written to {} (keep it private).",
+        path.display()
+    );
+    println!("Public key: {public}");
+    println!("Teammates trust it by adding to their config:");
+    println!("trusted_keys:\n  - name: <your name>\n    key: {public}");
+    Ok(())
+}
+
+/// Show the configured remote sources and the state of their cached copies
+fn list_sources(store: &dyn ConfigStore, output: OutputFormat) -> Result<()> {
+    let config = store.load()?;
+
+    let cache_dir = ConfigStorage::get_source_cache_dir();
+    let views = config
+        .sources
+        .iter()
+        .map(|source| {
+            Ok(SourceView::new(
+                source,
+                source.load_cached(&cache_dir)?.as_ref(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fetched = |view: &SourceView| {
+        view.fetched_at.map(|fetched_at| {
+            fetched_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+    };
+
+    match output {
+        OutputFormat::Plain => {}
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = views
+                .iter()
+                .map(|view| {
+                    vec![
+                        view.name.clone(),
+                        view.layer.to_string(),
+                        fetched(view).unwrap_or_default(),
+                        view.url.clone(),
+                    ]
+                })
+                .collect();
+            print!(
+                "{}",
+                output::table(&["NAME", "LAYER", "FETCHED", "URL"], &rows)
+            );
+            return Ok(());
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print!("{}", output.serialize(&views)?);
+            return Ok(());
+        }
+    }
+
+    if views.is_empty() {
+        println!("No remote sources configured.");
+        return Ok(());
+    }
+
+    for view in &views {
+        let fetched = fetched(view).map_or_else(
+            || "never fetched".to_string(),
+            |fetched| format!("fetched {fetched}"),
+        );
+        let layer = match view.layer {
+            ConfigLayer::User => String::new(),
+            layer => format!(" [{layer}]"),
+        };
+        println!("  - {}: {} ({fetched}){layer}", view.name, view.url);
+    }
+
+    Ok(())
+}
+
+/// Fetch remote sources into the cache
+///
+/// Every source is attempted; the command fails if any of them could not be updated.
+fn update_sources(store: &dyn ConfigStore, name: Option<&str>) -> Result<()> {
+    let config = store.load()?;
+    let selected: Vec<&RemoteSource> = config
+        .sources
+        .iter()
+        .filter(|source| name.is_none_or(|name| source.name == name))
+        .collect();
+
+    if selected.is_empty() {
+        match name {
+            Some(name) => return Err(NotFoundError::Source(name.to_string()).into()),
+            None => {
+                println!("No remote sources configured.");
+                return Ok(());
+            }
+        }
+    }
+
+    let cache_dir = ConfigStorage::get_source_cache_dir();
+    let mut failed = 0;
+    for source in selected {
+        match source.update(&cache_dir) {
+            Ok(FetchOutcome::Updated { environments }) => {
+                println!("{}: updated ({environments} environment(s))", source.name);
+            }
+            Ok(FetchOutcome::NotModified) => println!("{}: not modified", source.name),
+            Err(e) => {
+                failed += 1;
+                // An unreadable copy is as good as none, and must not stop the other sources
+                let fallback = match source.load_cached(&cache_dir) {
+                    Ok(Some(_)) => "keeping the last good copy".to_string(),
+                    Ok(None) => "no copy available".to_string(),
+                    Err(cache_error) => format!("no usable copy: {cache_error:#}"),
+                };
+                eprintln!("{}: {e:#} ({fallback})", source.name);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("Failed to update {failed} source(s).");
+    }
+    Ok(())
+}
+
+/// Show every configuration file and the environments it contributes
+fn show_config_sources(output: OutputFormat) -> Result<()> {
+    let files = ConfigFileView::all(&ConfigStorage::config_sources()?);
+
+    match output {
+        OutputFormat::Plain => {}
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = files
+                .iter()
+                .map(|file| {
+                    let environments: Vec<&str> = file
+                        .environments
+                        .iter()
+                        .map(|env| env.name.as_str())
+                        .collect();
+                    vec![
+                        file.layer.to_string(),
+                        yes_or_empty(file.exists),
+                        file.path.display().to_string(),
+                        environments.join(", "),
+                    ]
+                })
+                .collect();
+            print!(
+                "{}",
+                output::table(&["LAYER", "EXISTS", "PATH", "ENVIRONMENTS"], &rows)
+            );
+            return Ok(());
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            print!("{}", output.serialize(&files)?);
+            return Ok(());
+        }
+    }
+
+    println!("Configuration sources (lowest to highest precedence):");
+    for file in &files {
+        let missing = if file.exists { "" } else { " (not found)" };
+        println!(
+            "  {:<8} {}{missing}",
+            file.layer.to_string(),
+            file.path.display()
+        );
+        for env in &file.environments {
+            let hidden_by = env
+                .hidden_by
+                .map(|layer| format!(" (hidden by {layer})"))
+                .unwrap_or_default();
+            println!("    - {}{hidden_by}", env.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert the user config file to another format
+fn convert_config(format: ConfigFormat) -> Result<()> {
+    let path = ConfigStorage::get_config_path();
+    let converted = ConfigStorage::convert_config_file(&path, format)?;
+
+    println!("Converted {} to {}", path.display(), converted.display());
+    println!("The original file was kept as {}.bak", path.display());
+    Ok(())
+}
+
+/// Show or change the layout of the user configuration
+fn config_layout(layout: Option<ConfigLayout>) -> Result<()> {
+    let path = ConfigStorage::get_config_path();
+
+    let Some(layout) = layout else {
+        println!("{}", ConfigStorage::config_layout(&path));
+        return Ok(());
+    };
+
+    if ConfigStorage::set_layout(&path, layout)? {
+        match layout {
+            ConfigLayout::Directory => {
+                let dir = path.with_file_name("environments");
+                println!("Environments are now stored in {}", dir.display());
+                println!(
+                    "Current environment and timers are kept in state.yaml; do not commit it."
+                );
+            }
+            ConfigLayout::File => {
+                println!("Environments are now stored in {}", path.display());
+            }
+        }
+    } else {
+        println!("The configuration already uses the {layout} layout.");
+    }
+    Ok(())
+}
+
+/// Make sure the configuration loads, offering to restore the last good copy of a
+/// damaged user config file
+fn check_config(store: &FileConfigStore) -> Result<()> {
+    let Err(error) = store.load() else {
+        return Ok(());
+    };
+    let path = store.user_path();
+    let damaged = error
+        .downcast_ref::<ParseError>()
+        .is_some_and(|e| path.parent().is_some_and(|dir| e.path.starts_with(dir)));
+    if !damaged {
+        return Err(error);
+    }
+
+    let saved = fs::metadata(ConfigStorage::snapshot_path(path))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|time| {
+            chrono::DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        });
+    let Some(saved) = saved else {
+        return Err(error.context(
+            "The configuration is damaged; run `hostctl config repair` to salvage what can be parsed",
+        ));
+    };
+
+    let hint = format!(
+        "The configuration is damaged; run `hostctl config repair` to salvage what can be parsed, or `hostctl config repair --snapshot` to restore the copy saved {saved}"
+    );
+    if !io::stdin().is_terminal() {
+        return Err(error.context(hint));
+    }
+
+    eprintln!("Error: {error:?}");
+    eprintln!();
+    if !confirm(&format!(
+        "Restore the last good configuration, saved {saved}? Damaged files are backed up. [y/N] "
+    )) {
+        anyhow::bail!(hint);
+    }
+    for backup in ConfigStorage::restore_snapshot(path)? {
+        eprintln!("Backed up {}", backup.display());
+    }
+    eprintln!("Restored the configuration saved {saved}.");
+    Ok(())
+}
+
+/// Repair a damaged user config file
+fn repair_config(snapshot: bool) -> Result<()> {
+    let path = ConfigStorage::get_config_path();
+
+    if snapshot {
+        for backup in ConfigStorage::restore_snapshot(&path)? {
+            println!("Backed up {}", backup.display());
+        }
+        println!("Restored the last good copy of {}", path.display());
+        return Ok(());
+    }
+
+    let Some(repair) = ConfigStorage::repair_config_file(&path)? else {
+        println!("{} is valid; nothing to repair.", path.display());
+        return Ok(());
+    };
+
+    println!("Repaired {}", path.display());
+    for (label, items) in [
+        ("Recovered", &repair.recovered),
+        ("Restored from the last good copy", &repair.restored),
+        ("Lost", &repair.lost),
+    ] {
+        if !items.is_empty() {
+            println!("  {label}: {}", items.join(", "));
+        }
+    }
+    for backup in &repair.backups {
+        println!("Damaged file kept as {}", backup.display());
+    }
+    Ok(())
+}
+
+/// Merge three versions of a config file, reporting conflicts on stderr
+///
+/// Fails when there are conflicts, which makes git mark the file as conflicted.
+fn merge_driver(base: &Path, ours: &Path, theirs: &Path, path: &Path) -> Result<()> {
+    let conflicts = ConfigStorage::merge_files(base, ours, theirs, path)?;
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    for conflict in &conflicts {
+        eprintln!("Conflict: {conflict} was changed on both sides; kept our version.");
+    }
+    anyhow::bail!(
+        "{} merge conflict(s) in {}",
+        conflicts.len(),
+        path.display()
+    );
+}
+
+/// Turn the config directory into a git repository
+fn enable_git() -> Result<()> {
+    let dir = ConfigStorage::get_config_dir_path();
+    if git::init(&dir)? {
+        println!(
+            "Configuration changes are now committed to a git repository in {}",
+            dir.display()
+        );
+    } else {
+        git::register_merge_driver(&dir)?;
+        println!("The config directory is already a git repository; registered the merge driver.");
+    }
+    Ok(())
+}
+
+/// Show the most recent commits of the configuration repository
+fn show_log(limit: usize, output: OutputFormat) -> Result<()> {
+    let dir = config_repository()?;
+    let commits: Vec<CommitView> = git::log(&dir, limit)?
+        .iter()
+        .map(CommitView::from)
+        .collect();
+    let rows: Vec<Vec<String>> = commits
+        .iter()
+        .map(|commit| {
+            vec![
+                commit.hash.clone(),
+                commit
+                    .timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string(),
+                commit.message.clone(),
+            ]
+        })
+        .collect();
+
+    match output {
+        OutputFormat::Plain => {
+            for row in &rows {
+                println!("{}", row.join("  "));
+            }
+        }
+        OutputFormat::Table => print!("{}", output::table(&["HASH", "TIME", "MESSAGE"], &rows)),
+        OutputFormat::Json | OutputFormat::Yaml => print!("{}", output.serialize(&commits)?),
+    }
+    Ok(())
+}
+
+/// Restore the environments of a revision, keeping the active environment
+fn checkout_config(store: &dyn ConfigStore, rev: &str, force: bool) -> Result<()> {
+    let dir = config_repository()?;
+    let before = store.load()?;
+
+    let hash = git::restore(&dir, rev)?;
+    if let Err(e) = apply_checkout(store, before, force) {
+        // Leave the configuration as it was before the checkout
+        git::restore(&dir, "HEAD")?;
+        return Err(e);
+    }
+    git::commit_all(&dir, &format!("checkout {hash}"))?;
+
+    println!("Restored configuration from {hash}.");
+    Ok(())
+}
+
+/// Load a restored configuration and update the hosts file if the active
+/// environment changed
+///
+/// Changes to environments that are protected before the checkout are authorized
+/// like the equivalent direct changes.
+fn apply_checkout(store: &dyn ConfigStore, before: Config, force: bool) -> Result<()> {
+    let mut config = store.load()?;
+
+    let mut guarded: Vec<&Environment> = before
+        .environments
+        .values()
+        .filter(|env| env.protected || env.locked)
+        .collect();
+    guarded.sort_by(|a, b| a.name.cmp(&b.name));
+    for env in guarded {
+        authorize_change(&before, &env.name, config.get_environment(&env.name), force)?;
+    }
+    config.current_environment = before.current_environment.clone();
+    config.pending_revert = before.pending_revert.clone();
+    config
+        .target_environments
+        .clone_from(&before.target_environments);
+
+    if let Some(name) = before.current_environment.as_deref()
+        && !journal::same_environment(before.get_environment(name), config.get_environment(name))
+    {
+        if config.get_environment(name).is_some() {
+            activate_environment(&mut config, name, &hosts_manager())?;
+        } else {
+            hosts_manager().clear_environment()?;
+            config.current_environment = None;
+            config.pending_revert = None;
+        }
+    }
+
+    let mut names: Vec<&String> = before.target_environments.values().collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        if !journal::same_environment(before.get_environment(name), config.get_environment(name)) {
+            reapply_in_targets(&mut config, name)?;
+        }
+    }
+
+    store.save(&config)
+}
+
+/// Lay out the entries of an environment as a table
+fn entries_table(env: &Environment) -> String {
+    let rows: Vec<Vec<String>> = env
+        .entries
+        .iter()
+        .map(|entry| {
+            let origin = match (&entry.source, &entry.target) {
+                (Some(source), _) => format!("from {source}"),
+                (None, Some(target)) => format!("alias of {target}"),
+                (None, None) => String::new(),
+            };
+            vec![
+                entry.hostname.clone(),
+                entry.ip.to_string(),
+                origin,
+                entry.comment.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    output::table(&["HOSTNAME", "IP", "ORIGIN", "COMMENT"], &rows)
+}
+
+/// "yes" for true and an empty table cell for false
+fn yes_or_empty(value: bool) -> String {
+    if value { "yes" } else { "" }.to_string()
+}
+
+/// Protection level of an environment as a table cell
+fn protection(protected: bool, locked: bool) -> String {
+    if locked {
+        "locked".to_string()
+    } else if protected {
+        "protected".to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Format an entry for display, noting where dynamic addresses come from
+fn format_entry(entry: &HostEntry) -> String {
+    match (&entry.source, &entry.target) {
+        (Some(source), _) => format!("{}  [from {source}]", entry.to_line()),
+        (None, Some(target)) => format!("{}  [alias of {target}]", entry.to_line()),
+        (None, None) => entry.to_line(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hostctl::storage::MemoryConfigStore;
+    use std::sync::OnceLock;
+
+    /// Keep the files written next to the configuration, like the journal, out of
+    /// the real config directory
+    fn isolate() {
+        static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let dir = tempfile::tempdir().unwrap();
+            Locations {
+                config_file: Some(dir.path().join("hostctl.yaml")),
+                hosts_file: Some(dir.path().join("hosts")),
+                root: None,
+            }
+            .install()
+            .unwrap();
+            dir
+        });
+    }
+
+    #[test]
+    fn test_commands_use_the_given_store() {
+        isolate();
+        let store = MemoryConfigStore::default();
+
+        add_environment(&store, "dev", Some("Development".to_string()), false, false).unwrap();
+        add_entry(&store, "dev", "10.0.0.1", "api.dev", None, false).unwrap();
+        protect_environment(&store, "dev", true).unwrap();
+
+        let config = store.load().unwrap();
+        let dev = config.get_environment("dev").unwrap();
+        assert_eq!(dev.description.as_deref(), Some("Development"));
+        assert_eq!(dev.entries[0].hostname, "api.dev");
+        assert!(dev.locked);
+        assert!(!ConfigStorage::get_config_path().exists());
+
+        // Locked environments are refused even when forced
+        let error = remove_environment(&store, "dev", true).unwrap_err();
+        assert!(error.downcast_ref::<ProtectionError>().is_some());
+        assert!(store.load().unwrap().get_environment("dev").is_some());
+
+        let error = show_environment(&store, "prod", OutputFormat::Plain).unwrap_err();
+        assert!(error.downcast_ref::<NotFoundError>().is_some());
+    }
+}
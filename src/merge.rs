@@ -0,0 +1,520 @@
+use crate::config::{CONFIG_VERSION, Config, Environment, HostEntry};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A change made differently on both sides of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Environment the conflict is in (`None` for top-level settings)
+    pub environment: Option<String>,
+    /// What both sides changed, e.g. a hostname or `description`
+    pub item: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.environment {
+            Some(env) => write!(f, "{} in environment '{env}'", self.item),
+            None => write!(f, "{}", self.item),
+        }
+    }
+}
+
+/// Result of a three-way merge
+///
+/// Conflicting items keep the value of our side.
+#[derive(Debug, Clone)]
+pub struct Merged<T> {
+    /// The merged value
+    pub value: T,
+    /// Items both sides changed differently
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Compare two values by their serialized form
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Three-way merge of a single value
+///
+/// # Returns
+/// Returns the side that changed relative to `base`, or `None` if both sides changed
+/// it differently
+fn merge_value<T: Serialize + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if same(ours, theirs) || same(base, theirs) {
+        Some(ours.clone())
+    } else if same(base, ours) {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+/// Three-way merge of a field of an environment, keeping our value on conflict
+fn merge_field<T: Serialize + Clone>(
+    name: &str,
+    item: &str,
+    [base, ours, theirs]: [&T; 3],
+    conflicts: &mut Vec<Conflict>,
+) -> T {
+    merge_value(base, ours, theirs).unwrap_or_else(|| {
+        conflicts.push(Conflict {
+            environment: Some(name.to_string()),
+            item: item.to_string(),
+        });
+        ours.clone()
+    })
+}
+
+/// Key identifying an entry: its hostname and how many entries with the same
+/// hostname precede it (e.g. an IPv4 and an IPv6 address of one host)
+fn entry_keys(entries: &[HostEntry]) -> Vec<(String, usize)> {
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let count = seen.entry(entry.hostname.as_str()).or_default();
+            *count += 1;
+            (entry.hostname.clone(), *count - 1)
+        })
+        .collect()
+}
+
+/// Three-way merge of the entries of an environment, keyed by hostname
+fn merge_entries(
+    name: &str,
+    base: &[HostEntry],
+    ours: &[HostEntry],
+    theirs: &[HostEntry],
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<HostEntry> {
+    let index = |entries: &[HostEntry]| -> HashMap<(String, usize), HostEntry> {
+        entry_keys(entries)
+            .into_iter()
+            .zip(entries.iter().cloned())
+            .collect()
+    };
+    let (base_entries, our_entries, their_entries) = (index(base), index(ours), index(theirs));
+
+    // Our order first, then entries only they added, in their order
+    let mut keys = entry_keys(ours);
+    keys.extend(
+        entry_keys(theirs)
+            .into_iter()
+            .filter(|key| !our_entries.contains_key(key)),
+    );
+
+    let mut merged = Vec::new();
+    for key in keys {
+        let sides = (
+            base_entries.get(&key),
+            our_entries.get(&key),
+            their_entries.get(&key),
+        );
+        match merge_value(&sides.0, &sides.1, &sides.2) {
+            Some(entry) => merged.extend(entry.cloned()),
+            None => {
+                conflicts.push(Conflict {
+                    environment: Some(name.to_string()),
+                    item: format!("hostname '{}'", key.0),
+                });
+                merged.extend(sides.1.cloned());
+            }
+        }
+    }
+    merged
+}
+
+/// Three-way merge of an environment
+///
+/// Environments changed on both sides are merged field by field and entry by entry;
+/// only an item both sides changed differently is a conflict.
+///
+/// # Arguments
+/// * `name` - Name of the environment, used in conflicts
+/// * `base` - The environment in the common ancestor
+/// * `ours` - The environment on our side
+/// * `theirs` - The environment on their side
+///
+/// # Returns
+/// Returns the merged environment (`None` if it was removed) and any conflicts
+#[must_use]
+pub fn merge_environment(
+    name: &str,
+    base: Option<&Environment>,
+    ours: Option<&Environment>,
+    theirs: Option<&Environment>,
+) -> Merged<Option<Environment>> {
+    let mut conflicts = Vec::new();
+    if let Some(env) = merge_value(&base, &ours, &theirs) {
+        return Merged {
+            value: env.cloned(),
+            conflicts,
+        };
+    }
+
+    let (Some(ours), Some(theirs)) = (ours, theirs) else {
+        // Removed on one side and changed on the other
+        conflicts.push(Conflict {
+            environment: Some(name.to_string()),
+            item: "removed environment".to_string(),
+        });
+        return Merged {
+            value: ours.cloned(),
+            conflicts,
+        };
+    };
+    let empty = Environment::new(name.to_string());
+    let base = base.unwrap_or(&empty);
+
+    let mut merged = ours.clone();
+    merged.description = merge_field(
+        name,
+        "description",
+        [&base.description, &ours.description, &theirs.description],
+        &mut conflicts,
+    );
+    merged.protected = merge_field(
+        name,
+        "protected",
+        [&base.protected, &ours.protected, &theirs.protected],
+        &mut conflicts,
+    );
+    merged.locked = merge_field(
+        name,
+        "locked",
+        [&base.locked, &ours.locked, &theirs.locked],
+        &mut conflicts,
+    );
+    merged.entries = merge_entries(
+        name,
+        &base.entries,
+        &ours.entries,
+        &theirs.entries,
+        &mut conflicts,
+    );
+
+    Merged {
+        value: Some(merged),
+        conflicts,
+    }
+}
+
+/// Three-way merge of configurations
+///
+/// Environments are merged one by one with `merge_environment`; top-level lists
+/// such as policies, targets, sources and trusted keys are merged as a whole. The active
+/// environment and pending timers are local state, so our side wins for them.
+///
+/// # Arguments
+/// * `base` - The common ancestor
+/// * `ours` - Our side
+/// * `theirs` - Their side
+///
+/// # Returns
+/// Returns the merged configuration and any conflicts
+#[must_use]
+pub fn merge_configs(base: &Config, ours: &Config, theirs: &Config) -> Merged<Config> {
+    let mut conflicts = Vec::new();
+    let mut merged = ours.clone();
+    merged.version = CONFIG_VERSION;
+
+    merged.policies =
+        merge_value(&base.policies, &ours.policies, &theirs.policies).unwrap_or_else(|| {
+            conflicts.push(Conflict {
+                environment: None,
+                item: "policies".to_string(),
+            });
+            ours.policies.clone()
+        });
+    merged.targets =
+        merge_value(&base.targets, &ours.targets, &theirs.targets).unwrap_or_else(|| {
+            conflicts.push(Conflict {
+                environment: None,
+                item: "targets".to_string(),
+            });
+            ours.targets.clone()
+        });
+    merged.sources =
+        merge_value(&base.sources, &ours.sources, &theirs.sources).unwrap_or_else(|| {
+            conflicts.push(Conflict {
+                environment: None,
+                item: "sources".to_string(),
+            });
+            ours.sources.clone()
+        });
+    merged.trusted_keys = merge_value(&base.trusted_keys, &ours.trusted_keys, &theirs.trusted_keys)
+        .unwrap_or_else(|| {
+            conflicts.push(Conflict {
+                environment: None,
+                item: "trusted_keys".to_string(),
+            });
+            ours.trusted_keys.clone()
+        });
+
+    let mut names: Vec<&String> = ours
+        .environments
+        .keys()
+        .chain(theirs.environments.keys())
+        .chain(base.environments.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    merged.environments.clear();
+    for name in names {
+        let result = merge_environment(
+            name,
+            base.environments.get(name),
+            ours.environments.get(name),
+            theirs.environments.get(name),
+        );
+        conflicts.extend(result.conflicts);
+        if let Some(env) = result.value {
+            merged.environments.insert(name.clone(), env);
+        }
+    }
+
+    Merged {
+        value: merged,
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::TrustedKey;
+    use crate::config::ConfigLayer;
+    use crate::sources::RemoteSource;
+
+    fn env(name: &str, entries: &[(&str, &str)]) -> Environment {
+        let mut env = Environment::new(name.to_string());
+        for (ip, hostname) in entries {
+            env.add_entry(HostEntry::new(ip.parse().unwrap(), (*hostname).to_string()));
+        }
+        env
+    }
+
+    fn config(envs: &[Environment]) -> Config {
+        let mut config = Config::new();
+        for env in envs {
+            config.add_environment(env.clone());
+        }
+        config
+    }
+
+    fn hosts(env: &Environment) -> Vec<(String, String)> {
+        env.entries
+            .iter()
+            .map(|e| (e.ip.to_string(), e.hostname.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_both_sides_add_entries() {
+        let base = config(&[env("dev", &[("127.0.0.1", "app.local")])]);
+        let ours = config(&[env(
+            "dev",
+            &[("127.0.0.1", "app.local"), ("10.0.0.1", "api.local")],
+        )]);
+        let theirs = config(&[
+            env(
+                "dev",
+                &[("127.0.0.1", "app.local"), ("10.0.0.2", "db.local")],
+            ),
+            env("qa", &[]),
+        ]);
+
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        let dev = merged.value.get_environment("dev").unwrap();
+        assert_eq!(
+            hosts(dev),
+            [
+                ("127.0.0.1".to_string(), "app.local".to_string()),
+                ("10.0.0.1".to_string(), "api.local".to_string()),
+                ("10.0.0.2".to_string(), "db.local".to_string()),
+            ]
+        );
+        assert!(merged.value.get_environment("qa").is_some());
+    }
+
+    #[test]
+    fn test_removal_and_change_of_different_hostnames() {
+        let base = config(&[env(
+            "dev",
+            &[("127.0.0.1", "app.local"), ("10.0.0.1", "api.local")],
+        )]);
+        let ours = config(&[env("dev", &[("10.0.0.1", "api.local")])]);
+        let theirs = config(&[env(
+            "dev",
+            &[("127.0.0.1", "app.local"), ("10.0.0.9", "api.local")],
+        )]);
+
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        let dev = merged.value.get_environment("dev").unwrap();
+        assert_eq!(
+            hosts(dev),
+            [("10.0.0.9".to_string(), "api.local".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_same_hostname_changed_differently_conflicts() {
+        let base = config(&[env("dev", &[("127.0.0.1", "app.local")])]);
+        let ours = config(&[env("dev", &[("10.0.0.1", "app.local")])]);
+        let theirs = config(&[env("dev", &[("10.0.0.2", "app.local")])]);
+
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(
+            merged.conflicts,
+            [Conflict {
+                environment: Some("dev".to_string()),
+                item: "hostname 'app.local'".to_string(),
+            }]
+        );
+        assert_eq!(
+            merged.conflicts[0].to_string(),
+            "hostname 'app.local' in environment 'dev'"
+        );
+        // Our side is kept
+        let dev = merged.value.get_environment("dev").unwrap();
+        assert_eq!(dev.entries[0].ip.to_string(), "10.0.0.1");
+    }
+
+    #[test]
+    fn test_same_change_on_both_sides_is_not_a_conflict() {
+        let base = config(&[env("dev", &[("127.0.0.1", "app.local")])]);
+        let changed = config(&[env("dev", &[("10.0.0.1", "app.local")])]);
+
+        let merged = merge_configs(&base, &changed, &changed);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_removed_environment() {
+        let base = config(&[env("dev", &[("127.0.0.1", "app.local")])]);
+        let ours = config(&[]);
+        let theirs = base.clone();
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        assert!(merged.value.environments.is_empty());
+
+        let theirs = config(&[env("dev", &[("10.0.0.1", "app.local")])]);
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].item, "removed environment");
+    }
+
+    #[test]
+    fn test_environment_fields() {
+        let base = env("dev", &[]);
+        let mut ours = base.clone();
+        ours.description = Some("Ours".to_string());
+        ours.add_entry(HostEntry::new(
+            "10.0.0.1".parse().unwrap(),
+            "api.local".to_string(),
+        ));
+        let mut theirs = base.clone();
+        theirs.protected = true;
+        theirs.description = Some("Theirs".to_string());
+
+        let merged = merge_environment("dev", Some(&base), Some(&ours), Some(&theirs));
+        let value = merged.value.unwrap();
+        assert!(value.protected);
+        assert_eq!(value.description.as_deref(), Some("Ours"));
+        assert_eq!(value.entries.len(), 1);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].item, "description");
+    }
+
+    #[test]
+    fn test_targets() {
+        let mut base = config(&[]);
+        base.targets
+            .insert("devbox".to_string(), "/srv/devbox/etc/hosts".into());
+        let mut ours = base.clone();
+        ours.target_environments
+            .insert("devbox".to_string(), "dev".to_string());
+        let mut theirs = base.clone();
+        theirs
+            .targets
+            .insert("fixture".to_string(), "fixtures/hosts".into());
+        theirs
+            .target_environments
+            .insert("devbox".to_string(), "stage".to_string());
+
+        // Targets are merged, the active environments are our own state
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.value.targets, theirs.targets);
+        assert_eq!(merged.value.target_environments, ours.target_environments);
+
+        ours.targets
+            .insert("devbox".to_string(), "/var/devbox/etc/hosts".into());
+        theirs.targets.remove("devbox");
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].item, "targets");
+    }
+
+    #[test]
+    fn test_sources() {
+        let source = |name: &str, url: &str| RemoteSource {
+            name: name.to_string(),
+            url: url.to_string(),
+            layer: ConfigLayer::User,
+        };
+        let mut base = config(&[]);
+        base.sources
+            .push(source("platform", "https://example.com/a.yaml"));
+        let ours = base.clone();
+        let mut theirs = base.clone();
+        theirs
+            .sources
+            .push(source("qa", "https://example.com/qa.yaml"));
+
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.value.sources.len(), 2);
+        assert_eq!(merged.value.sources[1].name, "qa");
+
+        let mut ours = base.clone();
+        ours.sources[0].url = "https://example.com/b.yaml".to_string();
+        theirs.sources.clear();
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].item, "sources");
+        assert_eq!(merged.value.sources[0].url, "https://example.com/b.yaml");
+    }
+
+    #[test]
+    fn test_trusted_keys() {
+        let trusted = |name: &str, key: &str| TrustedKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            layer: ConfigLayer::User,
+        };
+        let mut base = config(&[]);
+        base.trusted_keys.push(trusted("platform", "AAAA"));
+        let mut ours = base.clone();
+        ours.trusted_keys.clear();
+        let theirs = base.clone();
+
+        // A key removed on one side stays removed
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        assert!(merged.value.trusted_keys.is_empty());
+
+        let mut theirs = base.clone();
+        theirs.trusted_keys[0].key = "BBBB".to_string();
+        let merged = merge_configs(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].item, "trusted_keys");
+        assert!(merged.value.trusted_keys.is_empty());
+    }
+}
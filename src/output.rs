@@ -0,0 +1,618 @@
+use crate::config::{Config, ConfigLayer, Environment, HostEntry};
+use crate::git::Commit;
+use crate::journal::Journal;
+use crate::sources::{CachedSource, RemoteSource};
+use crate::storage::ConfigSource;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_yaml_ok as serde_yaml;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How read commands print their results
+///
+/// `plain` is the human-readable text printed by default. `json` and `yaml` follow
+/// the schema of the views in this module, which only ever gains fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Plain,
+    /// Aligned columns with a header row
+    Table,
+    /// JSON, pretty-printed
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plain => write!(f, "plain"),
+            Self::Table => write!(f, "table"),
+            Self::Json => write!(f, "json"),
+            Self::Yaml => write!(f, "yaml"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "plain" => Ok(Self::Plain),
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => anyhow::bail!("Unknown output format '{s}' (use plain, table, json or yaml)"),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Whether the format is meant for programs rather than people
+    #[must_use]
+    pub fn is_structured(self) -> bool {
+        matches!(self, Self::Json | Self::Yaml)
+    }
+
+    /// Serialize a view in a structured format
+    ///
+    /// # Arguments
+    /// * `value` - The view to serialize
+    ///
+    /// # Returns
+    /// Returns the document, ending with a newline
+    ///
+    /// # Errors
+    /// Returns an error if the format is not structured or serialization fails.
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        match self {
+            Self::Json => Ok(serde_json::to_string_pretty(value)? + "\n"),
+            Self::Yaml => Ok(serde_yaml::to_string(value)?),
+            Self::Plain | Self::Table => {
+                anyhow::bail!("The {self} output format is not structured")
+            }
+        }
+    }
+}
+
+/// An environment as listed by `list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvironmentSummary {
+    /// Full environment name
+    pub name: String,
+    /// Description, if any
+    pub description: Option<String>,
+    /// Layer the environment was loaded from
+    pub layer: ConfigLayer,
+    /// Whether the environment is active in the system hosts file
+    pub active: bool,
+    /// Whether switching to or modifying the environment requires confirmation
+    pub protected: bool,
+    /// Whether the environment rejects all modifications
+    pub locked: bool,
+    /// Number of hosts entries
+    pub entry_count: usize,
+}
+
+impl EnvironmentSummary {
+    /// Summaries of all environments of a configuration, sorted by name
+    ///
+    /// # Arguments
+    /// * `config` - The loaded configuration
+    #[must_use]
+    pub fn all(config: &Config) -> Vec<Self> {
+        let mut summaries: Vec<Self> = config
+            .environments
+            .values()
+            .map(|env| Self {
+                name: env.name.clone(),
+                description: env.description.clone(),
+                layer: env.layer,
+                active: config.current_environment.as_ref() == Some(&env.name),
+                protected: env.protected,
+                locked: env.locked,
+                entry_count: env.entries.len(),
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+}
+
+/// A hosts entry as shown by `show` and `current`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EntryView {
+    /// Hostname
+    pub hostname: String,
+    /// IP address; for dynamic entries the most recently resolved one
+    pub ip: String,
+    /// Source specification of a dynamic address, e.g. `env:API_IP`
+    pub source: Option<String>,
+    /// Hostname this entry is an alias of
+    pub target: Option<String>,
+    /// Comment, if any
+    pub comment: Option<String>,
+}
+
+impl From<&HostEntry> for EntryView {
+    fn from(entry: &HostEntry) -> Self {
+        Self {
+            hostname: entry.hostname.clone(),
+            ip: entry.ip.to_string(),
+            source: entry.source.as_ref().map(ToString::to_string),
+            target: entry.target.clone(),
+            comment: entry.comment.clone(),
+        }
+    }
+}
+
+/// An environment with its entries, as shown by `show` and `current`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvironmentView {
+    /// Full environment name
+    pub name: String,
+    /// Description, if any
+    pub description: Option<String>,
+    /// Layer the environment was loaded from
+    pub layer: ConfigLayer,
+    /// Whether the environment is active in the system hosts file
+    pub active: bool,
+    /// Whether switching to or modifying the environment requires confirmation
+    pub protected: bool,
+    /// Whether the environment rejects all modifications
+    pub locked: bool,
+    /// Number of hosts entries
+    pub entry_count: usize,
+    /// Hosts entries, in file order
+    pub entries: Vec<EntryView>,
+}
+
+impl EnvironmentView {
+    /// View of an environment of a configuration
+    ///
+    /// # Arguments
+    /// * `config` - The loaded configuration
+    /// * `env` - The environment
+    #[must_use]
+    pub fn new(config: &Config, env: &Environment) -> Self {
+        Self {
+            name: env.name.clone(),
+            description: env.description.clone(),
+            layer: env.layer,
+            active: config.current_environment.as_ref() == Some(&env.name),
+            protected: env.protected,
+            locked: env.locked,
+            entry_count: env.entries.len(),
+            entries: env.entries.iter().map(EntryView::from).collect(),
+        }
+    }
+}
+
+/// A pending timed revert, as shown by `status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingRevertView {
+    /// Environment restored at the deadline (`None` clears the managed entries)
+    pub previous_environment: Option<String>,
+    /// When the revert happens
+    pub deadline: DateTime<Utc>,
+}
+
+/// A configured target, as shown by `status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetView {
+    /// Target name
+    pub name: String,
+    /// Hosts file of the target
+    pub path: PathBuf,
+    /// Environment active in the target
+    pub active_environment: Option<String>,
+}
+
+/// Active environments and timers, as shown by `status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatusView {
+    /// Environment active in the system hosts file
+    pub active_environment: Option<String>,
+    /// Pending timed revert, if any
+    pub pending_revert: Option<PendingRevertView>,
+    /// Configured targets, sorted by name
+    pub targets: Vec<TargetView>,
+}
+
+impl StatusView {
+    /// Status of a configuration
+    ///
+    /// # Arguments
+    /// * `config` - The loaded configuration
+    /// * `target_path` - Resolves the configured path of a target to its hosts file
+    #[must_use]
+    pub fn new(config: &Config, target_path: impl Fn(&std::path::Path) -> PathBuf) -> Self {
+        Self {
+            active_environment: config.current_environment.clone(),
+            pending_revert: config
+                .pending_revert
+                .as_ref()
+                .map(|pending| PendingRevertView {
+                    previous_environment: pending.previous_environment.clone(),
+                    deadline: pending.deadline,
+                }),
+            targets: config
+                .targets
+                .iter()
+                .map(|(name, path)| TargetView {
+                    name: name.clone(),
+                    path: target_path(path),
+                    active_environment: config.target_environments.get(name).cloned(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A recorded change, as shown by `history`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryView {
+    /// 1-based position in the journal
+    pub number: usize,
+    /// When the change was made
+    pub timestamp: DateTime<Utc>,
+    /// Command line that made the change
+    pub command: String,
+    /// Full name of the changed environment
+    pub environment: String,
+    /// Whether the change was undone
+    pub undone: bool,
+}
+
+impl HistoryView {
+    /// The most recent changes of a journal, newest first
+    ///
+    /// # Arguments
+    /// * `journal` - The undo journal
+    /// * `limit` - Maximum number of changes
+    #[must_use]
+    pub fn recent(journal: &Journal, limit: usize) -> Vec<Self> {
+        journal
+            .records
+            .iter()
+            .enumerate()
+            .rev()
+            .take(limit)
+            .map(|(index, record)| Self {
+                number: index + 1,
+                timestamp: record.timestamp,
+                command: record.command.clone(),
+                environment: record.environment.clone(),
+                undone: index >= journal.cursor,
+            })
+            .collect()
+    }
+}
+
+/// A commit of the configuration repository, as shown by `log`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommitView {
+    /// Abbreviated commit hash
+    pub hash: String,
+    /// When the commit was made
+    pub timestamp: DateTime<Utc>,
+    /// First line of the commit message, usually the command that made the change
+    pub message: String,
+}
+
+impl From<&Commit> for CommitView {
+    fn from(commit: &Commit) -> Self {
+        Self {
+            hash: commit.hash.clone(),
+            timestamp: commit.timestamp,
+            message: commit.message.clone(),
+        }
+    }
+}
+
+/// A remote source, as shown by `sources list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceView {
+    /// Source name, the namespace of its environments
+    pub name: String,
+    /// URL of the remote file
+    pub url: String,
+    /// Layer the source is configured in
+    pub layer: ConfigLayer,
+    /// When the cached copy was last fetched or confirmed unchanged (`None` if never)
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl SourceView {
+    /// View of a remote source
+    ///
+    /// # Arguments
+    /// * `source` - The configured source
+    /// * `cached` - Its last good copy, if any
+    #[must_use]
+    pub fn new(source: &RemoteSource, cached: Option<&CachedSource>) -> Self {
+        Self {
+            name: source.name.clone(),
+            url: source.url.clone(),
+            layer: source.layer,
+            fetched_at: cached.map(|cached| cached.fetched_at),
+        }
+    }
+}
+
+/// An environment defined in a configuration file, as shown by `config sources`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerEnvironmentView {
+    /// Full environment name
+    pub name: String,
+    /// Higher layer defining an environment of the same name, if any
+    pub hidden_by: Option<ConfigLayer>,
+}
+
+/// A configuration file, as shown by `config sources`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigFileView {
+    /// Layer the file belongs to
+    pub layer: ConfigLayer,
+    /// Path of the file
+    pub path: PathBuf,
+    /// Whether the file exists
+    pub exists: bool,
+    /// Environments defined in the file
+    pub environments: Vec<LayerEnvironmentView>,
+}
+
+impl ConfigFileView {
+    /// Views of configuration files, noting which environments higher layers hide
+    ///
+    /// # Arguments
+    /// * `sources` - The configuration files, lowest precedence first
+    #[must_use]
+    pub fn all(sources: &[ConfigSource]) -> Vec<Self> {
+        sources
+            .iter()
+            .map(|source| Self {
+                layer: source.layer,
+                path: source.path.clone(),
+                exists: source.exists,
+                environments: source
+                    .environments
+                    .iter()
+                    .map(|name| LayerEnvironmentView {
+                        name: name.clone(),
+                        hidden_by: sources
+                            .iter()
+                            .filter(|other| other.layer > source.layer)
+                            .find(|other| other.environments.contains(name))
+                            .map(|other| other.layer),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Lay out rows in columns separated by two spaces, below a header row
+///
+/// # Arguments
+/// * `headers` - Column headers
+/// * `rows` - Cells of each row; missing cells are left empty
+///
+/// # Returns
+/// Returns the table, each line ending with a newline and without trailing spaces
+#[must_use]
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = headers.iter().map(ToString::to_string).collect();
+    let mut output = String::new();
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::new();
+        for (index, width) in widths.iter().enumerate() {
+            let cell = row.get(index).map_or("", String::as_str);
+            if index > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(cell);
+            line.extend(std::iter::repeat_n(
+                ' ',
+                width.saturating_sub(cell.chars().count()),
+            ));
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{IpSource, PendingRevert};
+    use std::path::Path;
+
+    fn config() -> Config {
+        let mut config = Config::new();
+        let mut dev =
+            Environment::new("dev".to_string()).with_description("Development".to_string());
+        dev.add_entry(HostEntry::new(
+            "10.0.0.1".parse().unwrap(),
+            "api.local".to_string(),
+        ));
+        dev.add_entry(HostEntry::from_source(
+            IpSource::Env {
+                name: "WEB_IP".to_string(),
+            },
+            "web.local".to_string(),
+        ));
+        dev.protected = true;
+        config.add_environment(dev);
+        config.add_environment(Environment::new("stage".to_string()));
+        config.current_environment = Some("dev".to_string());
+        config
+    }
+
+    #[test]
+    fn test_output_format_from_str() {
+        for format in [
+            OutputFormat::Plain,
+            OutputFormat::Table,
+            OutputFormat::Json,
+            OutputFormat::Yaml,
+        ] {
+            assert_eq!(format.to_string().parse::<OutputFormat>().unwrap(), format);
+        }
+        assert_eq!("YML".parse::<OutputFormat>().unwrap(), OutputFormat::Yaml);
+        assert!("xml".parse::<OutputFormat>().is_err());
+        assert!(OutputFormat::Table.serialize(&1).is_err());
+    }
+
+    #[test]
+    fn test_environment_summary_schema() {
+        let summaries = EnvironmentSummary::all(&config());
+        let json = serde_json::to_value(&summaries).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "name": "dev",
+                    "description": "Development",
+                    "layer": "user",
+                    "active": true,
+                    "protected": true,
+                    "locked": false,
+                    "entry_count": 2
+                },
+                {
+                    "name": "stage",
+                    "description": null,
+                    "layer": "user",
+                    "active": false,
+                    "protected": false,
+                    "locked": false,
+                    "entry_count": 0
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_environment_view_schema() {
+        let config = config();
+        let view = EnvironmentView::new(&config, config.get_environment("dev").unwrap());
+        let json = serde_json::to_value(&view).unwrap();
+        assert_eq!(json["active"], true);
+        assert_eq!(json["entry_count"], 2);
+        assert_eq!(
+            json["entries"][0],
+            serde_json::json!({
+                "hostname": "api.local",
+                "ip": "10.0.0.1",
+                "source": null,
+                "target": null,
+                "comment": null
+            })
+        );
+        assert_eq!(json["entries"][1]["source"], "env:WEB_IP");
+    }
+
+    #[test]
+    fn test_status_view() {
+        let mut config = config();
+        let deadline = "2026-01-01T12:00:00Z".parse().unwrap();
+        config.pending_revert = Some(PendingRevert::new(None, deadline));
+        config
+            .targets
+            .insert("devbox".to_string(), "/srv/devbox/etc/hosts".into());
+
+        let status = StatusView::new(&config, Path::to_path_buf);
+        let yaml = OutputFormat::Yaml.serialize(&status).unwrap();
+        assert_eq!(
+            yaml,
+            "active_environment: dev
+pending_revert:
+  previous_environment: null
+  deadline: 2026-01-01T12:00:00Z
+targets:
+- name: devbox
+  path: /srv/devbox/etc/hosts
+  active_environment: null
+"
+        );
+    }
+
+    #[test]
+    fn test_config_file_view() {
+        let source = |layer, path: &str, exists, environments: &[&str]| ConfigSource {
+            layer,
+            path: path.into(),
+            exists,
+            environments: environments.iter().map(ToString::to_string).collect(),
+        };
+        let sources = [
+            source(
+                ConfigLayer::System,
+                "/etc/hostctl/config.yaml",
+                true,
+                &["dev"],
+            ),
+            source(
+                ConfigLayer::User,
+                "/home/me/.config/hostctl/config.yaml",
+                true,
+                &["dev", "stage"],
+            ),
+            source(
+                ConfigLayer::Project,
+                "/src/webapp/.hostctl.yaml",
+                false,
+                &[],
+            ),
+        ];
+
+        let json = serde_json::to_value(ConfigFileView::all(&sources)).unwrap();
+        assert_eq!(
+            json[0]["environments"],
+            serde_json::json!([{"name": "dev", "hidden_by": "user"}])
+        );
+        assert_eq!(
+            json[1]["environments"],
+            serde_json::json!([
+                {"name": "dev", "hidden_by": null},
+                {"name": "stage", "hidden_by": null}
+            ])
+        );
+        assert_eq!(json[2]["layer"], "project");
+        assert_eq!(json[2]["exists"], false);
+    }
+
+    #[test]
+    fn test_table() {
+        let rows = vec![
+            vec![
+                "dev".to_string(),
+                "2".to_string(),
+                "Development".to_string(),
+            ],
+            vec!["staging".to_string(), "10".to_string()],
+        ];
+        assert_eq!(
+            table(&["NAME", "ENTRIES", "DESCRIPTION"], &rows),
+            "NAME     ENTRIES  DESCRIPTION
+dev      2        Development
+staging  10
+"
+        );
+    }
+}
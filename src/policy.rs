@@ -0,0 +1,384 @@
+use crate::config::{ConfigLayer, Environment, HostEntry};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// How a policy violation is reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The violation is reported but the command proceeds
+    Warning,
+    /// The violation aborts the command
+    #[default]
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A team policy restricting which entries environments may contain
+///
+/// A rule applies to the entries whose environment matches one of `environments`
+/// and whose hostname matches one of `hostnames`; an empty list matches everything.
+/// Patterns are case-insensitive and may use `*` as a wildcard, e.g. `*.corp.example.com`.
+///
+/// # Example
+/// ```yaml
+/// policies:
+///   - name: corp-internal
+///     hostnames: ["*.corp.example.com"]
+///     allow_networks: ["10.0.0.0/8"]
+///   - name: no-public-in-dev
+///     severity: warning
+///     environments: ["dev*"]
+///     deny_public_ips: true
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Rule name shown in violations
+    pub name: String,
+    /// Whether violations are warnings or errors
+    #[serde(default)]
+    pub severity: Severity,
+    /// Environment name patterns the rule applies to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub environments: Vec<String>,
+    /// Hostname patterns the rule applies to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<String>,
+    /// Reject matching hostnames altogether
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deny: bool,
+    /// Networks matching hostnames must map into
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_networks: Vec<IpNet>,
+    /// Reject publicly routable addresses
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deny_public_ips: bool,
+    /// Layer this rule was loaded from (not stored in the file)
+    #[serde(skip)]
+    pub layer: ConfigLayer,
+}
+
+/// An entry that breaks a policy rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Name of the broken rule
+    pub rule: String,
+    /// Severity of the broken rule
+    pub severity: Severity,
+    /// Environment containing the entry
+    pub environment: String,
+    /// Hostname of the entry
+    pub hostname: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}: {} in '{}': {}",
+            self.severity, self.rule, self.hostname, self.environment, self.message
+        )
+    }
+}
+
+/// Match a name against a pattern where `*` matches any sequence of characters
+///
+/// # Arguments
+/// * `pattern` - The pattern, compared case-insensitively
+/// * `name` - The name to test
+#[must_use]
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: the whole name must match
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Check whether an address is publicly routable
+///
+/// Private, loopback, link-local, shared (CGNAT), documentation, multicast and
+/// unspecified addresses are not public.
+#[must_use]
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_ipv4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_ipv4(v4),
+            None => is_public_ipv6(v6),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (64..128).contains(&b);
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = first & 0xfe00 == 0xfc00;
+    let link_local = first & 0xffc0 == 0xfe80;
+    let documentation = first == 0x2001 && ip.segments()[1] == 0x0db8;
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || unique_local
+        || link_local
+        || documentation)
+}
+
+impl PolicyRule {
+    /// Check whether the rule applies to an entry of an environment
+    fn applies_to(&self, environment: &str, hostname: &str) -> bool {
+        let matches_any = |patterns: &[String], name: &str| {
+            patterns.is_empty() || patterns.iter().any(|p| matches_pattern(p, name))
+        };
+        matches_any(&self.environments, environment) && matches_any(&self.hostnames, hostname)
+    }
+
+    /// Check one entry against the rule
+    ///
+    /// Addresses of dynamic entries are only checked once they have been resolved.
+    fn check_entry(&self, environment: &str, entry: &HostEntry, resolved: bool) -> Option<String> {
+        if !self.applies_to(environment, &entry.hostname) {
+            return None;
+        }
+        if self.deny {
+            return Some("hostname is not allowed".to_string());
+        }
+        if entry.is_dynamic() && !resolved {
+            return None;
+        }
+
+        if !self.allow_networks.is_empty()
+            && !self
+                .allow_networks
+                .iter()
+                .any(|net| net.contains(&entry.ip))
+        {
+            let networks: Vec<String> = self
+                .allow_networks
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            return Some(format!(
+                "{} is outside the allowed networks ({})",
+                entry.ip,
+                networks.join(", ")
+            ));
+        }
+        if self.deny_public_ips && is_public_ip(entry.ip) {
+            return Some(format!("{} is a public IP address", entry.ip));
+        }
+        None
+    }
+}
+
+/// Check an environment's entries against policy rules
+///
+/// # Arguments
+/// * `rules` - The policy rules to apply
+/// * `env` - The environment to check
+/// * `resolved` - Whether dynamic entries of `env` carry resolved addresses
+///
+/// # Returns
+/// Returns every violation found, in entry order
+#[must_use]
+pub fn check_environment(
+    rules: &[PolicyRule],
+    env: &Environment,
+    resolved: bool,
+) -> Vec<Violation> {
+    env.entries
+        .iter()
+        .flat_map(|entry| {
+            rules.iter().filter_map(move |rule| {
+                rule.check_entry(&env.name, entry, resolved)
+                    .map(|message| Violation {
+                        rule: rule.name.clone(),
+                        severity: rule.severity,
+                        environment: env.name.clone(),
+                        hostname: entry.hostname.clone(),
+                        message,
+                    })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_yaml_ok as serde_yaml;
+
+    const RULES: &str = r#"
+- name: localhost
+  hostnames: ["localhost", "ip6-localhost"]
+  allow_networks: ["127.0.0.0/8", "::1/128"]
+- name: corp-internal
+  hostnames: ["*.corp.example.com"]
+  allow_networks: ["10.0.0.0/8"]
+- name: no-public-in-dev
+  severity: warning
+  environments: ["dev*"]
+  deny_public_ips: true
+- name: no-shadowing
+  hostnames: ["github.com", "*.github.com"]
+  deny: true
+"#;
+
+    fn rules() -> Vec<PolicyRule> {
+        serde_yaml::from_str(RULES).unwrap()
+    }
+
+    fn env_with(name: &str, entries: &[(&str, &str)]) -> Environment {
+        let mut env = Environment::new(name.to_string());
+        for (ip, hostname) in entries {
+            env.add_entry(HostEntry::new(ip.parse().unwrap(), (*hostname).to_string()));
+        }
+        env
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("localhost", "LOCALHOST"));
+        assert!(!matches_pattern("localhost", "localhost2"));
+        assert!(matches_pattern(
+            "*.corp.example.com",
+            "api.corp.example.com"
+        ));
+        assert!(!matches_pattern("*.corp.example.com", "corp.example.com"));
+        assert!(matches_pattern("dev*", "dev"));
+        assert!(matches_pattern("dev*", "dev-eu"));
+        assert!(matches_pattern("*/dev", "webapp/dev"));
+        assert!(matches_pattern("a*b*c", "aXXbYYc"));
+        assert!(!matches_pattern("a*b*c", "aXXcYYb"));
+        assert!(matches_pattern("*", "anything"));
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "10.1.2.3",
+            "192.168.0.1",
+            "127.0.0.1",
+            "100.64.0.1",
+            "169.254.1.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_clean_environment_has_no_violations() {
+        let env = env_with(
+            "dev",
+            &[
+                ("127.0.0.1", "localhost"),
+                ("10.1.2.3", "api.corp.example.com"),
+                ("192.168.1.10", "app.local"),
+            ],
+        );
+        assert!(check_environment(&rules(), &env, true).is_empty());
+    }
+
+    #[test]
+    fn test_violations() {
+        let env = env_with(
+            "dev",
+            &[
+                ("192.168.1.1", "localhost"),
+                ("8.8.8.8", "api.corp.example.com"),
+                ("10.0.0.1", "github.com"),
+            ],
+        );
+        let violations = check_environment(&rules(), &env, true);
+        let found: Vec<(&str, &str, Severity)> = violations
+            .iter()
+            .map(|v| (v.rule.as_str(), v.hostname.as_str(), v.severity))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("localhost", "localhost", Severity::Error),
+                ("corp-internal", "api.corp.example.com", Severity::Error),
+                (
+                    "no-public-in-dev",
+                    "api.corp.example.com",
+                    Severity::Warning
+                ),
+                ("no-shadowing", "github.com", Severity::Error),
+            ]
+        );
+        assert!(violations[1].message.contains("10.0.0.0/8"));
+    }
+
+    #[test]
+    fn test_environment_scope() {
+        let env = env_with("prod", &[("8.8.8.8", "cdn.example.com")]);
+        assert!(check_environment(&rules(), &env, true).is_empty());
+    }
+
+    #[test]
+    fn test_unresolved_dynamic_entries_skip_address_checks() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::alias(
+            "api.corp.example.com".to_string(),
+            "backend".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "github.com".to_string(),
+            "backend".to_string(),
+        ));
+
+        let violations = check_environment(&rules(), &env, false);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].rule, "no-shadowing");
+    }
+}
@@ -0,0 +1,361 @@
+use crate::config::{Config, ConfigLayer, Environment};
+use crate::yaml_patch;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml_ok as serde_yaml;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of a project-local configuration
+pub const PROJECT_CONFIG_FILE: &str = ".hostctl.yaml";
+
+/// Contents of a project-local `.hostctl.yaml`
+///
+/// Environment names inside the file are unqualified; hostctl exposes them as
+/// `<project>/<environment>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    /// Project name used as the namespace; defaults to the directory name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// Map of the project's environments, with unqualified names as keys
+    #[serde(default, serialize_with = "crate::config::serialize_sorted")]
+    pub environments: HashMap<String, Environment>,
+}
+
+/// A project configuration discovered on disk
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// Namespace of the project's environments
+    pub name: String,
+    /// Path of the `.hostctl.yaml` file
+    pub path: PathBuf,
+    /// Parsed file contents
+    pub config: ProjectConfig,
+}
+
+/// Find the nearest `.hostctl.yaml`, walking up from a directory like git does
+///
+/// # Arguments
+/// * `start` - The directory to start searching from
+///
+/// # Returns
+/// Returns the path of the project file if one is found; otherwise returns `None`
+#[must_use]
+pub fn discover_project_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
+impl Project {
+    /// Discover and load the project configuration for a directory
+    ///
+    /// # Arguments
+    /// * `start` - The directory to start searching from
+    ///
+    /// # Errors
+    /// Returns an error if a project file is found but cannot be read or parsed.
+    pub fn discover(start: &Path) -> Result<Option<Self>> {
+        discover_project_file(start)
+            .map(|path| Self::load(&path))
+            .transpose()
+    }
+
+    /// Discover the project configuration for the current working directory
+    ///
+    /// # Errors
+    /// Returns an error if a project file is found but cannot be read or parsed.
+    pub fn discover_from_cwd() -> Result<Option<Self>> {
+        match std::env::current_dir() {
+            Ok(cwd) => Self::discover(&cwd),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Load a project configuration file
+    ///
+    /// Project environments may only contain static addresses and aliases: project
+    /// files come with checked-out repositories, and dynamic sources would let them
+    /// run commands or read files whenever hostctl runs inside one.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the `.hostctl.yaml` file
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read or parsed, the project name is invalid,
+    /// or an environment uses a dynamic IP source.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read project config: {}", path.display()))?;
+        let mut config: ProjectConfig = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse project config: {}", path.display()))?;
+
+        // Environment names are usually omitted in hand-written project files
+        fill_environment_names(&mut config);
+
+        let name = match &config.project {
+            Some(name) => name.clone(),
+            None => path
+                .parent()
+                .and_then(Path::file_name)
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .context("Cannot derive project name; set 'project' in the project config")?,
+        };
+        if name.is_empty() || name.contains('/') {
+            anyhow::bail!("Invalid project name '{name}' in {}", path.display());
+        }
+        for (key, env) in &config.environments {
+            if let Some(entry) = env.find_dynamic_source() {
+                anyhow::bail!(
+                    "Environment '{key}' of project '{name}' uses a dynamic IP source for '{}', which project files may not do",
+                    entry.hostname
+                );
+            }
+        }
+
+        Ok(Self {
+            name,
+            path: path.to_path_buf(),
+            config,
+        })
+    }
+
+    /// Namespaced name of one of the project's environments
+    ///
+    /// # Arguments
+    /// * `env` - Unqualified environment name
+    #[must_use]
+    pub fn qualified_name(&self, env: &str) -> String {
+        format!("{}/{env}", self.name)
+    }
+
+    /// Add the project's environments to a configuration under their qualified names
+    ///
+    /// # Arguments
+    /// * `config` - The configuration to extend
+    pub fn merge_into(&self, config: &mut Config) {
+        for (key, env) in &self.config.environments {
+            let mut env = env.clone();
+            env.name = self.qualified_name(key);
+            env.layer = ConfigLayer::Project;
+            config.add_environment(env);
+        }
+    }
+
+    /// Write the project environments of a configuration back to the project file
+    ///
+    /// The file is only rewritten if the project's environments actually changed, so
+    /// commands that merely update state leave the (usually version-controlled) file alone.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration holding the project environments under their qualified names
+    ///
+    /// # Returns
+    /// Returns `true` if the file was written
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be serialized or written.
+    pub fn save_changes(&self, config: &Config) -> Result<bool> {
+        let prefix = format!("{}/", self.name);
+        let environments: HashMap<String, Environment> = config
+            .environments
+            .values()
+            .filter(|env| env.layer == ConfigLayer::Project)
+            .filter_map(|env| {
+                let short = env.name.strip_prefix(&prefix)?.to_string();
+                let mut env = env.clone();
+                env.name.clone_from(&short);
+                Some((short, env))
+            })
+            .collect();
+
+        let updated = ProjectConfig {
+            project: self.config.project.clone(),
+            environments,
+        };
+        let unchanged = serde_yaml::to_value(&updated.environments)?
+            == serde_yaml::to_value(&self.config.environments)?;
+        if unchanged {
+            return Ok(false);
+        }
+
+        let content = match self.patch_file(&updated) {
+            Some(content) => content,
+            None => serde_yaml::to_string(&updated)
+                .with_context(|| "Failed to serialize project config")?,
+        };
+        fs::write(&self.path, content)
+            .with_context(|| format!("Failed to write project config: {}", self.path.display()))?;
+
+        Ok(true)
+    }
+
+    /// Edit the project file in place so it represents `updated`
+    ///
+    /// # Returns
+    /// Returns the new file contents, or `None` if the file cannot be patched and must
+    /// be serialized from scratch
+    fn patch_file(&self, updated: &ProjectConfig) -> Option<String> {
+        let text = fs::read_to_string(&self.path).ok()?;
+        let old = serde_yaml::to_value(&self.config).ok()?;
+        let new = serde_yaml::to_value(updated).ok()?;
+        let patched = yaml_patch::patch_document(&text, &old, &new)?;
+
+        // Only keep the patch if it reads back as exactly the updated configuration
+        let mut reread: ProjectConfig = serde_yaml::from_str(&patched).ok()?;
+        fill_environment_names(&mut reread);
+        (serde_yaml::to_value(&reread).ok()? == new).then_some(patched)
+    }
+}
+
+/// Use the map keys as names of environments that omit them
+fn fill_environment_names(config: &mut ProjectConfig) {
+    for (key, env) in &mut config.environments {
+        if env.name.is_empty() {
+            env.name.clone_from(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostEntry;
+    use std::net::{IpAddr, Ipv4Addr};
+    use tempfile::tempdir;
+
+    const PROJECT_YAML: &str = r"environments:
+  dev:
+    entries:
+      - ip: 127.0.0.1
+        hostname: api.webapp.local
+  e2e:
+    description: End-to-end tests
+    entries: []
+";
+
+    #[test]
+    fn test_discover_walks_up() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("webapp");
+        let nested = repo.join("src").join("components");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(repo.join(PROJECT_CONFIG_FILE), PROJECT_YAML).unwrap();
+
+        assert_eq!(
+            discover_project_file(&nested),
+            Some(repo.join(PROJECT_CONFIG_FILE))
+        );
+
+        let project = Project::discover(&nested).unwrap().unwrap();
+        assert_eq!(project.name, "webapp");
+        assert_eq!(project.config.environments.len(), 2);
+    }
+
+    #[test]
+    fn test_discover_none() {
+        let dir = tempdir().unwrap();
+        // The temp dir's ancestors are not expected to contain a project file
+        assert!(discover_project_file(dir.path()).is_none());
+    }
+
+    #[test]
+    fn test_explicit_project_name() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&path, format!("project: shop\n{PROJECT_YAML}")).unwrap();
+
+        let project = Project::load(&path).unwrap();
+        assert_eq!(project.name, "shop");
+        assert_eq!(project.qualified_name("dev"), "shop/dev");
+
+        fs::write(&path, "project: a/b\nenvironments: {}\n").unwrap();
+        assert!(Project::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_sources_are_rejected() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &path,
+            r"project: shop
+environments:
+  dev:
+    entries:
+      - hostname: api.webapp.local
+        source:
+          type: command
+          command: touch /tmp/pwned
+",
+        )
+        .unwrap();
+
+        let err = Project::load(&path).unwrap_err().to_string();
+        assert!(err.contains("dynamic IP source for 'api.webapp.local'"));
+        assert!(err.contains("project 'shop'"));
+    }
+
+    #[test]
+    fn test_merge_into_namespaces_environments() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&path, format!("project: shop\n{PROJECT_YAML}")).unwrap();
+        let project = Project::load(&path).unwrap();
+
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        project.merge_into(&mut config);
+
+        assert_eq!(config.environments.len(), 3);
+        let dev = config.get_environment("shop/dev").unwrap();
+        assert_eq!(dev.name, "shop/dev");
+        assert_eq!(dev.layer, ConfigLayer::Project);
+        assert_eq!(dev.entries.len(), 1);
+        assert_eq!(
+            config.get_environment("dev").unwrap().layer,
+            ConfigLayer::User
+        );
+    }
+
+    #[test]
+    fn test_save_changes_only_when_modified() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(PROJECT_CONFIG_FILE);
+        let original = format!("project: shop\n{PROJECT_YAML}");
+        fs::write(&path, &original).unwrap();
+        let project = Project::load(&path).unwrap();
+
+        let mut config = Config::new();
+        project.merge_into(&mut config);
+        config.current_environment = Some("shop/dev".to_string());
+
+        // State changes do not touch the project file
+        assert!(!project.save_changes(&config).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        config
+            .get_environment_mut("shop/e2e")
+            .unwrap()
+            .add_entry(HostEntry::new(
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)),
+                "ci.webapp.local".to_string(),
+            ));
+        assert!(project.save_changes(&config).unwrap());
+
+        // Hand-written parts of the file are kept
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("project: shop\nenvironments:\n  dev:\n    entries:\n"));
+        assert!(saved.contains("    description: End-to-end tests\n"));
+        assert!(!saved.contains(" name:"));
+
+        let reloaded = Project::load(&path).unwrap();
+        assert_eq!(reloaded.name, "shop");
+        let e2e = &reloaded.config.environments["e2e"];
+        assert_eq!(e2e.name, "e2e");
+        assert_eq!(e2e.entries.len(), 1);
+    }
+}
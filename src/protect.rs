@@ -0,0 +1,275 @@
+use crate::config::Environment;
+use std::fmt;
+
+/// An operation on an environment that may be guarded by protection settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Write the environment to the hosts file
+    Switch,
+    /// Delete the environment
+    Remove,
+    /// Add a hosts entry to the environment
+    AddEntry,
+    /// Remove a hosts entry from the environment
+    RemoveEntry,
+    /// Clear the environment's protection settings
+    Unprotect,
+}
+
+impl Action {
+    /// Check whether the action changes the stored environment
+    ///
+    /// Locked environments reject these actions. Unprotecting is how a lock is
+    /// lifted, so it is not considered a modification.
+    #[must_use]
+    pub fn is_modification(self) -> bool {
+        matches!(self, Self::Remove | Self::AddEntry | Self::RemoveEntry)
+    }
+
+    /// The action that replacing an environment with another state amounts to
+    ///
+    /// Used by commands that restore recorded states (undo, redo, checkout), so they
+    /// are guarded like the commands making the same change directly.
+    ///
+    /// # Arguments
+    /// * `current` - The environment as it is now
+    /// * `new` - The state about to replace it (`None` removes the environment)
+    ///
+    /// # Returns
+    /// Returns `None` if the change only adds protection or changes nothing guarded
+    #[must_use]
+    pub fn for_change(current: &Environment, new: Option<&Environment>) -> Option<Self> {
+        let Some(new) = new else {
+            return Some(Self::Remove);
+        };
+
+        // Everything but the protection settings is content of the environment
+        let content = |env: &Environment| {
+            let mut env = env.definition();
+            env.protected = false;
+            env.locked = false;
+            serde_json::to_value(env).ok()
+        };
+        if content(current) != content(new) {
+            let removes_entry = current
+                .entries
+                .iter()
+                .any(|entry| new.find_entry(&entry.hostname).is_none());
+            return Some(if removes_entry {
+                Self::RemoveEntry
+            } else {
+                Self::AddEntry
+            });
+        }
+
+        let lowers_protection =
+            (current.protected && !new.protected) || (current.locked && !new.locked);
+        lowers_protection.then_some(Self::Unprotect)
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Switch => write!(f, "switch to"),
+            Self::Remove => write!(f, "remove"),
+            Self::AddEntry => write!(f, "add an entry to"),
+            Self::RemoveEntry => write!(f, "remove an entry from"),
+            Self::Unprotect => write!(f, "unprotect"),
+        }
+    }
+}
+
+/// Error returned when an environment's protection prevents an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtectionError {
+    /// The environment is locked against modifications
+    Locked { environment: String },
+    /// The environment is protected and no confirmation could be asked for
+    ConfirmationRequired { environment: String, action: Action },
+    /// The user declined the confirmation prompt
+    Declined { environment: String, action: Action },
+}
+
+impl fmt::Display for ProtectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Locked { environment } => write!(
+                f,
+                "Environment '{environment}' is locked and cannot be modified. Run 'hostctl unprotect {environment}' first."
+            ),
+            Self::ConfirmationRequired {
+                environment,
+                action,
+            } => write!(
+                f,
+                "Environment '{environment}' is protected; refusing to {action} it without confirmation. Use --force to proceed."
+            ),
+            Self::Declined {
+                environment,
+                action,
+            } => write!(f, "Did not {action} protected environment '{environment}'."),
+        }
+    }
+}
+
+impl std::error::Error for ProtectionError {}
+
+/// Check whether an action may be performed on an environment
+///
+/// Locked environments reject modifications even with `force`. Other actions on
+/// protected environments need `force` or a positive answer from `confirm`; when no
+/// confirmation prompt is available (non-interactive runs) the check fails closed.
+///
+/// # Arguments
+/// * `env` - The environment the action targets
+/// * `action` - The action about to be performed
+/// * `force` - Whether the user passed `--force`
+/// * `confirm` - Prompt asking the user to confirm, or `None` if not interactive
+///
+/// # Errors
+/// Returns a `ProtectionError` describing why the action is not allowed.
+pub fn authorize<F>(
+    env: &Environment,
+    action: Action,
+    force: bool,
+    confirm: Option<F>,
+) -> Result<(), ProtectionError>
+where
+    F: FnOnce(&str) -> bool,
+{
+    if env.locked && action.is_modification() {
+        return Err(ProtectionError::Locked {
+            environment: env.name.clone(),
+        });
+    }
+
+    if !(env.protected || env.locked) || force {
+        return Ok(());
+    }
+
+    let Some(confirm) = confirm else {
+        return Err(ProtectionError::ConfirmationRequired {
+            environment: env.name.clone(),
+            action,
+        });
+    };
+
+    let prompt = format!(
+        "Environment '{}' is protected. Really {action} it? [y/N] ",
+        env.name
+    );
+    if confirm(&prompt) {
+        Ok(())
+    } else {
+        Err(ProtectionError::Declined {
+            environment: env.name.clone(),
+            action,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protected_env() -> Environment {
+        let mut env = Environment::new("prod-override".to_string());
+        env.protected = true;
+        env
+    }
+
+    fn no_prompt() -> Option<fn(&str) -> bool> {
+        None
+    }
+
+    #[test]
+    fn test_unprotected_environment_is_allowed() {
+        let env = Environment::new("dev".to_string());
+        assert!(authorize(&env, Action::Switch, false, no_prompt()).is_ok());
+        assert!(authorize(&env, Action::Remove, false, no_prompt()).is_ok());
+    }
+
+    #[test]
+    fn test_protected_fails_closed_without_prompt() {
+        let env = protected_env();
+        assert_eq!(
+            authorize(&env, Action::Switch, false, no_prompt()),
+            Err(ProtectionError::ConfirmationRequired {
+                environment: "prod-override".to_string(),
+                action: Action::Switch,
+            })
+        );
+        assert!(authorize(&env, Action::Switch, true, no_prompt()).is_ok());
+    }
+
+    #[test]
+    fn test_protected_asks_for_confirmation() {
+        let env = protected_env();
+        assert!(authorize(&env, Action::AddEntry, false, Some(|_: &str| true)).is_ok());
+        assert!(matches!(
+            authorize(&env, Action::AddEntry, false, Some(|_: &str| false)),
+            Err(ProtectionError::Declined { .. })
+        ));
+    }
+
+    #[test]
+    fn test_locked_rejects_modifications_even_with_force() {
+        let mut env = Environment::new("prod".to_string());
+        env.locked = true;
+
+        for action in [Action::Remove, Action::AddEntry, Action::RemoveEntry] {
+            assert!(matches!(
+                authorize(&env, action, true, no_prompt()),
+                Err(ProtectionError::Locked { .. })
+            ));
+        }
+        // Locked environments can still be switched to and unlocked with confirmation
+        assert!(authorize(&env, Action::Switch, true, no_prompt()).is_ok());
+        assert!(authorize(&env, Action::Unprotect, false, Some(|_: &str| true)).is_ok());
+    }
+
+    #[test]
+    fn test_action_for_change() {
+        use crate::config::HostEntry;
+
+        let mut env = protected_env();
+        env.add_entry(HostEntry::new(
+            "10.0.0.1".parse().unwrap(),
+            "api.local".to_string(),
+        ));
+        assert_eq!(Action::for_change(&env, None), Some(Action::Remove));
+        assert_eq!(Action::for_change(&env, Some(&env)), None);
+
+        let mut unprotected = env.clone();
+        unprotected.protected = false;
+        assert_eq!(
+            Action::for_change(&env, Some(&unprotected)),
+            Some(Action::Unprotect)
+        );
+        // Adding protection is never guarded
+        assert_eq!(Action::for_change(&unprotected, Some(&env)), None);
+
+        let mut extended = env.clone();
+        extended.add_entry(HostEntry::new(
+            "10.0.0.2".parse().unwrap(),
+            "web.local".to_string(),
+        ));
+        assert_eq!(
+            Action::for_change(&env, Some(&extended)),
+            Some(Action::AddEntry)
+        );
+        assert_eq!(
+            Action::for_change(&extended, Some(&env)),
+            Some(Action::RemoveEntry)
+        );
+
+        // Content changes take precedence, so a lock cannot be lifted along with them
+        let mut locked = extended.clone();
+        locked.locked = true;
+        assert_eq!(
+            Action::for_change(&locked, Some(&env)),
+            Some(Action::RemoveEntry)
+        );
+    }
+}
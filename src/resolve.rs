@@ -0,0 +1,485 @@
+use crate::config::{Environment, HostEntry, IpSource};
+use crate::hosts::HostsManager;
+use anyhow::{Context, Result};
+use std::fs;
+use std::net::IpAddr;
+use std::process::Command;
+
+/// Validate an environment before it is applied
+///
+/// Checks that every hostname is valid and that alias entries form no cycles and
+/// point to hostnames defined in the same environment.
+///
+/// # Arguments
+/// * `env` - The environment to validate
+///
+/// # Errors
+/// Returns an error describing the first problem found.
+pub fn validate_environment(env: &Environment) -> Result<()> {
+    for entry in &env.entries {
+        if !HostsManager::is_valid_hostname(&entry.hostname) {
+            anyhow::bail!(
+                "Invalid hostname in environment '{}': {}",
+                env.name,
+                entry.hostname
+            );
+        }
+
+        if let Some(target) = &entry.target {
+            if entry.source.is_some() {
+                anyhow::bail!(
+                    "Entry '{}' in environment '{}' has both a source and a target",
+                    entry.hostname,
+                    env.name
+                );
+            }
+            if !HostsManager::is_valid_hostname(target) {
+                anyhow::bail!(
+                    "Invalid alias target in environment '{}': {} -> {target}",
+                    env.name,
+                    entry.hostname
+                );
+            }
+            alias_chain_end(env, entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve every dynamic entry of an environment to a concrete IP address
+///
+/// Returns a copy of the environment in which each entry's `ip` holds the address
+/// produced by its source, and each alias entry holds the address of the hostname it
+/// points to. Entries with a literal IP are returned unchanged.
+///
+/// # Arguments
+/// * `env` - The environment to resolve
+///
+/// # Errors
+/// Returns an error if the environment fails [`validate_environment`], or naming the
+/// entry and its source if any source fails to produce an IP address.
+pub fn resolve_environment(env: &Environment) -> Result<Environment> {
+    validate_environment(env)?;
+
+    let mut resolved = env.clone();
+
+    for entry in &mut resolved.entries {
+        if let Some(source) = &entry.source {
+            entry.ip = resolve_source(source).with_context(|| {
+                format!(
+                    "Failed to resolve IP for '{}' in environment '{}' (source: {source})",
+                    entry.hostname, env.name
+                )
+            })?;
+        }
+    }
+
+    // Aliases follow the addresses resolved above
+    let alias_ips = resolved
+        .entries
+        .iter()
+        .map(|entry| match entry.target {
+            Some(_) => alias_chain_end(&resolved, entry).map(|end| Some(end.ip)),
+            None => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (entry, ip) in resolved.entries.iter_mut().zip(alias_ips) {
+        if let Some(ip) = ip {
+            entry.ip = ip;
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Follow an alias entry to the entry that carries the actual address
+///
+/// # Errors
+/// Returns an error if the chain reaches a hostname that is not defined in the
+/// environment, or if it loops back onto itself.
+fn alias_chain_end<'a>(env: &'a Environment, start: &'a HostEntry) -> Result<&'a HostEntry> {
+    let mut chain = vec![start.hostname.as_str()];
+    let mut current = start;
+
+    while let Some(target) = &current.target {
+        let Some(next) = env.find_entry(target) else {
+            anyhow::bail!(
+                "Alias '{}' in environment '{}' points to '{target}', which is not defined in that environment",
+                current.hostname,
+                env.name
+            );
+        };
+
+        if chain.contains(&next.hostname.as_str()) {
+            chain.push(&next.hostname);
+            anyhow::bail!(
+                "Alias cycle in environment '{}': {}",
+                env.name,
+                chain.join(" -> ")
+            );
+        }
+
+        chain.push(&next.hostname);
+        current = next;
+    }
+
+    Ok(current)
+}
+
+/// Evaluate a single IP source
+///
+/// # Arguments
+/// * `source` - The source to evaluate
+///
+/// # Errors
+/// Returns an error if the source cannot be read or does not yield a valid IP address.
+pub fn resolve_source(source: &IpSource) -> Result<IpAddr> {
+    match source {
+        IpSource::Literal { ip } => Ok(*ip),
+        IpSource::Env { name } => {
+            let value = std::env::var(name)
+                .with_context(|| format!("Environment variable '{name}' is not set"))?;
+            parse_ip(&value).with_context(|| format!("Environment variable '{name}'"))
+        }
+        IpSource::Command { command } => {
+            let output = run_command(command)?;
+            let line = first_line(&output)
+                .with_context(|| format!("Command '{command}' printed nothing"))?;
+            parse_ip(line).with_context(|| format!("Output of command '{command}'"))
+        }
+        IpSource::File { path, line } => {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            let value = match line {
+                Some(n) => content
+                    .lines()
+                    .nth(n.saturating_sub(1))
+                    .with_context(|| format!("File {} has no line {n}", path.display()))?,
+                None => first_line(&content)
+                    .with_context(|| format!("File {} contains no address", path.display()))?,
+            };
+            parse_ip(value).with_context(|| format!("File {}", path.display()))
+        }
+        IpSource::Interface { name } => interface_ipv4(name),
+    }
+}
+
+/// Parse an IP address from a line, accepting trailing text after the address
+fn parse_ip(value: &str) -> Result<IpAddr> {
+    let value = value.trim();
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.split_whitespace().next()?.parse().ok())
+        .with_context(|| format!("'{value}' is not a valid IP address"))
+}
+
+/// First non-empty line that is not a `#` comment
+fn first_line(text: &str) -> Option<&str> {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Run a command through the platform shell and return its standard output
+fn run_command(command: &str) -> Result<String> {
+    #[cfg(target_os = "windows")]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(target_os = "windows"))]
+    let output = Command::new("sh").args(["-c", command]).output();
+
+    let output = output.with_context(|| format!("Failed to run command '{command}'"))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Command '{command}' failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Look up the first `IPv4` address assigned to a network interface
+#[cfg(unix)]
+fn interface_ipv4(name: &str) -> Result<IpAddr> {
+    use std::ffi::CStr;
+    use std::net::Ipv4Addr;
+
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: on success getifaddrs stores a list that is released with freeifaddrs below
+    if unsafe { libc::getifaddrs(&raw mut addrs) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to list network interfaces");
+    }
+
+    let mut found = false;
+    let mut address = None;
+    let mut cursor = addrs;
+    while !cursor.is_null() {
+        // SAFETY: cursor is a non-null node of the list returned by getifaddrs
+        let ifa = unsafe { &*cursor };
+        cursor = ifa.ifa_next;
+
+        if ifa.ifa_name.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_name is a NUL-terminated string owned by the list
+        if unsafe { CStr::from_ptr(ifa.ifa_name) }.to_bytes() != name.as_bytes() {
+            continue;
+        }
+        found = true;
+
+        if ifa.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_addr is non-null and points to a sockaddr owned by the list; the
+        // family check guarantees it is a sockaddr_in
+        unsafe {
+            if i32::from((*ifa.ifa_addr).sa_family) == libc::AF_INET {
+                let sin = &*ifa.ifa_addr.cast::<libc::sockaddr_in>();
+                address = Some(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)));
+                break;
+            }
+        }
+    }
+
+    // SAFETY: addrs was allocated by getifaddrs and is not used afterwards
+    unsafe { libc::freeifaddrs(addrs) };
+
+    match address {
+        Some(ip) => Ok(IpAddr::V4(ip)),
+        None if found => anyhow::bail!("Interface '{name}' has no IPv4 address"),
+        None => anyhow::bail!("Interface '{name}' not found"),
+    }
+}
+
+/// Look up the first `IPv4` address assigned to a network interface
+#[cfg(not(unix))]
+fn interface_ipv4(name: &str) -> Result<IpAddr> {
+    anyhow::bail!("Interface sources are not supported on this platform (interface '{name}')")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HostEntry;
+    use std::net::Ipv4Addr;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_literal() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(resolve_source(&IpSource::Literal { ip }).unwrap(), ip);
+    }
+
+    #[test]
+    fn test_resolve_missing_env_var() {
+        let source = IpSource::Env {
+            name: "HOSTCTL_TEST_SURELY_UNSET_VARIABLE".to_string(),
+        };
+        let err = resolve_source(&source).unwrap_err();
+        assert!(format!("{err:#}").contains("is not set"));
+    }
+
+    #[test]
+    fn test_resolve_file_source() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("vm.ip");
+        fs::write(&path, "# generated\n\n192.168.56.10 vm\n10.0.0.7\n").unwrap();
+
+        let first = IpSource::File {
+            path: path.clone(),
+            line: None,
+        };
+        assert_eq!(
+            resolve_source(&first).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(192, 168, 56, 10))
+        );
+
+        let fourth = IpSource::File {
+            path: path.clone(),
+            line: Some(4),
+        };
+        assert_eq!(
+            resolve_source(&fourth).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7))
+        );
+
+        let missing_line = IpSource::File {
+            path,
+            line: Some(10),
+        };
+        assert!(resolve_source(&missing_line).is_err());
+
+        let missing_file = IpSource::File {
+            path: PathBuf::from("/nonexistent/hostctl/vm.ip"),
+            line: None,
+        };
+        assert!(resolve_source(&missing_file).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_command_source() {
+        let ok = IpSource::Command {
+            command: "echo 10.1.2.3".to_string(),
+        };
+        assert_eq!(
+            resolve_source(&ok).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3))
+        );
+
+        let failing = IpSource::Command {
+            command: "echo boom >&2; exit 3".to_string(),
+        };
+        let err = resolve_source(&failing).unwrap_err();
+        assert!(format!("{err:#}").contains("boom"));
+
+        let garbage = IpSource::Command {
+            command: "echo not-an-ip".to_string(),
+        };
+        assert!(resolve_source(&garbage).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_resolve_interface_source() {
+        let lo = IpSource::Interface {
+            name: "lo".to_string(),
+        };
+        assert_eq!(
+            resolve_source(&lo).unwrap(),
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        );
+
+        let missing = IpSource::Interface {
+            name: "hostctl-missing0".to_string(),
+        };
+        let err = resolve_source(&missing).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn test_resolve_alias_entries() {
+        let api_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::alias(
+            "www.dev.local".to_string(),
+            "web.dev.local".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "web.dev.local".to_string(),
+            "api.dev.local".to_string(),
+        ));
+        env.add_entry(HostEntry::new(api_ip, "api.dev.local".to_string()));
+
+        let resolved = resolve_environment(&env).unwrap();
+        assert!(resolved.entries.iter().all(|e| e.ip == api_ip));
+        // The alias relationship is kept for later switches
+        assert_eq!(
+            resolved.find_entry("www.dev.local").unwrap().target,
+            Some("web.dev.local".to_string())
+        );
+    }
+
+    #[test]
+    fn test_alias_follows_dynamic_source() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("api.ip");
+        fs::write(&path, "10.9.8.7\n").unwrap();
+
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::from_source(
+            IpSource::File { path, line: None },
+            "api.dev.local".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "www.dev.local".to_string(),
+            "api.dev.local".to_string(),
+        ));
+
+        let resolved = resolve_environment(&env).unwrap();
+        assert_eq!(
+            resolved.find_entry("www.dev.local").unwrap().ip,
+            IpAddr::V4(Ipv4Addr::new(10, 9, 8, 7))
+        );
+    }
+
+    #[test]
+    fn test_validate_dangling_alias() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::alias(
+            "www.dev.local".to_string(),
+            "missing.dev.local".to_string(),
+        ));
+
+        let err = validate_environment(&env).unwrap_err();
+        assert!(err.to_string().contains("missing.dev.local"));
+        assert!(err.to_string().contains("not defined"));
+        assert!(resolve_environment(&env).is_err());
+    }
+
+    #[test]
+    fn test_validate_alias_cycle() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::alias(
+            "a.local".to_string(),
+            "b.local".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "b.local".to_string(),
+            "c.local".to_string(),
+        ));
+        env.add_entry(HostEntry::alias(
+            "c.local".to_string(),
+            "a.local".to_string(),
+        ));
+
+        let err = validate_environment(&env).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("a.local -> b.local -> c.local -> a.local")
+        );
+
+        let mut self_loop = Environment::new("dev".to_string());
+        self_loop.add_entry(HostEntry::alias(
+            "a.local".to_string(),
+            "a.local".to_string(),
+        ));
+        assert!(validate_environment(&self_loop).is_err());
+    }
+
+    #[test]
+    fn test_validate_invalid_hostname() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            "bad_host".to_string(),
+        ));
+
+        let err = validate_environment(&env).unwrap_err();
+        assert!(err.to_string().contains("Invalid hostname"));
+    }
+
+    #[test]
+    fn test_resolve_environment_reports_entry() {
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            "static.dev".to_string(),
+        ));
+        env.add_entry(HostEntry::from_source(
+            IpSource::Env {
+                name: "HOSTCTL_TEST_SURELY_UNSET_VARIABLE".to_string(),
+            },
+            "vm.dev".to_string(),
+        ));
+
+        let err = resolve_environment(&env).unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("vm.dev"));
+        assert!(message.contains("env:HOSTCTL_TEST_SURELY_UNSET_VARIABLE"));
+    }
+}
@@ -0,0 +1,454 @@
+use crate::config::{Config, ConfigLayer, Environment};
+use crate::format::ConfigFormat;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Time allowed for fetching one source
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A remote file of environment definitions
+///
+/// The file has the layout of a config file; only its `environments` are used. They
+/// are exposed read-only as `<source>/<environment>`.
+///
+/// # Example
+/// ```yaml
+/// sources:
+///   - name: platform
+///     url: https://config.example.com/hostctl/environments.yaml
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteSource {
+    /// Name of the source, used as namespace of its environments
+    pub name: String,
+    /// HTTP(S) URL of the file
+    pub url: String,
+    /// Layer this source was configured in (not stored in the file)
+    #[serde(skip)]
+    pub layer: ConfigLayer,
+}
+
+/// Last good copy of a remote source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSource {
+    /// URL the copy was fetched from
+    pub url: String,
+    /// `ETag` header of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// When the source was last fetched or confirmed unchanged
+    pub fetched_at: DateTime<Utc>,
+    /// The fetched file
+    pub content: String,
+}
+
+/// Result of updating a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// A new copy was stored
+    Updated {
+        /// Number of environments in the new copy
+        environments: usize,
+    },
+    /// The server reported the cached copy as current
+    NotModified,
+}
+
+/// Layout of a remote file
+#[derive(Debug, Deserialize)]
+struct RemoteDocument {
+    #[serde(default)]
+    environments: HashMap<String, Environment>,
+}
+
+impl RemoteSource {
+    /// Namespaced name of one of the source's environments
+    ///
+    /// # Arguments
+    /// * `env` - Name of the environment in the remote file
+    #[must_use]
+    pub fn qualified_name(&self, env: &str) -> String {
+        format!("{}/{env}", self.name)
+    }
+
+    /// Path of the cached copy in a cache directory
+    ///
+    /// # Arguments
+    /// * `cache_dir` - Directory holding the cached sources
+    #[must_use]
+    pub fn cache_path(&self, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(format!("{}.json", self.name))
+    }
+
+    /// Check that the source can be used
+    ///
+    /// # Errors
+    /// Returns an error if the name cannot be used as a namespace and file name, or the
+    /// URL is not HTTP(S).
+    pub fn validate(&self) -> Result<()> {
+        let valid_name = !self.name.is_empty()
+            && !self.name.starts_with('.')
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_name {
+            anyhow::bail!("Invalid source name '{}'", self.name);
+        }
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!(
+                "Source '{}' must use an http:// or https:// URL: {}",
+                self.name,
+                self.url
+            );
+        }
+        Ok(())
+    }
+
+    /// Format of the remote file, from the extension of the URL path (YAML by default)
+    fn format(&self) -> ConfigFormat {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        ConfigFormat::from_path(Path::new(path)).unwrap_or_default()
+    }
+
+    /// Parse and validate the contents of the remote file
+    ///
+    /// Remote environments may only contain static addresses and aliases: dynamic
+    /// sources would let the remote file run commands or read files on this machine.
+    ///
+    /// # Arguments
+    /// * `content` - The file contents
+    ///
+    /// # Returns
+    /// Returns the environments under their qualified names, marked as remote
+    ///
+    /// # Errors
+    /// Returns an error if the contents cannot be parsed or use dynamic sources.
+    pub fn parse(&self, content: &str) -> Result<Vec<Environment>> {
+        let document: RemoteDocument = self
+            .format()
+            .parse(content)
+            .with_context(|| format!("Invalid contents of source '{}'", self.name))?;
+
+        let mut environments = Vec::new();
+        for (name, mut env) in document.environments {
+            if let Some(entry) = env.find_dynamic_source() {
+                anyhow::bail!(
+                    "Environment '{name}' of source '{}' uses a dynamic IP source for '{}', which remote sources may not do",
+                    self.name,
+                    entry.hostname
+                );
+            }
+            env.name = self.qualified_name(&name);
+            env.layer = ConfigLayer::Remote;
+            environments.push(env);
+        }
+        environments.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(environments)
+    }
+
+    /// Load the last good copy of the source
+    ///
+    /// Copies fetched from a different URL are ignored.
+    ///
+    /// # Arguments
+    /// * `cache_dir` - Directory holding the cached sources
+    ///
+    /// # Returns
+    /// Returns the cached copy, or `None` if the source was never fetched
+    ///
+    /// # Errors
+    /// Returns an error if the cached copy cannot be read.
+    pub fn load_cached(&self, cache_dir: &Path) -> Result<Option<CachedSource>> {
+        let path = self.cache_path(cache_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read cached source: {}", path.display()))?;
+        let cached: CachedSource = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse cached source: {}", path.display()))?;
+        Ok((cached.url == self.url).then_some(cached))
+    }
+
+    /// Fetch the source and store it as the new last good copy
+    ///
+    /// A cached copy is revalidated with `If-None-Match` and `If-Modified-Since`. New
+    /// contents are only stored if they are valid, so a failed update keeps the
+    /// previous copy usable. A cached copy that cannot be read is fetched anew.
+    ///
+    /// # Arguments
+    /// * `cache_dir` - Directory holding the cached sources
+    ///
+    /// # Errors
+    /// Returns an error if the source cannot be fetched, its contents are invalid or
+    /// the cache cannot be written.
+    pub fn update(&self, cache_dir: &Path) -> Result<FetchOutcome> {
+        self.validate()?;
+        let cached = self.load_cached(cache_dir).ok().flatten();
+
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(FETCH_TIMEOUT))
+            .build()
+            .into();
+        let mut request = agent.get(&self.url);
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header("If-Modified-Since", last_modified);
+            }
+        }
+        let mut response = request
+            .call()
+            .with_context(|| format!("Failed to fetch source '{}' from {}", self.name, self.url))?;
+
+        if response.status() == 304 {
+            let mut cached = cached.with_context(|| {
+                format!("Source '{}' answered 304 without a cached copy", self.name)
+            })?;
+            cached.fetched_at = Utc::now();
+            self.store(cache_dir, &cached)?;
+            return Ok(FetchOutcome::NotModified);
+        }
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let (etag, last_modified) = (header("etag"), header("last-modified"));
+        let content = response
+            .body_mut()
+            .read_to_string()
+            .with_context(|| format!("Failed to read source '{}'", self.name))?;
+
+        let environments = self.parse(&content)?.len();
+        self.store(
+            cache_dir,
+            &CachedSource {
+                url: self.url.clone(),
+                etag,
+                last_modified,
+                fetched_at: Utc::now(),
+                content,
+            },
+        )?;
+        Ok(FetchOutcome::Updated { environments })
+    }
+
+    /// Write a copy of the source to the cache
+    fn store(&self, cache_dir: &Path, cached: &CachedSource) -> Result<()> {
+        fs::create_dir_all(cache_dir).with_context(|| {
+            format!("Failed to create cache directory: {}", cache_dir.display())
+        })?;
+        let path = self.cache_path(cache_dir);
+        fs::write(&path, serde_json::to_string_pretty(cached)? + "\n")
+            .with_context(|| format!("Failed to write cached source: {}", path.display()))
+    }
+}
+
+/// Add the environments of all cached sources to a configuration
+///
+/// Remote environments never hide environments of other layers. Sources that were
+/// never fetched contribute nothing, and so do sources whose cached copy cannot be
+/// read or parsed, after a warning: `sources update` replaces such copies, so they
+/// must not keep hostctl from loading its configuration.
+///
+/// # Arguments
+/// * `config` - The configuration to extend
+/// * `cache_dir` - Directory holding the cached sources
+pub fn merge_cached(config: &mut Config, cache_dir: &Path) {
+    for source in config.sources.clone() {
+        let environments = source
+            .load_cached(cache_dir)
+            .and_then(|cached| cached.map_or(Ok(Vec::new()), |c| source.parse(&c.content)));
+        match environments {
+            Ok(environments) => {
+                for env in environments {
+                    config.environments.entry(env.name.clone()).or_insert(env);
+                }
+            }
+            Err(e) => eprintln!("Warning: ignoring source '{}': {e:#}", source.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const DOCUMENT: &str = "environments:\n  dev:\n    entries:\n    - ip: 10.0.0.1\n      hostname: api.corp.local\n  qa:\n    entries: []\n";
+
+    /// Serve one canned response per request and return the received request headers
+    fn serve(responses: Vec<String>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/environments.yaml",
+            listener.local_addr().unwrap()
+        );
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    headers.push_str(&line);
+                }
+                requests.push(headers);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn ok_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Sun, 18 Oct 2026 10:00:00 GMT\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    fn source(url: &str) -> RemoteSource {
+        RemoteSource {
+            name: "platform".to_string(),
+            url: url.to_string(),
+            layer: ConfigLayer::User,
+        }
+    }
+
+    #[test]
+    fn test_update_and_revalidate() {
+        let not_modified =
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (url, server) = serve(vec![ok_response(DOCUMENT), not_modified.to_string()]);
+        let cache = tempfile::tempdir().unwrap();
+        let source = source(&url);
+
+        assert_eq!(
+            source.update(cache.path()).unwrap(),
+            FetchOutcome::Updated { environments: 2 }
+        );
+        assert_eq!(
+            source.update(cache.path()).unwrap(),
+            FetchOutcome::NotModified
+        );
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].to_lowercase().contains("if-none-match"));
+        let revalidation = requests[1].to_lowercase();
+        assert!(revalidation.contains("if-none-match: \"v1\""));
+        assert!(revalidation.contains("if-modified-since: sun, 18 oct 2026 10:00:00 gmt"));
+
+        let cached = source.load_cached(cache.path()).unwrap().unwrap();
+        assert_eq!(cached.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(cached.content, DOCUMENT);
+    }
+
+    #[test]
+    fn test_offline_use_of_last_good_copy() {
+        let (url, server) = serve(vec![
+            ok_response(DOCUMENT),
+            ok_response("environments: [not, a, map]\n"),
+        ]);
+        let cache = tempfile::tempdir().unwrap();
+        let source = source(&url);
+        source.update(cache.path()).unwrap();
+
+        // Invalid contents do not replace the cached copy
+        assert!(source.update(cache.path()).is_err());
+        server.join().unwrap();
+        // Nothing listens any more
+        assert!(source.update(cache.path()).is_err());
+
+        let mut config = Config::new();
+        config.sources.push(source);
+        let mut local = Environment::new("platform/qa".to_string());
+        local.description = Some("Local".to_string());
+        config.add_environment(local);
+        merge_cached(&mut config, cache.path());
+
+        let dev = config.get_environment("platform/dev").unwrap();
+        assert_eq!(dev.layer, ConfigLayer::Remote);
+        assert_eq!(dev.entries[0].hostname, "api.corp.local");
+        // Remote environments do not hide existing ones
+        let qa = config.get_environment("platform/qa").unwrap();
+        assert_eq!(qa.description.as_deref(), Some("Local"));
+    }
+
+    #[test]
+    fn test_unreadable_cache_is_replaced() {
+        let cache = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![ok_response(DOCUMENT)]);
+        let source = source(&url);
+        fs::write(source.cache_path(cache.path()), "not json").unwrap();
+
+        let mut config = Config::new();
+        config.sources.push(source.clone());
+        merge_cached(&mut config, cache.path());
+        assert!(config.get_environment("platform/dev").is_none());
+
+        source.update(cache.path()).unwrap();
+        server.join().unwrap();
+        assert!(source.load_cached(cache.path()).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_cache_of_other_url_is_ignored() {
+        let cache = tempfile::tempdir().unwrap();
+        let old = source("http://old.example.com/envs.yaml");
+        old.store(
+            cache.path(),
+            &CachedSource {
+                url: old.url.clone(),
+                etag: None,
+                last_modified: None,
+                fetched_at: Utc::now(),
+                content: DOCUMENT.to_string(),
+            },
+        )
+        .unwrap();
+
+        assert!(old.load_cached(cache.path()).unwrap().is_some());
+        let moved = source("http://new.example.com/envs.yaml");
+        assert!(moved.load_cached(cache.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_remote_dynamic_sources_are_rejected() {
+        let source = source("https://example.com/envs.json");
+        let content = r#"{"environments": {"dev": {"entries": [{"hostname": "x.local", "source": {"type": "command", "command": "id"}}]}}}"#;
+        let error = source.parse(content).unwrap_err();
+        assert!(error.to_string().contains("dynamic IP source"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(source("https://example.com/envs.yaml").validate().is_ok());
+        assert!(source("ftp://example.com/envs.yaml").validate().is_err());
+        let mut bad = source("https://example.com/envs.yaml");
+        bad.name = "a/b".to_string();
+        assert!(bad.validate().is_err());
+    }
+}
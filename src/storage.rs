@@ -1,8 +1,83 @@
-use crate::config::Config;
+use crate::config::{CONFIG_VERSION, Config, ConfigLayer, Environment, PendingRevert};
+use crate::format::ConfigFormat;
+use crate::hosts;
+use crate::journal::JOURNAL_FILE;
+use crate::merge::{self, Conflict};
+use crate::project::Project;
+use crate::sources;
+use crate::sudo;
+use crate::yaml_patch;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 use serde_yaml_ok as serde_yaml;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// A migration upgrading a raw config document by one format version
+type Migration = fn(&mut Mapping) -> Result<()>;
+
+/// Migrations between config format versions
+///
+/// `MIGRATIONS[n]` upgrades a document from version `n` to version `n + 1`, so its
+/// length always equals `CONFIG_VERSION`.
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1];
+
+/// Version 0 files predate the `version` key; the layout is otherwise unchanged
+fn migrate_v0_to_v1(doc: &mut Mapping) -> Result<()> {
+    if !doc.contains_key("environments") {
+        doc.insert("environments".into(), Value::Mapping(Mapping::new()));
+    }
+    Ok(())
+}
+
+/// Read the format version of a raw config document
+///
+/// Documents without a `version` key are version 0.
+fn document_version(doc: &Mapping) -> Result<u32> {
+    match doc.get("version") {
+        None => Ok(0),
+        Some(value) => value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .with_context(|| format!("Invalid config version: {value:?}")),
+    }
+}
+
+/// Upgrade a raw config document to the current format version
+///
+/// # Arguments
+/// * `doc` - The parsed config file
+///
+/// # Returns
+/// Returns the version the document had before the upgrade
+///
+/// # Errors
+/// Returns an error if the document was written by a newer hostctl or a migration fails.
+fn migrate_document(doc: &mut Mapping) -> Result<u32> {
+    let original = document_version(doc)?;
+    if original > CONFIG_VERSION {
+        anyhow::bail!(
+            "Config format version {original} was written by a newer hostctl; this build supports up to version {CONFIG_VERSION}. Please upgrade hostctl."
+        );
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(original as usize) {
+        migration(doc).with_context(|| {
+            format!(
+                "Failed to migrate config from version {from} to {}",
+                from + 1
+            )
+        })?;
+    }
+    doc.insert("version".into(), CONFIG_VERSION.into());
+
+    Ok(original)
+}
 
 /// Get config directory path
 ///
@@ -10,7 +85,7 @@ use std::path::PathBuf;
 /// - Windows: `%APPDATA%\hostctl`
 /// - Linux/macOS: `~/.config/hostctl`
 #[cfg(target_os = "windows")]
-fn get_config_dir() -> PathBuf {
+fn default_config_dir() -> PathBuf {
     dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("C:\\ProgramData"))
         .join("hostctl")
@@ -20,15 +95,555 @@ fn get_config_dir() -> PathBuf {
 ///
 /// Returns different config directories based on operating system:
 /// - Windows: `%APPDATA%\hostctl`
-/// - Linux/macOS: `~/.config/hostctl`
+/// - Linux/macOS: `$XDG_CONFIG_HOME/hostctl`, or `~/.config/hostctl` if it is not set
+///
+/// When run through `sudo` or `pkexec`, `~` is the home of the invoking user, not root's.
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-fn get_config_dir() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(".config")
+fn default_config_dir() -> PathBuf {
+    let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    xdg_config_home
+        .or_else(|| {
+            sudo::invoking_user()
+                .map(|user| user.home.clone())
+                .or_else(dirs::home_dir)
+                .map(|home| home.join(".config"))
+        })
+        .unwrap_or_else(|| PathBuf::from(".config"))
         .join("hostctl")
 }
 
+/// Get the directory of the machine-wide system config
+///
+/// Returns different directories based on operating system:
+/// - Windows: `%ProgramData%\hostctl`
+/// - Linux/macOS: `/etc/hostctl`
+#[cfg(target_os = "windows")]
+fn default_system_config_dir() -> PathBuf {
+    std::env::var_os("ProgramData")
+        .map_or_else(|| PathBuf::from("C:\\ProgramData"), PathBuf::from)
+        .join("hostctl")
+}
+
+/// Get the directory of the machine-wide system config
+///
+/// Returns different directories based on operating system:
+/// - Windows: `%ProgramData%\hostctl`
+/// - Linux/macOS: `/etc/hostctl`
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn default_system_config_dir() -> PathBuf {
+    PathBuf::from("/etc/hostctl")
+}
+
+/// Locations chosen for this process with `Locations::install`
+static LOCATIONS: OnceLock<Locations> = OnceLock::new();
+
+/// Where hostctl keeps the config and hosts files, if not in the default locations
+///
+/// The hostctl command installs the locations given by `--config`, `--hosts-file`
+/// and `--root` (or `HOSTCTL_CONFIG` and `HOSTCTL_HOSTS_FILE`) at startup; every
+/// path `ConfigStorage` hands out follows them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locations {
+    /// User config file; the journal, caches and snapshot are kept next to it
+    pub config_file: Option<PathBuf>,
+    /// Hosts file; backups are kept next to it
+    pub hosts_file: Option<PathBuf>,
+    /// Directory the default locations are relative to, e.g. a container root filesystem
+    pub root: Option<PathBuf>,
+}
+
+impl Locations {
+    /// Use these locations for the rest of the process
+    ///
+    /// Relative paths are resolved against the working directory.
+    ///
+    /// # Errors
+    /// Returns an error if a path cannot be made absolute or locations were
+    /// already installed.
+    pub fn install(self) -> Result<()> {
+        let absolute = |path: Option<PathBuf>| -> Result<Option<PathBuf>> {
+            path.map(|path| {
+                std::path::absolute(&path)
+                    .with_context(|| format!("Invalid path: {}", path.display()))
+            })
+            .transpose()
+        };
+        let locations = Self {
+            config_file: absolute(self.config_file)?,
+            hosts_file: absolute(self.hosts_file)?,
+            root: absolute(self.root)?,
+        };
+        LOCATIONS
+            .set(locations)
+            .map_err(|_| anyhow::anyhow!("Locations are already installed"))
+    }
+
+    /// The locations used by this process
+    #[must_use]
+    pub fn current() -> &'static Self {
+        static DEFAULT: Locations = Locations {
+            config_file: None,
+            hosts_file: None,
+            root: None,
+        };
+        LOCATIONS.get().unwrap_or(&DEFAULT)
+    }
+
+    /// Whether any location differs from the default
+    #[must_use]
+    pub fn is_custom(&self) -> bool {
+        self.config_file.is_some() || self.hosts_file.is_some() || self.root.is_some()
+    }
+
+    /// Move a default location below the root directory, if one is set
+    ///
+    /// # Arguments
+    /// * `path` - An absolute default location, e.g. `/etc/hosts`
+    #[must_use]
+    pub fn rooted(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(
+                path.components()
+                    .filter(|component| matches!(component, Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            ),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Path of the hosts file to manage
+    #[must_use]
+    pub fn hosts_file(&self) -> PathBuf {
+        self.hosts_file
+            .clone()
+            .unwrap_or_else(|| self.rooted(hosts::system_hosts_path()))
+    }
+
+    /// Path of the hosts file of a target
+    ///
+    /// Absolute paths are moved below the root directory, if one is set, and
+    /// relative paths are resolved against the config directory.
+    ///
+    /// # Arguments
+    /// * `path` - The path configured for the target
+    #[must_use]
+    pub fn target_file(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            self.rooted(path)
+        } else {
+            get_config_dir().join(path)
+        }
+    }
+
+    /// Command line arguments passing these locations on to another hostctl process
+    #[must_use]
+    pub fn args(&self) -> Vec<std::ffi::OsString> {
+        let mut args = Vec::new();
+        for (flag, path) in [
+            ("--config", &self.config_file),
+            ("--hosts-file", &self.hosts_file),
+            ("--root", &self.root),
+        ] {
+            if let Some(path) = path {
+                args.push(flag.into());
+                args.push(path.clone().into_os_string());
+            }
+        }
+        args
+    }
+}
+
+/// Get the config directory, following the installed `Locations`
+fn get_config_dir() -> PathBuf {
+    let locations = Locations::current();
+    match locations.config_file.as_deref().and_then(Path::parent) {
+        Some(dir) => dir.to_path_buf(),
+        None => locations.rooted(&default_config_dir()),
+    }
+}
+
+/// Get the directory of the system config, following the installed `Locations`
+fn get_system_config_dir() -> PathBuf {
+    Locations::current().rooted(&default_system_config_dir())
+}
+
+/// Directory next to the config file holding one file per environment
+const ENVIRONMENTS_DIR: &str = "environments";
+
+/// File next to the config file holding state in the directory layout
+const STATE_FILE: &str = "state.yaml";
+
+/// Directory in the config directory holding the cached copies of remote sources
+const SOURCE_CACHE_DIR: &str = "cache/sources";
+
+/// Copy of the user configuration as it was last saved successfully
+const SNAPSHOT_FILE: &str = "last-good.yaml";
+
+/// File in the config directory locked while a command modifies the configuration
+const LOCK_FILE: &str = "hostctl.lock";
+
+/// File extensions of environment files, in the order they are looked up
+const ENVIRONMENT_EXTENSIONS: [&str; 4] = ["yaml", "yml", "toml", "json"];
+
+/// How the user configuration is laid out on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigLayout {
+    /// Everything in a single config file
+    File,
+    /// One file per environment in `environments/`, with state in `state.yaml`
+    Directory,
+}
+
+impl fmt::Display for ConfigLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File => write!(f, "file"),
+            Self::Directory => write!(f, "directory"),
+        }
+    }
+}
+
+impl FromStr for ConfigLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "file" => Ok(Self::File),
+            "directory" | "dir" => Ok(Self::Directory),
+            _ => anyhow::bail!("Unknown config layout '{s}' (use file or directory)"),
+        }
+    }
+}
+
+/// Machine-local state kept out of version control in the directory layout
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    current_environment: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pending_revert: Option<PendingRevert>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    target_environments: BTreeMap<String, String>,
+}
+
+/// Config file names, in the order they are looked up
+const CONFIG_FILE_NAMES: [&str; 4] = ["config.yaml", "config.yml", "config.toml", "config.json"];
+
+/// Find the config file in a directory
+///
+/// Falls back to `config.yaml` if none of `CONFIG_FILE_NAMES` exists.
+fn find_config_file(dir: &Path) -> PathBuf {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| dir.join("config.yaml"))
+}
+
+/// Get the path of the machine-wide system config file
+fn get_system_config_path() -> PathBuf {
+    find_config_file(&get_system_config_dir())
+}
+
+/// A configuration file contributing environments to the merged configuration
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    /// Layer the file belongs to
+    pub layer: ConfigLayer,
+    /// Path of the file
+    pub path: PathBuf,
+    /// Whether the file exists
+    pub exists: bool,
+    /// Names of the environments defined in the file, as exposed by hostctl
+    pub environments: Vec<String>,
+}
+
+/// A config or environment file that is not valid in its format
+///
+/// Attached as context to parse errors, so callers can find out which file is
+/// damaged with `anyhow::Error::downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Line of the error, starting at 1
+    pub line: Option<usize>,
+    /// Column of the error, starting at 1
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to parse {}", self.path.display())?;
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, " at line {line}, column {column}"),
+            (Some(line), None) => write!(f, " at line {line}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Attach the file and the location of the error in it to a parse error
+fn parse_error(path: &Path, content: &str, error: anyhow::Error) -> anyhow::Error {
+    let (line, column) = error_location(&error, content).unzip();
+    error.context(ParseError {
+        path: path.to_path_buf(),
+        line,
+        column,
+    })
+}
+
+/// Line and column of a YAML, TOML or JSON parse error
+fn error_location(error: &anyhow::Error, content: &str) -> Option<(usize, usize)> {
+    if let Some(error) = error.downcast_ref::<serde_yaml::Error>() {
+        let location = error.location()?;
+        return Some((location.line(), location.column()));
+    }
+    if let Some(error) = error.downcast_ref::<serde_json::Error>() {
+        return (error.line() > 0).then(|| (error.line(), error.column()));
+    }
+    let offset = error.downcast_ref::<toml::de::Error>()?.span()?.start;
+    let before = content.get(..offset)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some((
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    ))
+}
+
+/// Copy a file to `<file>.<timestamp>.bak` before it is overwritten or removed
+///
+/// Existing backups are never replaced; a counter is added to the name instead.
+fn backup_file(path: &Path) -> Result<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let mut backup = path.with_file_name(format!("{file_name}.{timestamp}.bak"));
+    for n in 1.. {
+        if !backup.exists() {
+            break;
+        }
+        backup = path.with_file_name(format!("{file_name}.{timestamp}.{n}.bak"));
+    }
+    fs::copy(path, &backup).with_context(|| format!("Failed to back up {}", backup.display()))?;
+    Ok(backup)
+}
+
+/// Result of salvaging a damaged config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Repair {
+    /// Environments salvaged from the damaged files
+    pub recovered: Vec<String>,
+    /// Environments that could not be parsed and were taken from the last good copy
+    pub restored: Vec<String>,
+    /// Environments and settings that could not be salvaged
+    pub lost: Vec<String>,
+    /// Copies of the damaged files
+    pub backups: Vec<PathBuf>,
+}
+
+/// What could be parsed from a damaged YAML config file
+#[derive(Debug, Default)]
+struct Salvage {
+    /// The parseable settings
+    doc: Mapping,
+    /// The parseable environments
+    environments: Mapping,
+    /// Names of environments that could not be parsed
+    lost_environments: Vec<String>,
+    /// Descriptions of other parts that could not be parsed
+    lost: Vec<String>,
+}
+
+impl Salvage {
+    /// Keep an environment if it is valid, otherwise record it as lost
+    fn add_environment(&mut self, name: &Value, value: &Value) {
+        let valid = serde_yaml::from_value::<Environment>(value.clone()).is_ok();
+        match name.as_str() {
+            Some(_) if valid => {
+                self.environments.insert(name.clone(), value.clone());
+            }
+            Some(name) => self.lost_environments.push(name.to_string()),
+            None => self.lost.push(format!("environment {name:?}")),
+        }
+    }
+}
+
+/// Split YAML text into blocks, each starting at a line indented by exactly `indent`
+///
+/// # Returns
+/// Returns the line number of each block's first line and its text
+fn yaml_blocks(lines: &[(usize, &str)], indent: usize) -> Vec<(usize, String)> {
+    let mut blocks: Vec<(usize, String)> = Vec::new();
+    for &(number, line) in lines {
+        let content = line.trim_start();
+        let starts_block = !content.is_empty()
+            && !content.starts_with('#')
+            && !content.starts_with('-')
+            && line.len() - content.len() == indent;
+        match blocks.last_mut() {
+            Some((_, text)) if !starts_block => {
+                text.push_str(line);
+                text.push('\n');
+            }
+            _ if starts_block => blocks.push((number, format!("{line}\n"))),
+            // Comments and blank lines before the first block
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Describe a block that cannot be parsed by the key it starts with
+fn block_label(number: usize, text: &str) -> String {
+    let key = text
+        .split_once(':')
+        .map(|(key, _)| key.trim().trim_matches(['\'', '"']))
+        .filter(|key| !key.is_empty() && !key.contains('\n'));
+    match key {
+        Some(key) => key.to_string(),
+        None => format!("line {number}"),
+    }
+}
+
+/// Salvage the parseable top-level settings and environments of damaged YAML text
+///
+/// Every top-level key and every environment is parsed on its own, so damage in one
+/// of them does not affect the others.
+fn salvage_yaml(content: &str) -> Salvage {
+    let lines: Vec<(usize, &str)> = content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !matches!(line.trim_end(), "---" | "..."))
+        .collect();
+
+    let mut salvage = Salvage::default();
+    for (number, text) in yaml_blocks(&lines, 0) {
+        let label = block_label(number, &text);
+        if label == "environments" {
+            if let Ok(Some(Value::Mapping(envs))) =
+                serde_yaml::from_str::<Mapping>(&text).map(|mut doc| doc.remove("environments"))
+            {
+                for (name, value) in &envs {
+                    salvage.add_environment(name, value);
+                }
+                continue;
+            }
+
+            // Parse the environments one by one
+            let children: Vec<(usize, &str)> = text
+                .lines()
+                .skip(1)
+                .enumerate()
+                .map(|(i, line)| (number + 1 + i, line))
+                .collect();
+            let indent = children
+                .iter()
+                .map(|(_, line)| line.trim_start())
+                .zip(children.iter().map(|(_, line)| line.len()))
+                .find(|(content, _)| !content.is_empty() && !content.starts_with('#'))
+                .map_or(0, |(content, len)| len - content.len());
+            if indent == 0 {
+                salvage.lost.push("environments".to_string());
+                continue;
+            }
+            for (number, text) in yaml_blocks(&children, indent) {
+                match serde_yaml::from_str::<Mapping>(&text) {
+                    Ok(env) => {
+                        for (name, value) in &env {
+                            salvage.add_environment(name, value);
+                        }
+                    }
+                    Err(_) => salvage.lost_environments.push(block_label(number, &text)),
+                }
+            }
+            continue;
+        }
+
+        // Keep a setting only if the configuration still loads with it
+        let setting = serde_yaml::from_str::<Mapping>(&text).ok();
+        let mut candidate = salvage.doc.clone();
+        candidate.extend(setting.into_iter().flatten());
+        let mut migrated = candidate.clone();
+        migrated.insert("environments".into(), Value::Mapping(Mapping::new()));
+        let valid = migrate_document(&mut migrated).is_ok()
+            && serde_yaml::from_value::<Config>(Value::Mapping(migrated)).is_ok();
+        if valid && candidate.len() > salvage.doc.len() {
+            salvage.doc = candidate;
+        } else {
+            salvage.lost.push(format!("setting '{label}'"));
+        }
+    }
+
+    salvage
+}
+
+/// Write a serializable value to a file in the format given by its extension
+///
+/// Existing YAML files are edited in place so only the changed parts of the document
+/// are rewritten, keeping comments, key order and formatting elsewhere. Files whose
+/// contents would not change are not touched.
+///
+/// # Arguments
+/// * `path` - Path of the file
+/// * `value` - The value to write
+/// * `parse` - Parses file contents the same way the value is loaded
+fn write_document<T, F>(path: &Path, value: &T, parse: F) -> Result<()>
+where
+    T: Serialize,
+    F: Fn(&str) -> Result<T>,
+{
+    write_document_as(path, ConfigFormat::from_path(path)?, value, parse)
+}
+
+/// Write a serializable value to a file in the given format
+///
+/// Like `write_document`, for files whose name does not tell their format.
+fn write_document_as<T, F>(path: &Path, format: ConfigFormat, value: &T, parse: F) -> Result<()>
+where
+    T: Serialize,
+    F: Fn(&str) -> Result<T>,
+{
+    let existing = fs::read_to_string(path).ok();
+
+    let patched = match (format, &existing) {
+        (ConfigFormat::Yaml, Some(text)) => patch_document(text, value, &parse),
+        _ => None,
+    };
+    let content = match patched {
+        Some(content) => content,
+        None => format
+            .serialize(value)
+            .with_context(|| format!("Failed to serialize {}", path.display()))?,
+    };
+
+    if existing.as_deref() != Some(content.as_str()) {
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write file: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Edit existing YAML text in place so it represents `value`
+///
+/// # Returns
+/// Returns the new text, or `None` if the text cannot be patched and must be
+/// serialized from scratch
+fn patch_document<T, F>(text: &str, value: &T, parse: F) -> Option<String>
+where
+    T: Serialize,
+    F: Fn(&str) -> Result<T>,
+{
+    let old = serde_yaml::to_value(parse(text).ok()?).ok()?;
+    let new = serde_yaml::to_value(value).ok()?;
+    let patched = yaml_patch::patch_document(text, &old, &new)?;
+
+    // Only keep the patch if it reads back as exactly the new value
+    let reread = serde_yaml::to_value(parse(&patched).ok()?).ok()?;
+    (reread == new).then_some(patched)
+}
+
 /// Configuration storage manager
 ///
 /// Responsible for reading, writing, and managing configuration files.
@@ -37,95 +652,1145 @@ pub struct ConfigStorage;
 impl ConfigStorage {
     /// Get the full path to the config file
     ///
+    /// The file may be in YAML, TOML or JSON format; see `find_config_file`.
+    ///
     /// # Returns
     /// Returns the `PathBuf` of the config file
     #[must_use]
     pub fn get_config_path() -> PathBuf {
-        get_config_dir().join("config.yaml")
+        Locations::current()
+            .config_file
+            .clone()
+            .unwrap_or_else(|| find_config_file(&get_config_dir()))
+    }
+
+    /// Get the full path to the read-only system config file
+    ///
+    /// # Returns
+    /// Returns the `PathBuf` of the system config file
+    #[must_use]
+    pub fn get_system_config_path() -> PathBuf {
+        get_system_config_path()
+    }
+
+    /// Get the path to the config directory
+    ///
+    /// # Returns
+    /// Returns the `PathBuf` of the config directory
+    #[must_use]
+    pub fn get_config_dir_path() -> PathBuf {
+        get_config_dir()
+    }
+
+    /// Get root's own config file when it is ignored in favour of the invoking user's
+    ///
+    /// When hostctl runs through `sudo` or `pkexec`, the invoking user's configuration
+    /// is used; a configuration root has of its own is then silently ignored.
+    ///
+    /// # Returns
+    /// Returns the path of root's config file if hostctl runs on behalf of another
+    /// user and that file exists
+    #[must_use]
+    pub fn get_shadowed_config_path() -> Option<PathBuf> {
+        sudo::invoking_user()?;
+        if Locations::current().is_custom() {
+            return None;
+        }
+        let own = sudo::root_home()?.join(".config").join("hostctl");
+        if own == get_config_dir() {
+            return None;
+        }
+        let path = find_config_file(&own);
+        path.exists().then_some(path)
+    }
+
+    /// Get the directory holding the last good copies of remote sources
+    ///
+    /// # Returns
+    /// Returns the `PathBuf` of the cache directory in the config directory
+    #[must_use]
+    pub fn get_source_cache_dir() -> PathBuf {
+        get_config_dir().join(SOURCE_CACHE_DIR)
+    }
+
+    /// Get the path to the undo journal
+    ///
+    /// # Returns
+    /// Returns the `PathBuf` of the journal file in the config directory
+    #[must_use]
+    pub fn get_journal_path() -> PathBuf {
+        get_config_dir().join(JOURNAL_FILE)
+    }
+
+    /// Load configuration from file
+    ///
+    /// The layers are merged from lowest to highest precedence: the system config,
+    /// the user config, and a project-local `.hostctl.yaml` discovered from the working
+    /// directory. A user environment hides a system environment of the same name;
+    /// project environments are added under their `<project>/<environment>` names.
+    /// Policy rules of the system and user layers both apply.
+    /// If no config file exists, returns a new empty configuration.
+    ///
+    /// # Errors
+    /// Returns an error if a file exists but cannot be read or parsed.
+    ///
+    /// # Returns
+    /// Returns the loaded configuration or a newly created empty configuration
+    pub fn load_config() -> Result<Config> {
+        FileConfigStore::discover()?.load()
+    }
+
+    /// Load and merge the configuration layers from specific locations
+    ///
+    /// A user config file written in an older format is upgraded on disk first.
+    ///
+    /// # Arguments
+    /// * `system_path` - Path of the system config file
+    /// * `user_path` - Path of the user config file
+    /// * `project` - The project configuration, if any
+    ///
+    /// # Errors
+    /// Returns an error if a file exists but cannot be read or parsed, or the user
+    /// config file cannot be upgraded.
+    pub fn load_layers(
+        system_path: &Path,
+        user_path: &Path,
+        project: Option<&Project>,
+    ) -> Result<Config> {
+        // Only the user's own file is upgraded on disk; the system file belongs to administrators
+        Self::upgrade_config_file(user_path)?;
+        Self::read_layers(system_path, user_path, project)
+    }
+
+    /// Load and merge the configuration layers without writing any file
+    ///
+    /// Files in an older format are only migrated in memory, so this is safe to call
+    /// without holding the config lock.
+    ///
+    /// # Arguments
+    /// * `system_path` - Path of the system config file
+    /// * `user_path` - Path of the user config file
+    /// * `project` - The project configuration, if any
+    ///
+    /// # Errors
+    /// Returns an error if a file exists but cannot be read or parsed.
+    pub fn read_layers(
+        system_path: &Path,
+        user_path: &Path,
+        project: Option<&Project>,
+    ) -> Result<Config> {
+        let mut config = Self::load_config_file(user_path)?;
+
+        // State (current environment, timers) only ever lives in the user layer
+        let system = Self::load_config_file(system_path)?;
+        for mut rule in system.policies {
+            rule.layer = ConfigLayer::System;
+            config.policies.push(rule);
+        }
+        for mut source in system.sources {
+            source.layer = ConfigLayer::System;
+            config.sources.push(source);
+        }
+        for mut key in system.trusted_keys {
+            key.layer = ConfigLayer::System;
+            config.trusted_keys.push(key);
+        }
+        for (name, mut env) in system.environments {
+            env.layer = ConfigLayer::System;
+            config.environments.entry(name).or_insert(env);
+        }
+
+        if let Some(project) = project {
+            project.merge_into(&mut config);
+        }
+
+        Ok(config)
+    }
+
+    /// List the configuration files of every layer and the environments they define
+    ///
+    /// Sources are ordered from lowest to highest precedence. Environments hidden by a
+    /// higher layer are still listed under the file that defines them.
+    ///
+    /// # Errors
+    /// Returns an error if a file exists but cannot be read or parsed.
+    pub fn config_sources() -> Result<Vec<ConfigSource>> {
+        let mut sources = Vec::new();
+        let mut remote = Vec::new();
+
+        for (layer, path) in [
+            (ConfigLayer::System, get_system_config_path()),
+            (ConfigLayer::User, Self::get_config_path()),
+        ] {
+            let config = Self::load_config_file(&path)?;
+            remote.extend(config.sources);
+            let mut environments: Vec<String> = config.environments.into_keys().collect();
+            environments.sort();
+            sources.push(ConfigSource {
+                layer,
+                exists: path.exists(),
+                path,
+                environments,
+            });
+        }
+
+        let cache_dir = Self::get_source_cache_dir();
+        for source in &remote {
+            let cached = source.load_cached(&cache_dir)?;
+            let environments = match &cached {
+                Some(cached) => source
+                    .parse(&cached.content)?
+                    .into_iter()
+                    .map(|env| env.name)
+                    .collect(),
+                None => Vec::new(),
+            };
+            sources.push(ConfigSource {
+                layer: ConfigLayer::Remote,
+                path: source.cache_path(&cache_dir),
+                exists: cached.is_some(),
+                environments,
+            });
+        }
+
+        if let Some(project) = Project::discover_from_cwd()? {
+            let mut environments: Vec<String> = project
+                .config
+                .environments
+                .keys()
+                .map(|name| project.qualified_name(name))
+                .collect();
+            environments.sort();
+            sources.push(ConfigSource {
+                layer: ConfigLayer::Project,
+                path: project.path.clone(),
+                exists: true,
+                environments,
+            });
+        }
+
+        Ok(sources)
+    }
+
+    /// Ensure a configuration does not modify environments of the read-only system layer
+    ///
+    /// # Arguments
+    /// * `system_path` - Path of the system config file
+    /// * `config` - The configuration about to be saved
+    ///
+    /// # Errors
+    /// Returns an error if a system environment differs from the system config file.
+    pub fn check_system_layer(system_path: &Path, config: &Config) -> Result<()> {
+        let system = Self::load_config_file(system_path)?;
+
+        for env in config
+            .environments
+            .values()
+            .filter(|env| env.layer == ConfigLayer::System)
+        {
+            let unchanged = match system.environments.get(&env.name) {
+                Some(original) => serde_yaml::to_value(original)? == serde_yaml::to_value(env)?,
+                None => false,
+            };
+            if !unchanged {
+                anyhow::bail!(
+                    "Environment '{}' is defined in the system configuration ({}) and is read-only",
+                    env.name,
+                    system_path.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load configuration from a specific file
+    ///
+    /// Files written in an older format are migrated in memory; use
+    /// `upgrade_config_file` to also rewrite them on disk.
+    /// If the file does not exist, returns a new empty configuration.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but cannot be read or parsed, or was
+    /// written by a newer hostctl.
+    pub fn load_config_file(path: &Path) -> Result<Config> {
+        Ok(Self::read_config_file(path)?.0)
+    }
+
+    /// Rewrite a config file in an older format in the current format
+    ///
+    /// The original file is copied to `<file>.v<version>.bak` before it is rewritten.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Returns
+    /// Returns the path of the backup if the file was upgraded
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, migrated, backed up or written.
+    pub fn upgrade_config_file(path: &Path) -> Result<Option<PathBuf>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let (config, original) = Self::read_config_file(path)?;
+        if original == CONFIG_VERSION {
+            return Ok(None);
+        }
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut backup = path.with_file_name(format!("{file_name}.v{original}.bak"));
+        if backup.exists() {
+            let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+            backup = path.with_file_name(format!("{file_name}.v{original}.{timestamp}.bak"));
+        }
+        fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up config file: {}", backup.display()))?;
+
+        Self::save_config_file(path, &config)?;
+        Ok(Some(backup))
+    }
+
+    /// Read, migrate and parse a config file
+    ///
+    /// # Returns
+    /// Returns the configuration and the format version the file was written in
+    fn read_config_file(path: &Path) -> Result<(Config, u32)> {
+        if !path.exists() {
+            return Ok((Config::new(), CONFIG_VERSION));
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        let (mut config, version) = Self::parse_config(path, &content)?;
+
+        if Self::config_layout(path) == ConfigLayout::Directory {
+            Self::read_environment_dir(path, &mut config)?;
+        }
+
+        Ok((config, version))
+    }
+
+    /// Layout of the configuration stored at a config file path
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    #[must_use]
+    pub fn config_layout(path: &Path) -> ConfigLayout {
+        if path.with_file_name(ENVIRONMENTS_DIR).is_dir() {
+            ConfigLayout::Directory
+        } else {
+            ConfigLayout::File
+        }
+    }
+
+    /// Add the environment files and state of the directory layout to a configuration
+    fn read_environment_dir(path: &Path, config: &mut Config) -> Result<()> {
+        let dir = path.with_file_name(ENVIRONMENTS_DIR);
+        for (name, file) in Self::environment_files(&dir)? {
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read environment file: {}", file.display()))?;
+            let mut env: Environment = ConfigFormat::from_path(&file)?
+                .parse(&content)
+                .map_err(|e| parse_error(&file, &content, e))?;
+            // The file name is authoritative
+            env.name = name;
+            config.add_environment(env);
+        }
+
+        let state_path = path.with_file_name(STATE_FILE);
+        if state_path.exists() {
+            let content = fs::read_to_string(&state_path)
+                .with_context(|| format!("Failed to read state file: {}", state_path.display()))?;
+            let state: State = serde_yaml::from_str(&content)
+                .map_err(|e| parse_error(&state_path, &content, e.into()))?;
+            config.current_environment = state.current_environment;
+            config.pending_revert = state.pending_revert;
+            config.target_environments = state.target_environments;
+        }
+
+        Ok(())
+    }
+
+    /// List the environment files of a directory with the environment names they define
+    fn environment_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+        let mut files = Vec::new();
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        for entry in entries {
+            let file = entry?.path();
+            let supported = file
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ENVIRONMENT_EXTENSIONS.contains(&ext));
+            let name = file.file_stem().and_then(|stem| stem.to_str());
+            if let (true, Some(name)) = (supported && file.is_file(), name) {
+                files.push((name.to_string(), file.clone()));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Migrate and parse the contents of a config file
+    ///
+    /// # Returns
+    /// Returns the configuration and the format version the contents were written in
+    fn parse_config(path: &Path, content: &str) -> Result<(Config, u32)> {
+        Self::parse_config_as(ConfigFormat::from_path(path)?, path, content)
+    }
+
+    /// Migrate and parse the contents of a config file in the given format
+    fn parse_config_as(format: ConfigFormat, path: &Path, content: &str) -> Result<(Config, u32)> {
+        let mut doc: Mapping = format
+            .parse(content)
+            .map_err(|e| parse_error(path, content, e))?;
+        let original = migrate_document(&mut doc)
+            .with_context(|| format!("Cannot load config file: {}", path.display()))?;
+
+        let mut config: Config = match serde_yaml::from_value(Value::Mapping(doc)) {
+            Ok(config) => config,
+            Err(e) => {
+                // Parsing the text directly locates the offending value
+                let error = format.parse::<Config>(content).err();
+                return Err(parse_error(
+                    path,
+                    content,
+                    error.unwrap_or_else(|| e.into()),
+                ));
+            }
+        };
+
+        // Environment names may be omitted in the file; the map key is authoritative
+        for (key, env) in &mut config.environments {
+            if env.name.is_empty() {
+                env.name.clone_from(key);
+            }
+        }
+
+        Ok((config, original))
+    }
+
+    /// Save configuration to file
+    ///
+    /// Each environment is written back to the layer it was loaded from. User
+    /// environments and state are written to the user config file. Project
+    /// environments are written back to the project's `.hostctl.yaml`, which is only
+    /// touched if they changed. The system layer is read-only.
+    /// If the config directory does not exist, it will be created automatically.
+    ///
+    /// # Arguments
+    /// * `config` - The configuration to save
+    ///
+    /// # Errors
+    /// Returns an error if a system environment was modified, the directory cannot be
+    /// created or a file cannot be written.
+    pub fn save_config(config: &Config) -> Result<()> {
+        FileConfigStore::discover()?.save(config)
     }
 
-    /// Get the path to the config directory
+    /// Save the user layer of a configuration to a specific file
+    ///
+    /// Environments loaded from other layers are left out. Existing YAML files are
+    /// edited in place, so comments and formatting of unchanged parts survive.
+    /// If the parent directory does not exist, it will be created automatically.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    /// * `config` - The configuration to save
+    ///
+    /// # Errors
+    /// Returns an error if the directory cannot be created or the file cannot be written.
+    pub fn save_config_file(path: &Path, config: &Config) -> Result<()> {
+        // Create config directory (if it doesn't exist)
+        if let Some(config_dir) = path.parent() {
+            fs::create_dir_all(config_dir).with_context(|| {
+                format!(
+                    "Failed to create config directory: {}",
+                    config_dir.display()
+                )
+            })?;
+        }
+
+        let mut user = config.layer_view(ConfigLayer::User);
+        user.version = CONFIG_VERSION;
+        let snapshot = serde_yaml::to_string(&user)?;
+
+        if Self::config_layout(path) == ConfigLayout::Directory {
+            Self::save_environment_dir(path, user)?;
+        } else {
+            write_document(path, &user, |text| {
+                let (mut current, version) = Self::parse_config(path, text)?;
+                current.version = version;
+                Ok(current)
+            })?;
+        }
+
+        let snapshot_path = Self::snapshot_path(path);
+        if fs::read_to_string(&snapshot_path).ok().as_deref() != Some(snapshot.as_str()) {
+            fs::write(&snapshot_path, snapshot).with_context(|| {
+                format!("Failed to write snapshot: {}", snapshot_path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Path of the last-known-good copy of a config file
+    ///
+    /// Every successful save updates the copy, so a config file that gets damaged,
+    /// e.g. by a bad manual edit, can be restored with `restore_snapshot`.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    #[must_use]
+    pub fn snapshot_path(path: &Path) -> PathBuf {
+        path.with_file_name(SNAPSHOT_FILE)
+    }
+
+    /// Load the last-known-good copy of a config file
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Returns
+    /// Returns `None` if no copy has been saved yet
+    ///
+    /// # Errors
+    /// Returns an error if the copy cannot be read or parsed.
+    pub fn load_snapshot(path: &Path) -> Result<Option<Config>> {
+        let snapshot_path = Self::snapshot_path(path);
+        if !snapshot_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&snapshot_path)
+            .with_context(|| format!("Failed to read snapshot: {}", snapshot_path.display()))?;
+        let (config, _) = Self::parse_config_as(ConfigFormat::Yaml, &snapshot_path, &content)?;
+        Ok(Some(config))
+    }
+
+    /// Replace a config file with its last-known-good copy
+    ///
+    /// The config file and, in the directory layout, all environment files are
+    /// backed up first, so changes made since the copy was saved are not lost.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Returns
+    /// Returns the paths of the backups
+    ///
+    /// # Errors
+    /// Returns an error if there is no copy or a file cannot be backed up or written.
+    pub fn restore_snapshot(path: &Path) -> Result<Vec<PathBuf>> {
+        let snapshot = Self::load_snapshot(path)?.with_context(|| {
+            format!("No last good copy of {} has been saved yet", path.display())
+        })?;
+
+        let mut files = vec![path.to_path_buf()];
+        if Self::config_layout(path) == ConfigLayout::Directory {
+            let dir = path.with_file_name(ENVIRONMENTS_DIR);
+            files.extend(
+                Self::environment_files(&dir)?
+                    .into_iter()
+                    .map(|(_, file)| file),
+            );
+        }
+        let backups = files
+            .iter()
+            .filter(|file| file.exists())
+            .map(|file| backup_file(file))
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::save_config_file(path, &snapshot)?;
+        Ok(backups)
+    }
+
+    /// Salvage the parseable environments and settings of a damaged config file
+    ///
+    /// Each environment of a YAML config file is parsed on its own, so only the
+    /// damaged ones are lost; in the directory layout, damaged environment files are
+    /// left out. Environments that cannot be salvaged are taken from the
+    /// last-known-good copy if it has them. Damaged files are backed up before the
+    /// repaired configuration is saved.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    ///
+    /// # Returns
+    /// Returns `None` if the configuration loads fine and needs no repair
+    ///
+    /// # Errors
+    /// Returns an error if the configuration fails to load for a reason other than a
+    /// damaged file, a damaged file is not YAML, or the result cannot be saved.
+    pub fn repair_config_file(path: &Path) -> Result<Option<Repair>> {
+        let error = match Self::read_config_file(path) {
+            Ok(_) => return Ok(None),
+            Err(e) if e.downcast_ref::<ParseError>().is_some() => e,
+            Err(e) => return Err(e),
+        };
+
+        let mut repair = Repair::default();
+        let mut lost_environments = Vec::new();
+        let format = ConfigFormat::from_path(path)?;
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+
+        let mut config = match Self::parse_config_as(format, path, &content) {
+            Ok((config, _)) => config,
+            Err(_) if format == ConfigFormat::Yaml => {
+                let salvage = salvage_yaml(&content);
+                lost_environments = salvage.lost_environments;
+                repair.lost = salvage.lost;
+                let mut doc = salvage.doc;
+                doc.insert("environments".into(), Value::Mapping(salvage.environments));
+                repair.backups.push(backup_file(path)?);
+                Self::parse_config_as(format, path, &serde_yaml::to_string(&doc)?)?.0
+            }
+            Err(_) => {
+                return Err(error.context(format!(
+                    "Only YAML config files can be salvaged; restore the last good copy of {} instead",
+                    path.display()
+                )));
+            }
+        };
+
+        if Self::config_layout(path) == ConfigLayout::Directory {
+            let dir = path.with_file_name(ENVIRONMENTS_DIR);
+            for (name, file) in Self::environment_files(&dir)? {
+                let env = fs::read_to_string(&file)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|content| {
+                        ConfigFormat::from_path(&file)?.parse::<Environment>(&content)
+                    });
+                match env {
+                    Ok(mut env) => {
+                        env.name.clone_from(&name);
+                        config.add_environment(env);
+                    }
+                    Err(_) => {
+                        repair.backups.push(backup_file(&file)?);
+                        lost_environments.push(name);
+                    }
+                }
+            }
+
+            // The state only records the active environment and a pending timer
+            let state = fs::read_to_string(path.with_file_name(STATE_FILE))
+                .ok()
+                .and_then(|content| serde_yaml::from_str::<State>(&content).ok());
+            if let Some(state) = state {
+                config.current_environment = state.current_environment;
+                config.pending_revert = state.pending_revert;
+                config.target_environments = state.target_environments;
+            }
+        }
+
+        repair.recovered = config.environments.keys().cloned().collect();
+        let snapshot = Self::load_snapshot(path).ok().flatten();
+        for name in lost_environments {
+            match snapshot.as_ref().and_then(|s| s.environments.get(&name)) {
+                Some(env) => {
+                    config.add_environment(env.clone());
+                    repair.restored.push(name);
+                }
+                None => repair.lost.push(format!("environment '{name}'")),
+            }
+        }
+
+        Self::save_config_file(path, &config)?;
+        Ok(Some(repair))
+    }
+
+    /// Save a configuration in the directory layout
+    ///
+    /// Each environment is written to its own file, which is only rewritten if the
+    /// environment changed. Files of removed environments are deleted.
+    fn save_environment_dir(path: &Path, mut config: Config) -> Result<()> {
+        let dir = path.with_file_name(ENVIRONMENTS_DIR);
+        let existing = Self::environment_files(&dir)?;
+
+        for (name, env) in &config.environments {
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                anyhow::bail!("Environment name '{name}' cannot be used as a file name");
+            }
+            let file = existing
+                .iter()
+                .find(|(existing_name, _)| existing_name == name)
+                .map_or_else(
+                    || dir.join(format!("{name}.yaml")),
+                    |(_, file)| file.clone(),
+                );
+
+            write_document(&file, env, |text| {
+                let mut current: Environment = ConfigFormat::from_path(&file)?.parse(text)?;
+                current.name.clone_from(name);
+                Ok(current)
+            })?;
+        }
+
+        for (name, file) in &existing {
+            if !config.environments.contains_key(name) {
+                fs::remove_file(file)
+                    .with_context(|| format!("Failed to remove {}", file.display()))?;
+            }
+        }
+
+        let state = State {
+            current_environment: config.current_environment.take(),
+            pending_revert: config.pending_revert.take(),
+            target_environments: std::mem::take(&mut config.target_environments),
+        };
+        let state_path = path.with_file_name(STATE_FILE);
+        fs::write(&state_path, serde_yaml::to_string(&state)?)
+            .with_context(|| format!("Failed to write state file: {}", state_path.display()))?;
+
+        config.environments.clear();
+        write_document(path, &config, |text| {
+            let (mut current, version) = Self::parse_config(path, text)?;
+            current.version = version;
+            Ok(current)
+        })
+    }
+
+    /// Switch the user configuration between a single file and the directory layout
+    ///
+    /// Files that are no longer used are kept with a `.bak` suffix.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file
+    /// * `layout` - The layout to switch to
+    ///
+    /// # Returns
+    /// Returns `false` if the configuration already uses the layout
+    ///
+    /// # Errors
+    /// Returns an error if the configuration cannot be read or written.
+    pub fn set_layout(path: &Path, layout: ConfigLayout) -> Result<bool> {
+        if Self::config_layout(path) == layout {
+            return Ok(false);
+        }
+
+        let config = Self::load_config_file(path)?;
+        let dir = path.with_file_name(ENVIRONMENTS_DIR);
+        match layout {
+            ConfigLayout::Directory => {
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+            }
+            ConfigLayout::File => {
+                for retired in [dir, path.with_file_name(STATE_FILE)] {
+                    if retired.exists() {
+                        let file_name = retired.file_name().unwrap_or_default().to_string_lossy();
+                        let backup = retired.with_file_name(format!("{file_name}.bak"));
+                        fs::rename(&retired, &backup).with_context(|| {
+                            format!("Failed to move {} aside", retired.display())
+                        })?;
+                    }
+                }
+            }
+        }
+
+        Self::save_config_file(path, &config)?;
+        Ok(true)
+    }
+
+    /// Convert a config file to another format
+    ///
+    /// The converted file is written next to the original with the new extension, and
+    /// the original is renamed to `<file>.bak` so only one config file remains.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the config file to convert
+    /// * `format` - The target format
+    ///
+    /// # Returns
+    /// Returns the path of the converted file
+    ///
+    /// # Errors
+    /// Returns an error if the file is missing or already in the target format, the
+    /// target file already exists, or a file cannot be read or written.
+    pub fn convert_config_file(path: &Path, format: ConfigFormat) -> Result<PathBuf> {
+        if !path.exists() {
+            anyhow::bail!("Config file does not exist: {}", path.display());
+        }
+        if ConfigFormat::from_path(path)? == format {
+            anyhow::bail!(
+                "Config file is already in {format} format: {}",
+                path.display()
+            );
+        }
+
+        let target = path.with_extension(format.extension());
+        if target.exists() {
+            anyhow::bail!("Refusing to overwrite existing file: {}", target.display());
+        }
+
+        let config = Self::load_config_file(path)?;
+        Self::save_config_file(&target, &config)?;
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let backup = path.with_file_name(format!("{file_name}.bak"));
+        fs::rename(path, &backup)
+            .with_context(|| format!("Failed to move {} aside", path.display()))?;
+
+        Ok(target)
+    }
+
+    /// Merge three versions of a config or environment file, as a git merge driver
+    ///
+    /// The merged result is written to `ours`, edited in place where possible. Files
+    /// in an `environments` directory are merged as single environments; other files
+    /// as complete configurations. Empty files (e.g. a missing common ancestor) are
+    /// treated as empty configurations.
+    ///
+    /// # Arguments
+    /// * `base` - The common ancestor version
+    /// * `ours` - Our version, replaced by the merge result
+    /// * `theirs` - Their version
+    /// * `name` - Path of the file in the repository, used to detect its format and kind
+    ///
+    /// # Returns
+    /// Returns the conflicts; conflicting items keep our side
+    ///
+    /// # Errors
+    /// Returns an error if a version cannot be read or parsed, or the result cannot be written.
+    pub fn merge_files(
+        base: &Path,
+        ours: &Path,
+        theirs: &Path,
+        name: &Path,
+    ) -> Result<Vec<Conflict>> {
+        let format = ConfigFormat::from_path(name).unwrap_or_default();
+        let read = |path: &Path| {
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))
+        };
+        let (base_text, ours_text, theirs_text) = (read(base)?, read(ours)?, read(theirs)?);
+
+        let in_environments_dir =
+            name.parent().and_then(Path::file_name) == Some(std::ffi::OsStr::new(ENVIRONMENTS_DIR));
+        if in_environments_dir {
+            let env_name = name
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let parse = |text: &str| -> Result<Environment> {
+                let mut env: Environment = format.parse(text).with_context(|| {
+                    format!("Failed to parse environment file: {}", name.display())
+                })?;
+                env.name.clone_from(&env_name);
+                Ok(env)
+            };
+            let parse_version = |text: &str| -> Result<Option<Environment>> {
+                if text.trim().is_empty() {
+                    return Ok(None);
+                }
+                parse(text).map(Some)
+            };
+
+            let merged = merge::merge_environment(
+                &env_name,
+                parse_version(&base_text)?.as_ref(),
+                parse_version(&ours_text)?.as_ref(),
+                parse_version(&theirs_text)?.as_ref(),
+            );
+            match merged.value {
+                Some(env) => write_document_as(ours, format, &env, parse)?,
+                None => fs::write(ours, "")
+                    .with_context(|| format!("Failed to write file: {}", ours.display()))?,
+            }
+            return Ok(merged.conflicts);
+        }
+
+        let parse = |text: &str| -> Result<Config> {
+            if text.trim().is_empty() {
+                return Ok(Config::new());
+            }
+            Ok(Self::parse_config_as(format, name, text)?.0)
+        };
+        let merged = merge::merge_configs(
+            &parse(&base_text)?,
+            &parse(&ours_text)?,
+            &parse(&theirs_text)?,
+        );
+        write_document_as(ours, format, &merged.value, parse)?;
+        Ok(merged.conflicts)
+    }
+
+    /// Ensure config directory exists
+    ///
+    /// If the config directory does not exist, it will be created automatically.
+    ///
+    /// # Errors
+    /// Returns an error if the directory cannot be created.
+    pub fn ensure_config_dir() -> Result<()> {
+        let config_dir = get_config_dir();
+        if !config_dir.exists() {
+            fs::create_dir_all(&config_dir).with_context(|| {
+                format!(
+                    "Failed to create config directory: {}",
+                    config_dir.display()
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Exclusive access to a configuration store, released when dropped
+pub struct StoreLock<'a> {
+    _guard: Box<dyn LockGuard + 'a>,
+}
+
+/// Anything held for the duration of a lock
+trait LockGuard {}
+
+impl<T> LockGuard for T {}
+
+impl<'a> StoreLock<'a> {
+    /// Wrap a value that holds a lock until it is dropped
+    ///
+    /// # Arguments
+    /// * `guard` - The value releasing the lock on drop, e.g. a mutex guard or locked file
+    #[must_use]
+    pub fn new<G: 'a>(guard: G) -> Self {
+        Self {
+            _guard: Box::new(guard),
+        }
+    }
+}
+
+/// Backend that configurations are loaded from and saved to
+///
+/// Commands load the configuration, modify it and save it again; holding the lock
+/// for the whole cycle keeps concurrent hostctl invocations from overwriting each
+/// other's changes.
+pub trait ConfigStore {
+    /// Load the merged configuration of all layers
+    ///
+    /// # Errors
+    /// Returns an error if the configuration cannot be read or parsed.
+    fn load(&self) -> Result<Config>;
+
+    /// Save a configuration, writing every environment back to its layer
+    ///
+    /// # Arguments
+    /// * `config` - The configuration to save
+    ///
+    /// # Errors
+    /// Returns an error if a read-only layer was modified or the configuration cannot be written.
+    fn save(&self, config: &Config) -> Result<()>;
+
+    /// Acquire exclusive access to the store, waiting for other holders
+    ///
+    /// # Errors
+    /// Returns an error if the lock cannot be acquired.
+    fn lock(&self) -> Result<StoreLock<'_>>;
+}
+
+/// Configuration store backed by the config files on disk
+#[derive(Debug, Clone)]
+pub struct FileConfigStore {
+    /// Path of the user config file
+    user_path: PathBuf,
+    /// Path of the read-only system config file
+    system_path: Option<PathBuf>,
+    /// Project configuration merged on top, if any
+    project: Option<Project>,
+    /// Directory holding the cached copies of remote sources
+    cache_dir: PathBuf,
+    /// Whether loading leaves files in an older format as they are
+    read_only: bool,
+}
+
+impl FileConfigStore {
+    /// Create a store for a user config file, without system or project layers
     ///
-    /// # Returns
-    /// Returns the `PathBuf` of the config directory
+    /// Remote sources are cached next to the config file.
+    ///
+    /// # Arguments
+    /// * `user_path` - Path of the user config file
     #[must_use]
-    pub fn get_config_dir_path() -> PathBuf {
-        get_config_dir()
+    pub fn new(user_path: PathBuf) -> Self {
+        let cache_dir = user_path.with_file_name(SOURCE_CACHE_DIR);
+        Self {
+            user_path,
+            system_path: None,
+            project: None,
+            cache_dir,
+            read_only: false,
+        }
     }
 
-    /// Load configuration from file
+    /// Create a store for the default locations, as used by the hostctl command
     ///
-    /// If the config file does not exist, returns a new empty configuration.
+    /// Includes the system config and the project discovered from the working directory.
     ///
     /// # Errors
-    /// Returns an error if the file exists but cannot be read or parsed.
-    ///
-    /// # Returns
-    /// Returns the loaded configuration or a newly created empty configuration
-    pub fn load_config() -> Result<Config> {
-        let config_path = Self::get_config_path();
-
-        if !config_path.exists() {
-            return Ok(Config::new());
+    /// Returns an error if a discovered project file cannot be loaded.
+    pub fn discover() -> Result<Self> {
+        let mut store = Self::new(ConfigStorage::get_config_path())
+            .with_system_config(get_system_config_path());
+        store.cache_dir = ConfigStorage::get_source_cache_dir();
+        if let Some(project) = Project::discover_from_cwd()? {
+            store = store.with_project(project);
         }
-
-        let content = fs::read_to_string(&config_path)
-            .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
-
-        let config: Config =
-            serde_yaml::from_str(&content).with_context(|| "Failed to parse config file")?;
-
-        Ok(config)
+        Ok(store)
     }
 
-    /// Save configuration to file
+    /// Add a read-only system config file beneath the user layer
     ///
-    /// If the config directory does not exist, it will be created automatically.
+    /// # Arguments
+    /// * `path` - Path of the system config file
+    #[must_use]
+    pub fn with_system_config(mut self, path: PathBuf) -> Self {
+        self.system_path = Some(path);
+        self
+    }
+
+    /// Merge a project configuration on top of the user layer
     ///
     /// # Arguments
-    /// * `config` - The configuration to save
+    /// * `project` - The project configuration
+    #[must_use]
+    pub fn with_project(mut self, project: Project) -> Self {
+        self.project = Some(project);
+        self
+    }
+
+    /// Load without upgrading a user config file in an older format on disk
     ///
-    /// # Errors
-    /// Returns an error if the directory cannot be created or the file cannot be written.
-    pub fn save_config(config: &Config) -> Result<()> {
-        let config_dir = get_config_dir();
-        let config_path = Self::get_config_path();
+    /// For callers that do not hold the lock and must not have side effects, such
+    /// as shell completion.
+    #[must_use]
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
 
-        // Create config directory (if it doesn't exist)
-        fs::create_dir_all(&config_dir).with_context(|| {
-            format!(
-                "Failed to create config directory: {}",
-                config_dir.display()
-            )
-        })?;
+    /// Path of the user config file
+    #[must_use]
+    pub fn user_path(&self) -> &Path {
+        &self.user_path
+    }
+
+    /// Path of the lock file next to the user config file
+    #[must_use]
+    pub fn lock_path(&self) -> PathBuf {
+        self.user_path.with_file_name(LOCK_FILE)
+    }
+}
+
+impl ConfigStore for FileConfigStore {
+    fn load(&self) -> Result<Config> {
+        let system_path = self.system_path.clone().unwrap_or_default();
+        let mut config = if self.read_only {
+            ConfigStorage::read_layers(&system_path, &self.user_path, self.project.as_ref())?
+        } else {
+            ConfigStorage::load_layers(&system_path, &self.user_path, self.project.as_ref())?
+        };
+        sources::merge_cached(&mut config, &self.cache_dir);
+        Ok(config)
+    }
 
-        let content =
-            serde_yaml::to_string(config).with_context(|| "Failed to serialize config")?;
+    fn save(&self, config: &Config) -> Result<()> {
+        if let Some(system_path) = &self.system_path {
+            ConfigStorage::check_system_layer(system_path, config)?;
+        }
+        ConfigStorage::save_config_file(&self.user_path, config)?;
 
-        fs::write(&config_path, content)
-            .with_context(|| format!("Failed to write config file: {}", config_path.display()))?;
+        if let Some(project) = &self.project {
+            project.save_changes(config)?;
+        }
 
         Ok(())
     }
 
-    /// Ensure config directory exists
+    /// Lock the config directory with an advisory file lock
     ///
-    /// If the config directory does not exist, it will be created automatically.
+    /// The lock is shared with other hostctl processes and released when the
+    /// returned guard is dropped or the process exits.
+    fn lock(&self) -> Result<StoreLock<'_>> {
+        let path = self.lock_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file: {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) => {
+                eprintln!("Waiting for another hostctl process to finish...");
+                file.lock()
+                    .with_context(|| format!("Failed to lock {}", path.display()))?;
+            }
+            Err(fs::TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+        }
+        Ok(StoreLock::new(file))
+    }
+}
+
+/// Configuration store kept in memory, for tests and embedding applications
+///
+/// Saved configurations are stored as they are; layers are not separated.
+#[derive(Debug, Default)]
+pub struct MemoryConfigStore {
+    config: std::sync::Mutex<Config>,
+    lock: std::sync::Mutex<()>,
+}
+
+impl MemoryConfigStore {
+    /// Create a store holding a configuration
     ///
-    /// # Errors
-    /// Returns an error if the directory cannot be created.
-    pub fn ensure_config_dir() -> Result<()> {
-        let config_dir = get_config_dir();
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir).with_context(|| {
-                format!(
-                    "Failed to create config directory: {}",
-                    config_dir.display()
-                )
-            })?;
+    /// # Arguments
+    /// * `config` - The initial configuration
+    #[must_use]
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: std::sync::Mutex::new(config),
+            lock: std::sync::Mutex::default(),
         }
+    }
+}
+
+impl ConfigStore for MemoryConfigStore {
+    fn load(&self) -> Result<Config> {
+        let config = self
+            .config
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Configuration store is poisoned"))?;
+        Ok(config.clone())
+    }
+
+    fn save(&self, config: &Config) -> Result<()> {
+        let mut stored = self
+            .config
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Configuration store is poisoned"))?;
+        stored.clone_from(config);
         Ok(())
     }
+
+    fn lock(&self) -> Result<StoreLock<'_>> {
+        let guard = self
+            .lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Configuration store lock is poisoned"))?;
+        Ok(StoreLock::new(guard))
+    }
 }
 
 #[cfg(test)]
@@ -238,6 +1903,413 @@ mod tests {
         assert!(config.get_environment("qa").is_none());
     }
 
+    #[test]
+    fn test_dynamic_entry_yaml() {
+        let yaml = r"current_environment: null
+environments:
+  vm:
+    name: vm
+    description: null
+    entries:
+      - hostname: vm.local
+        comment: null
+        source:
+          type: command
+          command: vagrant ssh -c 'hostname -I'
+      - hostname: bridge.local
+        comment: null
+        source:
+          type: file
+          path: /run/bridge.ip
+          line: 2
+";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let env = config.get_environment("vm").unwrap();
+
+        // Entries without an explicit ip start out unresolved
+        assert!(env.entries.iter().all(|e| e.ip.is_unspecified()));
+        assert!(env.entries.iter().all(HostEntry::is_dynamic));
+
+        let reserialized = serde_yaml::to_string(&config).unwrap();
+        assert!(reserialized.contains("type: command"));
+        assert!(reserialized.contains("line: 2"));
+        let roundtrip: Config = serde_yaml::from_str(&reserialized).unwrap();
+        assert_eq!(
+            roundtrip.get_environment("vm").unwrap().entries[1].source,
+            env.entries[1].source
+        );
+    }
+
+    #[test]
+    fn test_save_config_file_keeps_only_user_layer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hostctl").join("config.yaml");
+
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        let mut project_env = Environment::new("webapp/dev".to_string());
+        project_env.layer = ConfigLayer::Project;
+        config.add_environment(project_env);
+        config.current_environment = Some("webapp/dev".to_string());
+
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+        let loaded = ConfigStorage::load_config_file(&path).unwrap();
+
+        assert_eq!(loaded.environments.len(), 1);
+        assert!(loaded.get_environment("dev").is_some());
+        assert_eq!(loaded.current_environment, Some("webapp/dev".to_string()));
+    }
+
+    #[test]
+    fn test_load_layers_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.yaml");
+        let user_path = dir.path().join("user.yaml");
+        fs::write(
+            &system_path,
+            "current_environment: corp\npolicies:\n  - name: corp-only\n    hostnames: ['*.corp']\n    allow_networks: ['10.0.0.0/8']\nenvironments:\n  corp:\n    entries: []\n  dev:\n    description: System dev\n    entries: []\n",
+        )
+        .unwrap();
+        fs::write(
+            &user_path,
+            "current_environment: null\nenvironments:\n  dev:\n    description: My dev\n    entries: []\n",
+        )
+        .unwrap();
+
+        let config = ConfigStorage::load_layers(&system_path, &user_path, None).unwrap();
+
+        assert_eq!(config.environments.len(), 2);
+        let corp = config.get_environment("corp").unwrap();
+        assert_eq!(corp.layer, ConfigLayer::System);
+        let dev = config.get_environment("dev").unwrap();
+        assert_eq!(dev.layer, ConfigLayer::User);
+        assert_eq!(dev.description, Some("My dev".to_string()));
+        // State is never taken from the system layer
+        assert_eq!(config.current_environment, None);
+        assert_eq!(config.policies.len(), 1);
+        assert_eq!(config.policies[0].layer, ConfigLayer::System);
+
+        // System environments and policies are not written to the user file
+        ConfigStorage::save_config_file(&user_path, &config).unwrap();
+        let user = ConfigStorage::load_config_file(&user_path).unwrap();
+        assert!(user.get_environment("corp").is_none());
+        assert!(user.policies.is_empty());
+    }
+
+    #[test]
+    fn test_check_system_layer_rejects_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.yaml");
+        let user_path = dir.path().join("user.yaml");
+        fs::write(&system_path, "environments:\n  corp:\n    entries: []\n").unwrap();
+
+        let mut config = ConfigStorage::load_layers(&system_path, &user_path, None).unwrap();
+        ConfigStorage::check_system_layer(&system_path, &config).unwrap();
+
+        config
+            .get_environment_mut("corp")
+            .unwrap()
+            .add_entry(HostEntry::new(
+                std::net::IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                "intranet.corp".to_string(),
+            ));
+        let err = ConfigStorage::check_system_layer(&system_path, &config).unwrap_err();
+        assert!(err.to_string().contains("read-only"));
+    }
+
+    #[test]
+    fn test_migrations_cover_every_version() {
+        assert_eq!(MIGRATIONS.len(), CONFIG_VERSION as usize);
+    }
+
+    #[test]
+    fn test_upgrade_unversioned_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let original = "current_environment: dev\nenvironments:\n  dev:\n    name: dev\n    description: null\n    entries: []\n";
+        fs::write(&path, original).unwrap();
+
+        // Loading migrates in memory without touching the file
+        let config = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+
+        let backup = ConfigStorage::upgrade_config_file(&path).unwrap().unwrap();
+        assert_eq!(backup, dir.path().join("config.yaml.v0.bak"));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+
+        let upgraded = fs::read_to_string(&path).unwrap();
+        assert!(upgraded.starts_with(&format!("version: {CONFIG_VERSION}\n")));
+        let config = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(config.current_environment, Some("dev".to_string()));
+        assert!(config.get_environment("dev").is_some());
+
+        // Current files are left alone
+        assert!(ConfigStorage::upgrade_config_file(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_only_store_does_not_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let original = "current_environment: dev\nenvironments:\n  dev:\n    name: dev\n    description: null\n    entries: []\n";
+        fs::write(&path, original).unwrap();
+
+        let config = FileConfigStore::new(path.clone())
+            .read_only()
+            .load()
+            .unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.get_environment("dev").is_some());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!dir.path().join("config.yaml.v0.bak").exists());
+
+        FileConfigStore::new(path.clone()).load().unwrap();
+        assert_ne!(fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_newer_config_version_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let newer = format!(
+            "version: {}\ncurrent_environment: null\nenvironments: {{}}\n",
+            CONFIG_VERSION + 1
+        );
+        fs::write(&path, &newer).unwrap();
+
+        let err = ConfigStorage::load_config_file(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("newer hostctl"));
+        assert!(ConfigStorage::upgrade_config_file(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[test]
+    fn test_system_config_is_not_upgraded_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.yaml");
+        let original = "current_environment: null\nenvironments:\n  corp:\n    entries: []\n";
+        fs::write(&system_path, original).unwrap();
+
+        let config =
+            ConfigStorage::load_layers(&system_path, &dir.path().join("user.yaml"), None).unwrap();
+        assert!(config.get_environment("corp").is_some());
+        assert_eq!(fs::read_to_string(&system_path).unwrap(), original);
+    }
+
+    /// A configuration exercising every field of the file format
+    fn full_config() -> Config {
+        let mut config: Config = serde_yaml::from_str(
+            r"
+current_environment: dev
+pending_revert:
+  previous_environment: null
+  deadline: 2026-01-01T12:00:00Z
+policies:
+  - name: corp-internal
+    severity: warning
+    hostnames: ['*.corp.example.com']
+    allow_networks: ['10.0.0.0/8', 'fd00::/8']
+targets:
+  devbox: /srv/devbox/etc/hosts
+  fixture: fixtures/hosts
+target_environments:
+  devbox: dev
+environments:
+  dev:
+    name: dev
+    description: Development
+    protected: true
+    entries:
+      - ip: 127.0.0.1
+        hostname: api.local
+        comment: API
+      - ip: ::1
+        hostname: v6.local
+      - hostname: laptop.local
+        source:
+          type: file
+          path: /tmp/ip
+          line: 2
+      - hostname: www.local
+        target: api.local
+  empty:
+    name: empty
+    entries: []
+",
+        )
+        .unwrap();
+        config.version = CONFIG_VERSION;
+        config
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = full_config();
+        let expected = serde_yaml::to_value(&config).unwrap();
+
+        for format in [ConfigFormat::Yaml, ConfigFormat::Toml, ConfigFormat::Json] {
+            let path = dir.path().join(format!("config.{format}"));
+            ConfigStorage::save_config_file(&path, &config).unwrap();
+            let loaded = ConfigStorage::load_config_file(&path).unwrap();
+            assert_eq!(
+                serde_yaml::to_value(&loaded).unwrap(),
+                expected,
+                "{format} round trip"
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let yaml_path = dir.path().join("config.yaml");
+        ConfigStorage::save_config_file(&yaml_path, &full_config()).unwrap();
+
+        let toml_path = ConfigStorage::convert_config_file(&yaml_path, ConfigFormat::Toml).unwrap();
+        assert_eq!(toml_path, dir.path().join("config.toml"));
+        assert!(!yaml_path.exists());
+        assert!(dir.path().join("config.yaml.bak").exists());
+        assert_eq!(find_config_file(dir.path()), toml_path);
+
+        let loaded = ConfigStorage::load_config_file(&toml_path).unwrap();
+        assert_eq!(loaded.current_environment, Some("dev".to_string()));
+        assert_eq!(loaded.get_environment("dev").unwrap().entries.len(), 4);
+
+        assert!(ConfigStorage::convert_config_file(&toml_path, ConfigFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn test_save_config_file_keeps_comments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let original = "\
+version: 1
+# Switched by the deploy scripts
+current_environment: null
+environments:
+  # Local stack
+  dev:
+    entries:
+    - ip: 127.0.0.1
+      hostname: api.local # API server
+";
+        fs::write(&path, original).unwrap();
+
+        let mut config = ConfigStorage::load_config_file(&path).unwrap();
+        config
+            .get_environment_mut("dev")
+            .unwrap()
+            .add_entry(HostEntry::new(
+                std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                "web.local".to_string(),
+            ));
+        config.current_environment = Some("dev".to_string());
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("# Switched by the deploy scripts\ncurrent_environment: dev\n"));
+        assert!(saved.contains("  # Local stack\n  dev:\n"));
+        assert!(saved.contains("      hostname: api.local # API server\n"));
+        assert!(saved.contains("    - ip: 127.0.0.1\n      hostname: web.local\n"));
+
+        let reloaded = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(reloaded.get_environment("dev").unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn test_environment_directory_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let mut config = full_config();
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+
+        assert!(ConfigStorage::set_layout(&path, ConfigLayout::Directory).unwrap());
+        assert!(!ConfigStorage::set_layout(&path, ConfigLayout::Directory).unwrap());
+        assert_eq!(ConfigStorage::config_layout(&path), ConfigLayout::Directory);
+
+        let env_dir = dir.path().join("environments");
+        assert!(env_dir.join("dev.yaml").is_file());
+        assert!(env_dir.join("empty.yaml").is_file());
+        let state = fs::read_to_string(dir.path().join("state.yaml")).unwrap();
+        assert!(state.contains("current_environment: dev"));
+        assert!(state.contains("devbox: dev"));
+        let main = fs::read_to_string(&path).unwrap();
+        assert!(!main.contains("dev:"));
+        assert!(main.contains("corp-internal"));
+        assert!(main.contains("fixture: fixtures/hosts"));
+
+        let loaded = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(
+            serde_yaml::to_value(&loaded).unwrap(),
+            serde_yaml::to_value(&config).unwrap()
+        );
+
+        // Only changed environments are rewritten, removed ones are deleted
+        let dev_file = env_dir.join("dev.yaml");
+        let annotated = format!(
+            "# Shared dev hosts\n{}",
+            fs::read_to_string(&dev_file).unwrap()
+        );
+        fs::write(&dev_file, &annotated).unwrap();
+        config.remove_environment("empty");
+        config.current_environment = None;
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+
+        assert_eq!(fs::read_to_string(&dev_file).unwrap(), annotated);
+        assert!(!env_dir.join("empty.yaml").exists());
+        assert!(
+            fs::read_to_string(dir.path().join("state.yaml"))
+                .unwrap()
+                .contains("current_environment: null")
+        );
+
+        // Back to a single file
+        assert!(ConfigStorage::set_layout(&path, ConfigLayout::File).unwrap());
+        assert_eq!(ConfigStorage::config_layout(&path), ConfigLayout::File);
+        assert!(dir.path().join("environments.bak").is_dir());
+        let single = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(single.environments.len(), 1);
+        assert!(single.get_environment("dev").is_some());
+    }
+
+    #[test]
+    fn test_environments_are_sorted_on_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut config = Config::new();
+        for name in ["zeta", "alpha", "mid"] {
+            config.add_environment(Environment::new(name.to_string()));
+        }
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        let positions: Vec<usize> = ["\"alpha\"", "\"mid\"", "\"zeta\""]
+            .iter()
+            .map(|name| saved.find(name).unwrap())
+            .collect();
+        assert!(positions.is_sorted());
+    }
+
+    #[test]
+    fn test_load_config_file_fills_missing_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        fs::write(
+            &path,
+            "current_environment: null\nenvironments:\n  dev:\n    description: null\n    entries: []\n",
+        )
+        .unwrap();
+
+        let config = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(config.get_environment("dev").unwrap().name, "dev");
+
+        // Missing files yield an empty configuration
+        let missing = ConfigStorage::load_config_file(&dir.path().join("missing.yaml")).unwrap();
+        assert!(missing.environments.is_empty());
+    }
+
     #[test]
     fn test_yaml_serialization_format() {
         let mut config = Config::new();
@@ -265,4 +2337,291 @@ mod tests {
         assert!(yaml.contains("app.demo"));
         assert!(yaml.contains("Application server"));
     }
+
+    #[test]
+    fn test_merge_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+        let base = write(
+            "base",
+            "version: 1\nenvironments:\n  dev:\n    entries:\n    - ip: 127.0.0.1\n      hostname: app.local\n",
+        );
+        let ours = write(
+            "ours",
+            "# Team environments\nversion: 1\nenvironments:\n  dev:\n    entries:\n    - ip: 127.0.0.1\n      hostname: app.local\n    - ip: 10.0.0.1\n      hostname: api.local\n",
+        );
+        let theirs = write(
+            "theirs",
+            "version: 1\nenvironments:\n  dev:\n    entries:\n    - ip: 127.0.0.1\n      hostname: app.local\n    - ip: 10.0.0.2\n      hostname: db.local\n",
+        );
+
+        let conflicts =
+            ConfigStorage::merge_files(&base, &ours, &theirs, Path::new("config.yaml")).unwrap();
+        assert!(conflicts.is_empty());
+
+        let merged = fs::read_to_string(&ours).unwrap();
+        assert!(merged.starts_with("# Team environments\n"));
+        let config = ConfigStorage::parse_config(Path::new("config.yaml"), &merged)
+            .unwrap()
+            .0;
+        let dev = config.get_environment("dev").unwrap();
+        let hostnames: Vec<&str> = dev.entries.iter().map(|e| e.hostname.as_str()).collect();
+        assert_eq!(hostnames, ["app.local", "api.local", "db.local"]);
+
+        // Environment files of the directory layout are merged as single environments
+        let base = write(
+            "env_base",
+            "entries:\n- ip: 127.0.0.1\n  hostname: app.local\n",
+        );
+        let ours = write(
+            "env_ours",
+            "entries:\n- ip: 10.0.0.1\n  hostname: app.local\n",
+        );
+        let theirs = write(
+            "env_theirs",
+            "entries:\n- ip: 10.0.0.2\n  hostname: app.local\n",
+        );
+        let conflicts =
+            ConfigStorage::merge_files(&base, &ours, &theirs, Path::new("environments/dev.yaml"))
+                .unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].environment.as_deref(), Some("dev"));
+    }
+
+    /// Check that a second lock of a store only succeeds after the first is released
+    fn assert_lock_is_exclusive(store: &(dyn ConfigStore + Sync)) {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let acquired = AtomicBool::new(false);
+        let lock = store.lock().unwrap();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let _lock = store.lock().unwrap();
+                acquired.store(true, Ordering::SeqCst);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            assert!(!acquired.load(Ordering::SeqCst));
+            drop(lock);
+            waiter.join().unwrap();
+        });
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_file_config_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let system_path = dir.path().join("system.yaml");
+        fs::write(&system_path, "environments:\n  corp:\n    entries: []\n").unwrap();
+        let store =
+            FileConfigStore::new(dir.path().join("config.yaml")).with_system_config(system_path);
+
+        let mut config = store.load().unwrap();
+        assert_eq!(
+            config.get_environment("corp").unwrap().layer,
+            ConfigLayer::System
+        );
+        config.add_environment(Environment::new("dev".to_string()));
+        store.save(&config).unwrap();
+
+        let saved = fs::read_to_string(store.user_path()).unwrap();
+        assert!(saved.contains("dev:"));
+        assert!(!saved.contains("corp"));
+        assert!(store.load().unwrap().get_environment("dev").is_some());
+
+        // The system layer is read-only
+        config.get_environment_mut("corp").unwrap().description = Some("Mine".to_string());
+        assert!(store.save(&config).is_err());
+
+        assert_lock_is_exclusive(&store);
+        assert!(store.lock_path().exists());
+    }
+
+    #[test]
+    fn test_memory_config_store() {
+        let store = MemoryConfigStore::default();
+        assert!(store.load().unwrap().environments.is_empty());
+
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        config.current_environment = Some("dev".to_string());
+        store.save(&config).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.current_environment.as_deref(), Some("dev"));
+        assert!(loaded.get_environment("dev").is_some());
+
+        assert_lock_is_exclusive(&store);
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, content, line, column) in [
+            ("config.yaml", "environments:\n  dev: [\n", 3, 1),
+            (
+                "config.yaml",
+                "environments:\n  dev:\n    entries: 5\n",
+                3,
+                14,
+            ),
+            ("config.json", "{\"environments\": {\"dev\": 5}}", 1, 26),
+            ("config.toml", "[environments.dev]\nentries = [\n", 2, 12),
+        ] {
+            let path = dir.path().join(file);
+            fs::write(&path, content).unwrap();
+            let err = ConfigStorage::load_config_file(&path).unwrap_err();
+            let parse_error = err.downcast_ref::<ParseError>().unwrap();
+            assert_eq!(parse_error.path, path);
+            assert_eq!(
+                (parse_error.line, parse_error.column),
+                (Some(line), Some(column)),
+                "{file}: {err:#}"
+            );
+            assert!(
+                err.to_string()
+                    .ends_with(&format!("at line {line}, column {column}"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        assert!(ConfigStorage::load_snapshot(&path).unwrap().is_none());
+        assert!(ConfigStorage::restore_snapshot(&path).is_err());
+
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+        let snapshot = ConfigStorage::load_snapshot(&path).unwrap().unwrap();
+        assert!(snapshot.get_environment("dev").is_some());
+
+        fs::write(&path, "environments:\n  dev: [\n").unwrap();
+        assert!(ConfigStorage::load_config_file(&path).is_err());
+
+        let backups = ConfigStorage::restore_snapshot(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&backups[0]).unwrap(),
+            "environments:\n  dev: [\n"
+        );
+        let restored = ConfigStorage::load_config_file(&path).unwrap();
+        assert!(restored.get_environment("dev").is_some());
+
+        // Backups made within the same second do not replace each other
+        let again = ConfigStorage::restore_snapshot(&path).unwrap();
+        assert_ne!(again, backups);
+        assert!(backups[0].exists());
+    }
+
+    #[test]
+    fn test_repair_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+
+        let mut config = Config::new();
+        let mut qa = Environment::new("qa".to_string());
+        qa.add_entry(HostEntry::new(
+            std::net::IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            "qa.local".to_string(),
+        ));
+        config.add_environment(qa);
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+        assert!(ConfigStorage::repair_config_file(&path).unwrap().is_none());
+
+        fs::write(
+            &path,
+            "version: 1\ncurrent_environment: dev\npolicies: 5\nenvironments:\n  dev:\n    entries:\n    - ip: 10.0.0.1\n      hostname: api.local\n  broken:\n    entries: nope\n  qa:\n    entries:\n    - ip: [10.0.0.2\n",
+        )
+        .unwrap();
+
+        let repair = ConfigStorage::repair_config_file(&path).unwrap().unwrap();
+        assert_eq!(repair.recovered, ["dev"]);
+        assert_eq!(repair.restored, ["qa"]);
+        assert_eq!(repair.lost, ["setting 'policies'", "environment 'broken'"]);
+        assert_eq!(repair.backups.len(), 1);
+
+        let repaired = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(repaired.current_environment.as_deref(), Some("dev"));
+        assert_eq!(repaired.get_environment("dev").unwrap().entries.len(), 1);
+        assert_eq!(repaired.get_environment("qa").unwrap().entries.len(), 1);
+        assert!(repaired.get_environment("broken").is_none());
+    }
+
+    #[test]
+    fn test_repair_environment_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        config.add_environment(Environment::new("qa".to_string()));
+        ConfigStorage::save_config_file(&path, &config).unwrap();
+        ConfigStorage::set_layout(&path, ConfigLayout::Directory).unwrap();
+
+        let envs = dir.path().join(ENVIRONMENTS_DIR);
+        fs::write(envs.join("dev.yaml"), "entries: [\n").unwrap();
+        fs::write(envs.join("new.yaml"), "entries: 5\n").unwrap();
+
+        let repair = ConfigStorage::repair_config_file(&path).unwrap().unwrap();
+        assert_eq!(repair.recovered, ["qa"]);
+        assert_eq!(repair.restored, ["dev"]);
+        assert_eq!(repair.lost, ["environment 'new'"]);
+        assert_eq!(repair.backups.len(), 2);
+
+        let repaired = ConfigStorage::load_config_file(&path).unwrap();
+        assert_eq!(repaired.environments.len(), 2);
+        assert!(!envs.join("new.yaml").exists());
+    }
+
+    #[test]
+    fn test_locations() {
+        let default = Locations::default();
+        assert!(!default.is_custom());
+        assert_eq!(
+            default.rooted(Path::new("/etc/hosts")),
+            Path::new("/etc/hosts")
+        );
+        assert_eq!(default.hosts_file(), crate::hosts::system_hosts_path());
+        assert!(default.args().is_empty());
+
+        let rooted = Locations {
+            root: Some(PathBuf::from("/srv/rootfs")),
+            ..Locations::default()
+        };
+        assert!(rooted.is_custom());
+        assert_eq!(
+            rooted.rooted(Path::new("/etc/hostctl/config.yaml")),
+            Path::new("/srv/rootfs/etc/hostctl/config.yaml")
+        );
+        assert!(rooted.hosts_file().starts_with("/srv/rootfs"));
+        assert_eq!(rooted.args(), ["--root", "/srv/rootfs"]);
+        assert_eq!(
+            rooted.target_file(Path::new("/srv/devbox/etc/hosts")),
+            Path::new("/srv/rootfs/srv/devbox/etc/hosts")
+        );
+
+        // Explicit files are not moved below the root
+        let explicit = Locations {
+            hosts_file: Some(PathBuf::from("/tmp/hosts")),
+            config_file: Some(PathBuf::from("/tmp/hostctl.yaml")),
+            ..rooted
+        };
+        assert_eq!(explicit.hosts_file(), Path::new("/tmp/hosts"));
+        assert_eq!(
+            explicit.args(),
+            [
+                "--config",
+                "/tmp/hostctl.yaml",
+                "--hosts-file",
+                "/tmp/hosts",
+                "--root",
+                "/srv/rootfs"
+            ]
+        );
+    }
 }
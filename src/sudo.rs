@@ -0,0 +1,271 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// A user on whose behalf hostctl runs as root, through `sudo` or `pkexec`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvokingUser {
+    /// Login name
+    pub name: String,
+    /// User ID
+    pub uid: u32,
+    /// Primary group ID
+    pub gid: u32,
+    /// Home directory
+    pub home: PathBuf,
+}
+
+/// The user who ran hostctl through `sudo` or `pkexec`
+///
+/// Writing the system hosts file requires root, so hostctl is usually run with
+/// `sudo`. The configuration still belongs to the invoking user, who is taken from
+/// `SUDO_USER` or `PKEXEC_UID`.
+///
+/// # Returns
+/// Returns `None` if hostctl is not running as root on behalf of another user
+#[must_use]
+pub fn invoking_user() -> Option<&'static InvokingUser> {
+    static USER: OnceLock<Option<InvokingUser>> = OnceLock::new();
+    USER.get_or_init(|| {
+        if !is_root() {
+            return None;
+        }
+        from_env(
+            std::env::var("SUDO_USER").ok().as_deref(),
+            std::env::var("PKEXEC_UID").ok().as_deref(),
+        )
+    })
+    .as_ref()
+}
+
+/// Home directory of root, whatever `HOME` is set to
+#[must_use]
+pub fn root_home() -> Option<PathBuf> {
+    lookup_uid(0).map(|root| root.home)
+}
+
+/// Look up the invoking user from the values of `SUDO_USER` and `PKEXEC_UID`
+///
+/// Invocations by root itself, e.g. `sudo` in a root shell, have no invoking user.
+fn from_env(sudo_user: Option<&str>, pkexec_uid: Option<&str>) -> Option<InvokingUser> {
+    let user = match (sudo_user, pkexec_uid) {
+        (Some(name), _) if !name.is_empty() => lookup_name(name)?,
+        (_, Some(uid)) => lookup_uid(uid.trim().parse().ok()?)?,
+        _ => return None,
+    };
+    (user.uid != 0).then_some(user)
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// Read a passwd entry with one of the reentrant `getpw*_r` functions
+///
+/// # Arguments
+/// * `get` - Calls the function with the entry, buffer, buffer length and result pointer
+#[cfg(unix)]
+fn read_passwd<F>(get: F) -> Option<InvokingUser>
+where
+    F: FnOnce(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+{
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut buffer: Vec<libc::c_char> = vec![0; 16 * 1024];
+    // SAFETY: passwd is a plain C struct for which all-zero bytes are a valid value
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+    if get(
+        &raw mut entry,
+        buffer.as_mut_ptr(),
+        buffer.len(),
+        &raw mut result,
+    ) != 0
+        || result.is_null()
+    {
+        return None;
+    }
+
+    // SAFETY: on success the entry's strings are NUL-terminated and stored in buffer,
+    // which is still alive
+    let (name, home) = unsafe {
+        (
+            CStr::from_ptr(entry.pw_name).to_string_lossy().into_owned(),
+            PathBuf::from(OsStr::from_bytes(CStr::from_ptr(entry.pw_dir).to_bytes())),
+        )
+    };
+    Some(InvokingUser {
+        name,
+        uid: entry.pw_uid,
+        gid: entry.pw_gid,
+        home,
+    })
+}
+
+#[cfg(unix)]
+fn lookup_name(name: &str) -> Option<InvokingUser> {
+    let name = std::ffi::CString::new(name).ok()?;
+    read_passwd(|entry, buffer, len, result| {
+        // SAFETY: all pointers are valid for the duration of the call
+        unsafe { libc::getpwnam_r(name.as_ptr(), entry, buffer, len, result) }
+    })
+}
+
+#[cfg(unix)]
+fn lookup_uid(uid: u32) -> Option<InvokingUser> {
+    read_passwd(|entry, buffer, len, result| {
+        // SAFETY: all pointers are valid for the duration of the call
+        unsafe { libc::getpwuid_r(uid, entry, buffer, len, result) }
+    })
+}
+
+#[cfg(not(unix))]
+fn lookup_name(_name: &str) -> Option<InvokingUser> {
+    None
+}
+
+#[cfg(not(unix))]
+fn lookup_uid(_uid: u32) -> Option<InvokingUser> {
+    None
+}
+
+impl InvokingUser {
+    /// Give files that root created in a directory of the user back to the user
+    ///
+    /// Everything below `dir` that is owned by root is handed to the user, as are
+    /// root-owned directories between the user's home and `dir`, e.g. a `~/.config`
+    /// that hostctl created. Files owned by anyone else are left alone, as are
+    /// directories outside the user's home, e.g. a config given with `--config`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory, usually the user's config directory
+    ///
+    /// # Errors
+    /// Returns an error if the ownership of a file cannot be changed.
+    #[cfg(unix)]
+    pub fn restore_ownership(&self, dir: &Path) -> Result<()> {
+        if !dir.starts_with(&self.home) {
+            return Ok(());
+        }
+        self.take_tree(dir)?;
+        for ancestor in dir.ancestors().skip(1) {
+            if !ancestor.starts_with(&self.home) || ancestor == self.home {
+                break;
+            }
+            self.take(ancestor)?;
+        }
+        Ok(())
+    }
+
+    /// Give a file or directory and everything below it to the user if owned by root
+    #[cfg(unix)]
+    fn take_tree(&self, path: &Path) -> Result<()> {
+        if self.take(path)? {
+            for entry in std::fs::read_dir(path)? {
+                self.take_tree(&entry?.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Give a file to the user if owned by root, without following symlinks
+    ///
+    /// # Returns
+    /// Returns whether the path is a directory
+    #[cfg(unix)]
+    fn take(&self, path: &Path) -> Result<bool> {
+        use anyhow::Context;
+        use std::os::unix::fs::MetadataExt;
+
+        let Ok(metadata) = std::fs::symlink_metadata(path) else {
+            return Ok(false);
+        };
+        if metadata.uid() == 0 {
+            std::os::unix::fs::lchown(path, Some(self.uid), Some(self.gid))
+                .with_context(|| format!("Failed to give {} to {}", path.display(), self.name))?;
+        }
+        Ok(metadata.is_dir())
+    }
+
+    /// Give files that root created in a directory of the user back to the user
+    ///
+    /// Ownership is not changed on this platform.
+    ///
+    /// # Errors
+    /// Never fails on this platform.
+    #[cfg(not(unix))]
+    pub fn restore_ownership(&self, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_from_env() {
+        assert_eq!(from_env(None, None), None);
+        // Root invoking itself is not another user
+        assert_eq!(from_env(Some("root"), None), None);
+        assert_eq!(from_env(None, Some("0")), None);
+        assert_eq!(from_env(Some("no-such-user-hostctl"), None), None);
+        assert_eq!(from_env(None, Some("not-a-uid")), None);
+
+        let root = lookup_uid(0).unwrap();
+        assert_eq!(root.name, "root");
+        assert_eq!(root_home(), Some(root.home.clone()));
+        assert_eq!(lookup_name("root"), Some(root));
+    }
+
+    #[test]
+    fn test_restore_ownership() {
+        if !is_root() {
+            return;
+        }
+        let home = tempfile::tempdir().unwrap();
+        let dir = home.path().join(".config").join("hostctl");
+        std::fs::create_dir_all(dir.join("environments")).unwrap();
+        std::fs::write(dir.join("config.yaml"), "").unwrap();
+        std::fs::write(dir.join("environments").join("dev.yaml"), "").unwrap();
+        let other = dir.join("other.yaml");
+        std::fs::write(&other, "").unwrap();
+        std::os::unix::fs::chown(&other, Some(4242), Some(4242)).unwrap();
+
+        let user = InvokingUser {
+            name: "test".to_string(),
+            uid: 65534,
+            gid: 65534,
+            home: home.path().to_path_buf(),
+        };
+        user.restore_ownership(&dir).unwrap();
+
+        let uid = |path: &Path| std::fs::metadata(path).unwrap().uid();
+        assert_eq!(uid(&dir.join("config.yaml")), 65534);
+        assert_eq!(uid(&dir.join("environments").join("dev.yaml")), 65534);
+        assert_eq!(uid(&home.path().join(".config")), 65534);
+        assert_eq!(uid(&other), 4242);
+        // The home directory itself is not touched
+        assert_eq!(uid(home.path()), 0);
+
+        // Neither are directories outside the home
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("config.yaml"), "").unwrap();
+        user.restore_ownership(outside.path()).unwrap();
+        assert_eq!(uid(&outside.path().join("config.yaml")), 0);
+    }
+}
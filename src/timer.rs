@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use chrono::Duration;
+
+/// Parse a human-friendly duration such as `30m`, `2h` or `1h30m`
+///
+/// Supported units are `s` (seconds), `m` (minutes), `h` (hours) and `d` (days).
+/// Several components may be combined, e.g. `1d12h`.
+///
+/// # Arguments
+/// * `input` - The duration string to parse
+///
+/// # Errors
+/// Returns an error if the string is empty, contains an unknown unit or evaluates to zero.
+///
+/// # Example
+/// ```
+/// use hostctl::timer::parse_duration;
+///
+/// assert_eq!(parse_duration("1h30m").unwrap(), chrono::Duration::minutes(90));
+/// ```
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let input = input.trim();
+    if input.is_empty() {
+        anyhow::bail!("Duration must not be empty");
+    }
+
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        if digits.is_empty() {
+            anyhow::bail!("Invalid duration '{input}': expected a number before '{c}'");
+        }
+
+        let value: i64 = digits
+            .parse()
+            .with_context(|| format!("Invalid duration '{input}'"))?;
+        let component = match c {
+            's' => Duration::try_seconds(value),
+            'm' => Duration::try_minutes(value),
+            'h' => Duration::try_hours(value),
+            'd' => Duration::try_days(value),
+            _ => anyhow::bail!("Invalid duration '{input}': unknown unit '{c}' (use s, m, h or d)"),
+        };
+        total = component
+            .and_then(|component| total.checked_add(&component))
+            .with_context(|| format!("Duration '{input}' is too large"))?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() {
+        anyhow::bail!(
+            "Invalid duration '{input}': missing unit after '{digits}' (use s, m, h or d)"
+        );
+    }
+
+    if total <= Duration::zero() {
+        anyhow::bail!("Duration must be greater than zero");
+    }
+
+    Ok(total)
+}
+
+/// Format a duration for display, e.g. `1h 5m 3s`
+///
+/// # Arguments
+/// * `duration` - The duration to format
+#[must_use]
+pub fn format_duration(duration: Duration) -> String {
+    let total = duration.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (
+        total / 86_400,
+        total % 86_400 / 3_600,
+        total % 3_600 / 60,
+        total % 60,
+    );
+
+    let mut parts = Vec::new();
+    if days > 0 {
+        parts.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        parts.push(format!("{hours}h"));
+    }
+    if minutes > 0 {
+        parts.push(format!("{minutes}m"));
+    }
+    if seconds > 0 || parts.is_empty() {
+        parts.push(format!("{seconds}s"));
+    }
+
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration_single_unit() {
+        assert_eq!(parse_duration("45s").unwrap(), Duration::seconds(45));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::hours(2));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+    }
+
+    #[test]
+    fn test_parse_duration_combined() {
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert_eq!(
+            parse_duration(" 1d2h3m4s ").unwrap(),
+            Duration::seconds(86_400 + 7_200 + 180 + 4)
+        );
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("-5m").is_err());
+        assert!(parse_duration("106751991168d").is_err());
+        assert!(parse_duration("106751991167d106751991167d").is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::zero()), "0s");
+        assert_eq!(format_duration(Duration::seconds(59)), "59s");
+        assert_eq!(format_duration(Duration::minutes(30)), "30m");
+        assert_eq!(format_duration(Duration::seconds(3_723)), "1h 2m 3s");
+        assert_eq!(format_duration(Duration::hours(26)), "1d 2h");
+    }
+}
@@ -0,0 +1,680 @@
+//! Format-preserving edits of YAML documents
+//!
+//! Config files are often annotated by hand. Instead of re-serializing a whole
+//! document after a change, [`patch_document`] compares the old and new values and
+//! rewrites only the lines of the mappings, keys and sequence items that changed, so
+//! comments, key order and formatting elsewhere in the file survive.
+//!
+//! Only block-style YAML is understood. Whenever the text does not have the expected
+//! shape (flow collections, block scalars, anchors, ...), the smallest enclosing
+//! key or item is re-serialized instead, and callers are expected to verify the
+//! result and fall back to full serialization if needed.
+
+use serde_yaml::{Mapping, Value};
+use serde_yaml_ok as serde_yaml;
+
+/// A replacement of the line range `start..end` by `lines`
+struct Edit {
+    start: usize,
+    end: usize,
+    lines: Vec<String>,
+}
+
+/// Structure of a block of lines, as far as it is understood
+enum Node {
+    Map(MapNode),
+    Seq(SeqNode),
+    /// A scalar, flow collection or anything else that can only be replaced whole
+    Other,
+}
+
+struct MapNode {
+    /// Column of the keys
+    indent: usize,
+    entries: Vec<MapEntry>,
+    /// Line after the last entry
+    end: usize,
+}
+
+struct MapEntry {
+    key: String,
+    /// Line holding the key
+    line: usize,
+    /// Line after the entry's value
+    end: usize,
+    value: Node,
+}
+
+struct SeqNode {
+    /// Column of the `-` markers
+    indent: usize,
+    items: Vec<SeqItem>,
+}
+
+struct SeqItem {
+    /// Line holding the `-` marker
+    line: usize,
+    /// Line after the item's value
+    end: usize,
+    value: Node,
+}
+
+/// Apply the differences between two versions of a document to its text
+///
+/// # Arguments
+/// * `text` - The current YAML text
+/// * `old` - The value `text` represents
+/// * `new` - The value the patched text should represent
+///
+/// # Returns
+/// Returns the patched text, or `None` if the document's layout is not understood
+#[must_use]
+pub fn patch_document(text: &str, old: &Value, new: &Value) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let (Value::Mapping(old), Value::Mapping(new)) = (old, new) else {
+        return None;
+    };
+
+    let parser = Parser { lines: &lines };
+    let Node::Map(root) = parser.parse_block(0, lines.len(), None) else {
+        return None;
+    };
+    if root.indent != 0 {
+        return None;
+    }
+
+    let mut edits = Vec::new();
+    if !diff_map(&lines, &root, old, new, &mut edits) {
+        return None;
+    }
+
+    let mut output: Vec<String> = lines.iter().map(|line| (*line).to_string()).collect();
+    // Bottom-up, and replacements before insertions at the same line
+    edits.sort_by_key(|edit| std::cmp::Reverse((edit.start, edit.end)));
+    for edit in edits {
+        output.splice(edit.start..edit.end, edit.lines);
+    }
+
+    let mut patched = output.join("\n");
+    if !patched.is_empty() {
+        patched.push('\n');
+    }
+    Some(patched)
+}
+
+/// Check whether a line carries content rather than being blank or a comment
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+/// Number of leading spaces of a line
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Check whether the text after a key or `-` marker holds no value
+fn is_empty_value(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Split `key: value` into the parsed key and the text after the colon
+fn split_key(content: &str) -> Option<(String, &str)> {
+    let key_end = match content.chars().next()? {
+        quote @ ('"' | '\'') => content[1..].find(quote)? + 2,
+        '-' | '[' | '{' | '|' | '>' | '&' | '*' | '!' | '?' | '%' | '@' | '`' => return None,
+        _ => content.find(':')?,
+    };
+
+    let rest = content.get(key_end..)?.strip_prefix(':')?;
+    if !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let key: String = serde_yaml::from_str(&content[..key_end]).ok()?;
+    Some((key, rest))
+}
+
+struct Parser<'a> {
+    lines: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    /// Indentation and content of a line, where the first line of a block may start
+    /// at an explicit column (the content of a `- ` sequence item)
+    fn view(&self, index: usize, start: usize, first_col: Option<usize>) -> (usize, &str) {
+        let line = self.lines[index];
+        match first_col {
+            Some(col) if index == start => (col, line.get(col..).unwrap_or_default()),
+            _ => (indent_of(line), line.trim_start_matches(' ')),
+        }
+    }
+
+    /// Parse the block value occupying `start..end`
+    fn parse_block(&self, start: usize, end: usize, first_col: Option<usize>) -> Node {
+        let Some(first) = (start..end).find(|&i| {
+            let (_, content) = self.view(i, start, first_col);
+            is_content(content)
+        }) else {
+            return Node::Other;
+        };
+
+        let (indent, content) = self.view(first, start, first_col);
+        let parsed = if content == "-" || content.starts_with("- ") {
+            self.parse_seq(first, end, start, first_col, indent)
+                .map(Node::Seq)
+        } else {
+            self.parse_map(first, end, start, first_col, indent)
+                .map(Node::Map)
+        };
+        parsed.unwrap_or(Node::Other)
+    }
+
+    /// Index after the last content line that belongs to a value started at `from`
+    fn value_end(&self, from: usize, end: usize, indent: usize, allow_seq: bool) -> usize {
+        let mut last = from;
+        for i in from + 1..end {
+            let line = self.lines[i];
+            if !is_content(line) {
+                continue;
+            }
+            let col = indent_of(line);
+            let content = line.trim_start_matches(' ');
+            let seq_marker = content == "-" || content.starts_with("- ");
+            if col > indent || (allow_seq && col == indent && seq_marker) {
+                last = i;
+            } else {
+                break;
+            }
+        }
+        last + 1
+    }
+
+    fn parse_map(
+        &self,
+        first: usize,
+        end: usize,
+        start: usize,
+        first_col: Option<usize>,
+        indent: usize,
+    ) -> Option<MapNode> {
+        let mut entries = Vec::new();
+        let mut i = first;
+
+        while i < end {
+            let (col, content) = self.view(i, start, first_col);
+            if !is_content(content) {
+                i += 1;
+                continue;
+            }
+            if col != indent {
+                return None;
+            }
+
+            let (key, rest) = split_key(content)?;
+            let empty = is_empty_value(rest);
+            let value_end = self.value_end(i, end, indent, empty);
+            let value = if empty {
+                self.parse_block(i + 1, value_end, None)
+            } else {
+                Node::Other
+            };
+
+            entries.push(MapEntry {
+                key,
+                line: i,
+                end: value_end,
+                value,
+            });
+            i = value_end;
+        }
+
+        let end = entries.last()?.end;
+        Some(MapNode {
+            indent,
+            entries,
+            end,
+        })
+    }
+
+    fn parse_seq(
+        &self,
+        first: usize,
+        end: usize,
+        start: usize,
+        first_col: Option<usize>,
+        indent: usize,
+    ) -> Option<SeqNode> {
+        let mut items = Vec::new();
+        let mut i = first;
+
+        while i < end {
+            let (col, content) = self.view(i, start, first_col);
+            if !is_content(content) {
+                i += 1;
+                continue;
+            }
+            if col != indent {
+                return None;
+            }
+
+            let after = content.strip_prefix('-')?;
+            if !(after.is_empty() || after.starts_with(' ')) {
+                return None;
+            }
+            let item_end = self.value_end(i, end, indent, false);
+            let value = if is_empty_value(after) {
+                self.parse_block(i + 1, item_end, None)
+            } else {
+                let inner_col = indent + 1 + (after.len() - after.trim_start().len());
+                self.parse_block(i, item_end, Some(inner_col))
+            };
+
+            items.push(SeqItem {
+                line: i,
+                end: item_end,
+                value,
+            });
+            i = item_end;
+        }
+
+        Some(SeqNode { indent, items })
+    }
+}
+
+/// Serialize a value as block YAML indented by `indent` columns
+fn render(value: &Value, indent: usize) -> Option<Vec<String>> {
+    let text = serde_yaml::to_string(value).ok()?;
+    Some(
+        text.lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("{:indent$}{line}", "")
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Serialize a single `key: value` entry
+fn render_entry(key: &Value, value: &Value, indent: usize) -> Option<Vec<String>> {
+    let mut mapping = Mapping::new();
+    mapping.insert(key.clone(), value.clone());
+    render(&Value::Mapping(mapping), indent)
+}
+
+/// Serialize a single sequence item
+fn render_item(value: &Value, indent: usize) -> Option<Vec<String>> {
+    render(&Value::Sequence(vec![value.clone()]), indent)
+}
+
+/// Start of an entry including the comment lines directly above it
+fn attached_start(lines: &[&str], line: usize, indent: usize) -> usize {
+    let mut start = line;
+    while start > 0 {
+        let above = lines[start - 1];
+        if indent_of(above) == indent && above.trim_start().starts_with('#') {
+            start -= 1;
+        } else {
+            break;
+        }
+    }
+    start
+}
+
+/// Check whether an entry's key line also carries a parent's `-` marker
+fn shares_item_line(lines: &[&str], entry: &MapEntry, indent: usize) -> bool {
+    lines[entry.line]
+        .get(..indent)
+        .is_none_or(|prefix| !prefix.trim().is_empty())
+}
+
+/// Record the edits turning `old` into `new` within a mapping
+///
+/// Returns `false` if the mapping must be replaced as a whole.
+fn diff_map(
+    lines: &[&str],
+    map: &MapNode,
+    old: &Mapping,
+    new: &Mapping,
+    edits: &mut Vec<Edit>,
+) -> bool {
+    let text_entry = |key: &Value| {
+        let key = key.as_str()?;
+        map.entries.iter().find(|entry| entry.key == key)
+    };
+
+    for (key, old_value) in old {
+        if new.contains_key(key) {
+            continue;
+        }
+        if let Some(entry) = text_entry(key) {
+            if shares_item_line(lines, entry, map.indent) {
+                return false;
+            }
+            edits.push(Edit {
+                start: attached_start(lines, entry.line, map.indent),
+                end: entry.end,
+                lines: Vec::new(),
+            });
+        } else if !old_value.is_null() {
+            // The text has no such key, so the values did not come from this text
+            return false;
+        }
+    }
+
+    let mut inserted: Vec<Edit> = Vec::new();
+    for (position, (key, new_value)) in new.iter().enumerate() {
+        let old_value = old.get(key);
+        if old_value == Some(new_value) {
+            continue;
+        }
+
+        let Some(entry) = text_entry(key) else {
+            let Some(rendered) = render_entry(key, new_value, map.indent) else {
+                return false;
+            };
+            // New keys go before the next key that is already in the text, if any
+            let at = new
+                .keys()
+                .skip(position + 1)
+                .filter_map(text_entry)
+                .find(|next| !shares_item_line(lines, next, map.indent))
+                .map_or(map.end, |next| attached_start(lines, next.line, map.indent));
+            match inserted.iter_mut().find(|edit| edit.start == at) {
+                Some(edit) => edit.lines.extend(rendered),
+                None => inserted.push(Edit {
+                    start: at,
+                    end: at,
+                    lines: rendered,
+                }),
+            }
+            continue;
+        };
+
+        if let Some(old_value) = old_value
+            && diff_value(lines, &entry.value, old_value, new_value, edits)
+        {
+            continue;
+        }
+
+        let Some(mut rendered) = render_entry(key, new_value, map.indent) else {
+            return false;
+        };
+        // Keep a `- ` marker that shares the line with the key
+        rendered[0].replace_range(..map.indent, &lines[entry.line][..map.indent]);
+        edits.push(Edit {
+            start: entry.line,
+            end: entry.end,
+            lines: rendered,
+        });
+    }
+
+    edits.extend(inserted);
+    true
+}
+
+/// Record the edits turning `old` into `new` within a sequence
+///
+/// Returns `false` if the sequence must be replaced as a whole.
+fn diff_seq(
+    lines: &[&str],
+    seq: &SeqNode,
+    old: &[Value],
+    new: &[Value],
+    edits: &mut Vec<Edit>,
+) -> bool {
+    if seq.items.len() != old.len() {
+        return false;
+    }
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+    let items = &seq.items[prefix..seq.items.len() - suffix];
+
+    if old_changed.len() == new_changed.len() {
+        for ((item, old_value), new_value) in items.iter().zip(old_changed).zip(new_changed) {
+            if diff_value(lines, &item.value, old_value, new_value, edits) {
+                continue;
+            }
+            let Some(rendered) = render_item(new_value, seq.indent) else {
+                return false;
+            };
+            edits.push(Edit {
+                start: item.line,
+                end: item.end,
+                lines: rendered,
+            });
+        }
+        return true;
+    }
+
+    // Insertions go after the preceding item, replacements cover the changed items
+    let (start, end) = match (items.first(), items.last()) {
+        (Some(first), Some(last)) => (first.line, last.end),
+        _ => {
+            let at = match prefix {
+                0 => seq.items.first().map_or(0, |item| item.line),
+                n => seq.items[n - 1].end,
+            };
+            (at, at)
+        }
+    };
+
+    let mut rendered = Vec::new();
+    for value in new_changed {
+        let Some(lines) = render_item(value, seq.indent) else {
+            return false;
+        };
+        rendered.extend(lines);
+    }
+    edits.push(Edit {
+        start,
+        end,
+        lines: rendered,
+    });
+    true
+}
+
+/// Record the edits for a changed value, if its text is structured enough to patch
+///
+/// No edits are recorded if the value must be replaced as a whole.
+fn diff_value(
+    lines: &[&str],
+    node: &Node,
+    old: &Value,
+    new: &Value,
+    edits: &mut Vec<Edit>,
+) -> bool {
+    let mut nested = Vec::new();
+    let patched = match (node, old, new) {
+        // Emptied collections are re-rendered as `{}` or `[]`
+        (_, _, Value::Mapping(new)) if new.is_empty() => false,
+        (_, _, Value::Sequence(new)) if new.is_empty() => false,
+        (Node::Map(map), Value::Mapping(old), Value::Mapping(new)) => {
+            diff_map(lines, map, old, new, &mut nested)
+        }
+        (Node::Seq(seq), Value::Sequence(old), Value::Sequence(new)) => {
+            diff_seq(lines, seq, old, new, &mut nested)
+        }
+        _ => false,
+    };
+    if patched {
+        edits.extend(nested);
+    }
+    patched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNOTATED: &str = "\
+# Team hosts configuration
+current_environment: dev
+
+environments:
+  # Local development stack
+  dev:
+    name: dev
+    description: null
+    entries:
+    - ip: 127.0.0.1
+      hostname: api.local # the API
+      comment: null
+    - ip: 127.0.0.1
+      hostname: web.local
+      comment: null
+
+  # Staging, keep in sync with infra repo
+  staging:
+    name: staging
+    description: Staging
+    entries: []
+";
+
+    /// Apply a change to the annotated document and check the result parses to it
+    fn patch(change: impl FnOnce(&mut Value)) -> String {
+        let old: Value = serde_yaml::from_str(ANNOTATED).unwrap();
+        let mut new = old.clone();
+        change(&mut new);
+
+        let patched = patch_document(ANNOTATED, &old, &new).unwrap();
+        let reparsed: Value = serde_yaml::from_str(&patched).unwrap();
+        assert_eq!(reparsed, new, "patched text:\n{patched}");
+        patched
+    }
+
+    fn entries<'a>(doc: &'a mut Value, env: &str) -> &'a mut Vec<Value> {
+        doc["environments"][env]["entries"]
+            .as_sequence_mut()
+            .unwrap()
+    }
+
+    fn entry(ip: &str, hostname: &str) -> Value {
+        serde_yaml::from_str(&format!(
+            "{{ip: {ip}, hostname: {hostname}, comment: null}}"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_unchanged_document_is_identical() {
+        assert_eq!(patch(|_| {}), ANNOTATED);
+    }
+
+    #[test]
+    fn test_scalar_change_keeps_comments() {
+        let patched = patch(|doc| doc["current_environment"] = "staging".into());
+        assert_eq!(
+            patched,
+            ANNOTATED.replace("current_environment: dev", "current_environment: staging")
+        );
+    }
+
+    #[test]
+    fn test_append_entry() {
+        let patched = patch(|doc| {
+            entries(doc, "dev").push(entry("10.0.0.1", "db.local"));
+        });
+        assert!(patched.contains("hostname: api.local # the API"));
+        assert!(patched.contains(
+            "      comment: null\n    - ip: 10.0.0.1\n      hostname: db.local\n      comment: null\n\n  # Staging"
+        ));
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        let patched = patch(|doc| {
+            entries(doc, "dev").remove(1);
+        });
+        assert!(!patched.contains("web.local"));
+        assert!(patched.contains("hostname: api.local # the API"));
+        assert!(patched.contains("# Staging, keep in sync with infra repo"));
+    }
+
+    #[test]
+    fn test_change_inside_entry() {
+        let patched = patch(|doc| {
+            entries(doc, "dev")[0]["ip"] = "10.1.1.1".into();
+        });
+        assert!(patched.contains("    - ip: 10.1.1.1\n      hostname: api.local # the API\n"));
+    }
+
+    #[test]
+    fn test_fill_empty_flow_sequence() {
+        let patched = patch(|doc| {
+            entries(doc, "staging").push(entry("10.0.0.2", "api.staging"));
+        });
+        assert!(patched.contains("    entries:\n    - ip: 10.0.0.2\n"));
+        assert!(patched.contains("# Staging, keep in sync with infra repo"));
+    }
+
+    #[test]
+    fn test_remove_environment_with_its_comment() {
+        let patched = patch(|doc| {
+            doc["environments"]
+                .as_mapping_mut()
+                .unwrap()
+                .remove("staging");
+        });
+        assert!(!patched.contains("staging"));
+        assert!(!patched.contains("# Staging"));
+        assert!(patched.contains("# Local development stack"));
+    }
+
+    #[test]
+    fn test_add_environment() {
+        let patched = patch(|doc| {
+            let prod: Value =
+                serde_yaml::from_str("{name: prod, description: null, entries: []}").unwrap();
+            doc["environments"]
+                .as_mapping_mut()
+                .unwrap()
+                .insert("prod".into(), prod);
+        });
+        assert!(patched.starts_with("# Team hosts configuration\n"));
+        assert!(
+            patched.ends_with("  prod:\n    name: prod\n    description: null\n    entries: []\n")
+        );
+    }
+
+    #[test]
+    fn test_remove_first_key_of_item() {
+        // The key shares its line with the item's `-` marker, so the item is rewritten
+        let patched = patch(|doc| {
+            entries(doc, "dev")[1]
+                .as_mapping_mut()
+                .unwrap()
+                .shift_remove("ip");
+        });
+        assert!(patched.contains("    - hostname: web.local\n"), "{patched}");
+    }
+
+    #[test]
+    fn test_emptied_collection() {
+        let patched = patch(|doc| {
+            entries(doc, "dev").clear();
+        });
+        assert!(patched.contains("    entries: []\n\n  # Staging"));
+    }
+
+    #[test]
+    fn test_unsupported_layout() {
+        let old: Value = serde_yaml::from_str("{a: 1}").unwrap();
+        let new: Value = serde_yaml::from_str("{a: 2}").unwrap();
+        assert!(patch_document("{a: 1}\n", &old, &new).is_none());
+    }
+}
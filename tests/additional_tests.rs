@@ -1,7 +1,9 @@
 //! Additional tests - Test additional functionality of hostctl
 
 use hostctl::config::{Config, Environment, HostEntry};
+use hostctl::filesystem::{FileSystem, MemoryFileSystem};
 use hostctl::hosts::HostsManager;
+use hostctl::storage::{ConfigStore, MemoryConfigStore};
 use serde_yaml_ok as serde_yaml;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
@@ -327,4 +329,73 @@ mod tests {
         assert!(!HostsManager::is_valid_hostname("invalid-"));
         assert!(!HostsManager::is_valid_hostname("test_environment"));
     }
+
+    /// Add an entry to an environment the way a command does: lock, load, modify, save
+    fn add_entry_to(store: &dyn ConfigStore, environment: &str, hostname: &str) {
+        let _lock = store.lock().unwrap();
+        let mut config = store.load().unwrap();
+        config
+            .get_environment_mut(environment)
+            .unwrap()
+            .add_entry(HostEntry::new(
+                std::net::IpAddr::V4(Ipv4Addr::LOCALHOST),
+                hostname.to_string(),
+            ));
+        store.save(&config).unwrap();
+    }
+
+    /// Test a load/modify/save cycle against the in-memory store
+    #[test]
+    fn test_memory_store_round_trip() {
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        let store = MemoryConfigStore::new(config);
+
+        add_entry_to(&store, "dev", "app.local");
+
+        let config = store.load().unwrap();
+        let dev = config.get_environment("dev").unwrap();
+        assert_eq!(dev.entries.len(), 1);
+        assert_eq!(dev.entries[0].hostname, "app.local");
+    }
+
+    /// Test that locked read-modify-write cycles don't lose concurrent changes
+    #[test]
+    fn test_memory_store_concurrent_changes() {
+        let mut config = Config::new();
+        config.add_environment(Environment::new("dev".to_string()));
+        let store = MemoryConfigStore::new(config);
+
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let store = &store;
+                scope.spawn(move || add_entry_to(store, "dev", &format!("host{i}.local")));
+            }
+        });
+
+        let config = store.load().unwrap();
+        assert_eq!(config.get_environment("dev").unwrap().entries.len(), 8);
+    }
+
+    /// Test applying an environment to a hosts file held in memory
+    #[test]
+    fn test_apply_environment_in_memory() {
+        let fs = MemoryFileSystem::new();
+        let path = std::path::PathBuf::from("/etc/hosts");
+        fs.write(&path, "# Static entries\n127.0.0.1 localhost\n")
+            .unwrap();
+        let hosts = HostsManager::with_file_system(path.clone(), fs.clone());
+
+        let mut env = Environment::new("dev".to_string());
+        env.add_entry(HostEntry::new(
+            std::net::IpAddr::V6(Ipv6Addr::LOCALHOST),
+            "app.local".to_string(),
+        ));
+        hosts.apply_environment(&env).unwrap();
+
+        let content = fs.read_to_string(&path).unwrap();
+        assert!(content.contains("::1 app.local"));
+        assert_eq!(hosts.read_current_hosts().unwrap().len(), 2);
+        assert_eq!(hosts.path(), path);
+    }
 }
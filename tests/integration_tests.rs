@@ -6,6 +6,34 @@ use std::str;
 #[cfg(test)]
 pub mod additional_tests;
 
+/// Run hostctl with `hostctl.yaml` and `hosts` in `dir` as its config and hosts file
+///
+/// The hosts file is created with a localhost entry if it does not exist yet.
+fn hostctl_in(dir: &std::path::Path, args: &[&str]) -> std::process::Output {
+    let hosts_path = dir.join("hosts");
+    if !hosts_path.exists() {
+        std::fs::write(&hosts_path, "127.0.0.1 localhost\n").unwrap();
+    }
+    Command::new("cargo")
+        .args(["run", "--"])
+        .args(args)
+        .env("HOSTCTL_CONFIG", dir.join("hostctl.yaml"))
+        .env("HOSTCTL_HOSTS_FILE", hosts_path)
+        .output()
+        .expect("Failed to execute command")
+}
+
+/// Run hostctl like `hostctl_in` and assert that it succeeds
+fn hostctl_ok(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = hostctl_in(dir, args);
+    assert!(
+        output.status.success(),
+        "{args:?}: {}",
+        str::from_utf8(&output.stderr).unwrap()
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
 /// Test basic CLI commands
 #[test]
 fn test_cli_help() {
@@ -65,6 +93,180 @@ fn test_cli_show_no_env() {
     assert!(stderr.contains("not found"));
 }
 
+/// Test structured output of the read commands and the not-found exit code
+#[test]
+fn test_cli_output_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = |args: &[&str]| -> serde_json::Value {
+        serde_json::from_str(&hostctl_ok(dir.path(), args)).unwrap()
+    };
+
+    assert_eq!(json(&["current", "-o", "json"]), serde_json::Value::Null);
+    hostctl_ok(dir.path(), &["add", "dev", "-d", "Development"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.local"]);
+    hostctl_ok(dir.path(), &["add", "stage"]);
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+
+    let list = json(&["list", "--output", "json"]);
+    assert_eq!(list[0]["name"], "dev");
+    assert_eq!(list[0]["active"], true);
+    assert_eq!(list[0]["entry_count"], 1);
+    assert_eq!(list[1]["name"], "stage");
+    assert_eq!(list[1]["active"], false);
+
+    let show = json(&["show", "dev", "-o", "json"]);
+    assert_eq!(show["description"], "Development");
+    assert_eq!(show["entries"][0]["hostname"], "api.local");
+    assert_eq!(show["entries"][0]["ip"], "10.0.0.1");
+    assert_eq!(json(&["current", "-o", "json"])["name"], "dev");
+    assert_eq!(json(&["status", "-o", "json"])["active_environment"], "dev");
+    let history = json(&["history", "-o", "json"]);
+    assert_eq!(history[0]["environment"], "stage");
+    assert_eq!(history[0]["undone"], false);
+    assert_eq!(
+        json(&["sources", "list", "-o", "json"]),
+        serde_json::json!([])
+    );
+    let files = json(&["config", "sources", "-o", "json"]);
+    assert_eq!(files[1]["layer"], "user");
+    assert_eq!(files[1]["exists"], true);
+    assert_eq!(files[1]["environments"][0]["name"], "dev");
+
+    let yaml = hostctl_ok(dir.path(), &["show", "dev", "-o", "yaml"]);
+    assert!(yaml.contains("entry_count: 1"));
+    let table = hostctl_ok(dir.path(), &["list", "-o", "table"]);
+    assert!(table.starts_with("NAME "));
+    assert!(table.contains("dev    yes"));
+
+    // Missing environments and entries exit with 4, other errors with 1
+    let missing = hostctl_in(dir.path(), &["show", "missing", "-o", "json"]);
+    assert_eq!(missing.status.code(), Some(4));
+    assert!(missing.stdout.is_empty());
+    assert_eq!(
+        hostctl_in(dir.path(), &["remove-entry", "dev", "missing.local"])
+            .status
+            .code(),
+        Some(4)
+    );
+    assert_eq!(
+        hostctl_in(dir.path(), &["add-entry", "dev", "not-an-ip", "web.local"])
+            .status
+            .code(),
+        Some(1)
+    );
+}
+
+/// Test the completion scripts and the values they get from the configuration
+#[test]
+fn test_cli_completions() {
+    let dir = tempfile::tempdir().unwrap();
+
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.local"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.2", "web.local"]);
+    hostctl_ok(dir.path(), &["add", "stage"]);
+
+    assert_eq!(
+        hostctl_ok(dir.path(), &["__complete", "--", "switch", ""]),
+        "dev\nstage\n"
+    );
+    assert_eq!(
+        hostctl_ok(
+            dir.path(),
+            &["__complete", "--", "remove-entry", "dev", "w"]
+        ),
+        "web.local\n"
+    );
+    assert_eq!(hostctl_ok(dir.path(), &["__complete", "--", "add", ""]), "");
+    assert_eq!(
+        hostctl_ok(
+            dir.path(),
+            &["__complete", "--", "config", "sources", "-o", "y"]
+        ),
+        "yaml\n"
+    );
+    assert_eq!(
+        hostctl_ok(dir.path(), &["__complete", "--", "log", "--output", "t"]),
+        "table\n"
+    );
+    // A config file that cannot be loaded completes nothing rather than failing
+    let broken = dir.path().join("broken.yaml");
+    std::fs::write(&broken, "environments: [").unwrap();
+    assert_eq!(
+        hostctl_ok(
+            dir.path(),
+            &[
+                "__complete",
+                "--",
+                "--config",
+                broken.to_str().unwrap(),
+                "switch",
+                ""
+            ]
+        ),
+        ""
+    );
+    // Completion never rewrites a config file written in an older format
+    let old = dir.path().join("old.yaml");
+    let unversioned = "current_environment: null\nenvironments:\n  legacy:\n    name: legacy\n    description: null\n    entries: []\n";
+    std::fs::write(&old, unversioned).unwrap();
+    assert_eq!(
+        hostctl_ok(
+            dir.path(),
+            &[
+                "__complete",
+                "--",
+                "--config",
+                old.to_str().unwrap(),
+                "switch",
+                ""
+            ]
+        ),
+        "legacy\n"
+    );
+    assert_eq!(std::fs::read_to_string(&old).unwrap(), unversioned);
+
+    for (shell, function) in [
+        ("bash", "_hostctl_dynamic"),
+        ("zsh", "_hostctl_dynamic"),
+        ("fish", "__hostctl_candidates"),
+    ] {
+        let script = hostctl_ok(dir.path(), &["completions", shell]);
+        assert!(script.contains("switch"), "{shell}");
+        assert!(script.contains(function), "{shell}");
+    }
+
+    // Run the bash script the way bash does on <TAB>
+    let bin_dir = std::path::Path::new(env!("CARGO_BIN_EXE_hostctl"))
+        .parent()
+        .unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let script = r#"
+        source <(hostctl completions bash)
+        COMP_WORDS=(hostctl switch ""); COMP_CWORD=2
+        _hostctl_dynamic hostctl "" switch; echo "${COMPREPLY[*]}"
+        COMP_WORDS=(hostctl sw); COMP_CWORD=1; COMPREPLY=()
+        _hostctl_dynamic hostctl sw hostctl; echo "${COMPREPLY[*]}"
+    "#;
+    let Ok(output) = Command::new("bash")
+        .args(["-c", script])
+        .env("PATH", path)
+        .env("HOSTCTL_CONFIG", dir.path().join("hostctl.yaml"))
+        .env("HOSTCTL_HOSTS_FILE", dir.path().join("hosts"))
+        .output()
+    else {
+        return;
+    };
+    assert_eq!(
+        str::from_utf8(&output.stdout).unwrap(),
+        "dev stage\nswitch\n"
+    );
+}
+
 /// Test invalid command
 #[test]
 fn test_cli_invalid_command() {
@@ -154,3 +356,772 @@ fn test_cli_remove_entry_missing_args() {
     let stderr = str::from_utf8(&output.stderr).unwrap();
     assert!(stderr.contains("error:"));
 }
+
+/// Test switch command rejects an invalid timer duration
+#[test]
+fn test_cli_switch_invalid_duration() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.local"]);
+
+    for (duration, message) in [
+        ("10x", "unknown unit"),
+        // Valid on its own, but the deadline would be out of range
+        ("106751991167d", "too large"),
+        ("106751991167d106751991167d", "too large"),
+    ] {
+        let output = hostctl_in(dir.path(), &["switch", "dev", "--for", duration]);
+        assert!(!output.status.success(), "{duration}");
+        let stderr = str::from_utf8(&output.stderr).unwrap();
+        assert!(stderr.contains(message), "{duration}: {stderr}");
+    }
+
+    // Nothing was switched
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("hosts")).unwrap(),
+        "127.0.0.1 localhost\n"
+    );
+    assert!(hostctl_ok(dir.path(), &["status"]).contains("Active environment: (none)"));
+}
+
+/// Test cancel-timer cannot be combined with an environment name
+#[test]
+fn test_cli_switch_cancel_timer_conflicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = hostctl_in(dir.path(), &["switch", "dev", "--cancel-timer"]);
+
+    assert!(!output.status.success());
+
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("error:"));
+}
+
+#[test]
+fn test_cli_config_sources() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+
+    let stdout = hostctl_ok(dir.path(), &["config", "sources"]);
+    assert!(stdout.contains("system"));
+    let user = format!("user     {}", dir.path().join("hostctl.yaml").display());
+    assert!(stdout.contains(&user), "{stdout}");
+    assert!(stdout.contains("    - dev"));
+}
+
+#[test]
+fn test_cli_switch_protected_without_terminal() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "prod", "--protected"]);
+    hostctl_ok(
+        dir.path(),
+        &["add-entry", "prod", "10.0.0.1", "api.prod", "--force"],
+    );
+    let hosts_path = dir.path().join("hosts");
+    let before = std::fs::read_to_string(&hosts_path).unwrap();
+
+    // stdin is not a terminal here, so the switch fails instead of prompting
+    let output = hostctl_in(dir.path(), &["switch", "prod"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        str::from_utf8(&output.stderr)
+            .unwrap()
+            .contains("protected")
+    );
+    assert_eq!(std::fs::read_to_string(&hosts_path).unwrap(), before);
+    let status = hostctl_ok(dir.path(), &["status"]);
+    assert!(status.contains("Active environment: (none)"));
+
+    hostctl_ok(dir.path(), &["switch", "prod", "--force"]);
+    let hosts = std::fs::read_to_string(&hosts_path).unwrap();
+    assert!(hosts.contains("10.0.0.1") && hosts.contains("api.prod"));
+    let status = hostctl_ok(dir.path(), &["status"]);
+    assert!(status.contains("Active environment: prod"));
+}
+
+#[test]
+fn test_cli_policies() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "1.2.3.4", "github.com"]);
+    hostctl_ok(dir.path(), &["policy", "check"]);
+
+    // Add a rule that the existing entry violates
+    let config_path = dir.path().join("hostctl.yaml");
+    let mut config = std::fs::read_to_string(&config_path).unwrap();
+    config.push_str(
+        "policies:\n  - name: no-shadowing\n    hostnames: [\"github.com\", \"*.github.com\"]\n    deny: true\n",
+    );
+    std::fs::write(&config_path, config).unwrap();
+
+    let output = hostctl_in(
+        dir.path(),
+        &["add-entry", "dev", "1.2.3.4", "api.github.com"],
+    );
+    assert!(!output.status.success());
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("no-shadowing: api.github.com in 'dev'"),
+        "{stderr}"
+    );
+    let show = hostctl_ok(dir.path(), &["show", "dev"]);
+    assert!(!show.contains("api.github.com"));
+
+    let hosts_path = dir.path().join("hosts");
+    let before = std::fs::read_to_string(&hosts_path).unwrap();
+    let output = hostctl_in(dir.path(), &["switch", "dev"]);
+    assert!(!output.status.success());
+    assert!(
+        str::from_utf8(&output.stderr)
+            .unwrap()
+            .contains("policy violation")
+    );
+    assert_eq!(std::fs::read_to_string(&hosts_path).unwrap(), before);
+
+    let output = hostctl_in(dir.path(), &["policy", "check"]);
+    assert!(!output.status.success());
+    let stdout = str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        stdout.contains("[error] no-shadowing: github.com in 'dev'"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_undo_respects_protection() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "prod"]);
+    hostctl_ok(dir.path(), &["add-entry", "prod", "10.0.0.1", "api.prod"]);
+    hostctl_ok(dir.path(), &["protect", "prod", "--locked"]);
+
+    // Undoing the lock is an unprotect, which needs confirmation
+    let output = hostctl_in(dir.path(), &["undo"]);
+    assert_eq!(output.status.code(), Some(3));
+    let show = hostctl_ok(dir.path(), &["show", "prod", "-o", "json"]);
+    let show: serde_json::Value = serde_json::from_str(&show).unwrap();
+    assert_eq!(show["locked"], true);
+    assert_eq!(show["entry_count"], 1);
+    let history = hostctl_ok(dir.path(), &["history"]);
+    assert!(!history.contains("(undone)"));
+
+    let stdout = hostctl_ok(dir.path(), &["undo", "--force"]);
+    assert!(stdout.contains("Undid: hostctl protect prod --locked"));
+
+    // Locked environments are not modified even with --force
+    let config_path = dir.path().join("hostctl.yaml");
+    let config = std::fs::read_to_string(&config_path).unwrap();
+    let config = config.replace("    name: prod\n", "    name: prod\n    locked: true\n");
+    std::fs::write(&config_path, config).unwrap();
+    let output = hostctl_in(dir.path(), &["undo", "--force"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(str::from_utf8(&output.stderr).unwrap().contains("locked"));
+    let show = hostctl_ok(dir.path(), &["show", "prod"]);
+    assert!(show.contains("api.prod"));
+}
+
+#[test]
+fn test_cli_undo_after_switching_to_dynamic_environment() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(
+        dir.path(),
+        &["add-entry", "dev", "cmd:echo 10.0.0.7", "api.dev"],
+    );
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.8", "web.dev"]);
+
+    // Switching caches the resolved address without recording a change
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+    let show = hostctl_ok(dir.path(), &["show", "dev"]);
+    assert!(show.contains("10.0.0.7"), "{show}");
+
+    let output = hostctl_in(dir.path(), &["undo"]);
+    assert!(output.status.success());
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(!stderr.contains("was changed since"), "{stderr}");
+    assert!(
+        str::from_utf8(&output.stdout)
+            .unwrap()
+            .contains("Undid: hostctl add-entry dev 10.0.0.8 web.dev")
+    );
+    let hosts = std::fs::read_to_string(dir.path().join("hosts")).unwrap();
+    assert!(hosts.contains("10.0.0.7 api.dev"));
+    assert!(!hosts.contains("web.dev"));
+}
+
+#[test]
+fn test_cli_undo_redo() {
+    let dir = tempfile::tempdir().unwrap();
+    let hosts_path = dir.path().join("hosts");
+    let hosts = || std::fs::read_to_string(&hosts_path).unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.dev"]);
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.2", "web.dev"]);
+    hostctl_ok(dir.path(), &["remove-entry", "dev", "api.dev"]);
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+    assert!(hosts().contains("10.0.0.2 web.dev"));
+    assert!(!hosts().contains("api.dev"));
+
+    let history = hostctl_ok(dir.path(), &["history"]);
+    assert!(history.contains("hostctl remove-entry dev api.dev"));
+
+    // Undoing changes to the active environment restores it and its hosts entries
+    let stdout = hostctl_ok(dir.path(), &["undo"]);
+    assert!(stdout.contains("Undid: hostctl remove-entry dev api.dev"));
+    assert!(hosts().contains("10.0.0.1 api.dev"));
+    let stdout = hostctl_ok(dir.path(), &["undo"]);
+    assert!(stdout.contains("Undid: hostctl add-entry dev 10.0.0.2 web.dev"));
+    assert!(!hosts().contains("web.dev"));
+    let show = hostctl_ok(dir.path(), &["show", "dev"]);
+    assert!(show.contains("api.dev") && !show.contains("web.dev"));
+
+    let stdout = hostctl_ok(dir.path(), &["redo"]);
+    assert!(stdout.contains("Redid: hostctl add-entry dev 10.0.0.2 web.dev"));
+    assert!(hosts().contains("10.0.0.1 api.dev"));
+    assert!(hosts().contains("10.0.0.2 web.dev"));
+    let show = hostctl_ok(dir.path(), &["show", "dev"]);
+    assert!(show.contains("api.dev") && show.contains("web.dev"));
+    let status = hostctl_ok(dir.path(), &["status"]);
+    assert!(status.contains("Active environment: dev"));
+}
+
+#[test]
+fn test_cli_checkout_respects_protection() {
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["config", "git"]);
+    hostctl_ok(dir.path(), &["add", "prod"]);
+    hostctl_ok(dir.path(), &["add-entry", "prod", "10.0.0.1", "api.prod"]);
+    hostctl_ok(dir.path(), &["protect", "prod", "--locked"]);
+    let log: serde_json::Value =
+        serde_json::from_str(&hostctl_ok(dir.path(), &["log", "-o", "json"])).unwrap();
+    assert_eq!(log[2]["message"], "add prod");
+    let empty = log[2]["hash"].as_str().unwrap();
+    let commits = log.as_array().unwrap().len();
+
+    // Checking out a state without the entry would modify the locked environment
+    let output = hostctl_in(dir.path(), &["checkout", empty, "--force"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(str::from_utf8(&output.stderr).unwrap().contains("locked"));
+    let show = hostctl_ok(dir.path(), &["show", "prod"]);
+    assert!(show.contains("api.prod"));
+
+    // A protected environment needs confirmation, which cannot be given without a terminal
+    hostctl_ok(dir.path(), &["unprotect", "prod", "--force"]);
+    hostctl_ok(dir.path(), &["protect", "prod"]);
+    let output = hostctl_in(dir.path(), &["checkout", empty]);
+    assert_eq!(output.status.code(), Some(3));
+    let show = hostctl_ok(dir.path(), &["show", "prod"]);
+    assert!(show.contains("api.prod"));
+    let log: serde_json::Value =
+        serde_json::from_str(&hostctl_ok(dir.path(), &["log", "-o", "json"])).unwrap();
+    assert_eq!(log.as_array().unwrap().len(), commits + 2);
+    assert_eq!(log[0]["message"], "protect prod");
+
+    hostctl_ok(dir.path(), &["checkout", empty, "--force"]);
+    let show = hostctl_ok(dir.path(), &["show", "prod"]);
+    assert!(!show.contains("api.prod"));
+}
+
+#[test]
+fn test_cli_config_git_checkout() {
+    let dir = tempfile::tempdir().unwrap();
+    let hosts_path = dir.path().join("hosts");
+    let hosts = || std::fs::read_to_string(&hosts_path).unwrap();
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.dev"]);
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+    hostctl_ok(dir.path(), &["config", "git"]);
+    assert!(dir.path().join(".git").is_dir());
+
+    // Local paths given as global options are left out of the commit messages
+    let config = format!("--config={}", dir.path().join("hostctl.yaml").display());
+    hostctl_ok(
+        dir.path(),
+        &[
+            &config,
+            "add-entry",
+            "dev",
+            "10.0.0.2",
+            "web.dev",
+            "--hosts-file",
+            hosts_path.to_str().unwrap(),
+        ],
+    );
+    hostctl_ok(dir.path(), &["switch", "dev"]);
+    assert!(hosts().contains("10.0.0.2 web.dev"));
+
+    // Newest commit first, each named after the command that made it
+    let log = hostctl_ok(dir.path(), &["log"]);
+    let messages: Vec<&str> = log
+        .lines()
+        .map(|line| line.splitn(3, "  ").nth(2).unwrap())
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "switch dev",
+            "add-entry dev 10.0.0.2 web.dev",
+            "Initialize hostctl configuration"
+        ],
+        "{log}"
+    );
+
+    let initial = log
+        .lines()
+        .last()
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap();
+    let stdout = hostctl_ok(dir.path(), &["checkout", initial]);
+    assert!(stdout.contains(&format!("Restored configuration from {initial}")));
+    let show = hostctl_ok(dir.path(), &["show", "dev"]);
+    assert!(show.contains("api.dev") && !show.contains("web.dev"));
+    assert!(hosts().contains("10.0.0.1 api.dev"));
+    assert!(!hosts().contains("web.dev"));
+
+    let log: serde_json::Value =
+        serde_json::from_str(&hostctl_ok(dir.path(), &["log", "-o", "json"])).unwrap();
+    assert_eq!(log[0]["message"], format!("checkout {initial}"));
+    assert_eq!(log.as_array().unwrap().len(), 4);
+}
+
+#[test]
+fn test_cli_sources_update() {
+    use std::io::{BufRead, BufReader, Write};
+
+    // Serve the remote file once from a local server
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/envs.yaml", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let body = "environments:\n  dev:\n    entries:\n    - ip: 10.0.0.1\n      hostname: api.corp.local\n";
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && !line.trim().is_empty() {
+            line.clear();
+        }
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "mine"]);
+    let config_path = dir.path().join("hostctl.yaml");
+    let mut config = std::fs::read_to_string(&config_path).unwrap();
+    config.push_str(&format!("sources:\n  - name: platform\n    url: {url}\n"));
+    std::fs::write(&config_path, config).unwrap();
+
+    let stdout = hostctl_ok(dir.path(), &["sources", "list"]);
+    assert!(stdout.contains(&format!("platform: {url} (never fetched)")));
+
+    let stdout = hostctl_ok(dir.path(), &["sources", "update"]);
+    assert!(
+        stdout.contains("platform: updated (1 environment(s))"),
+        "{stdout}"
+    );
+    server.join().unwrap();
+
+    let stdout = hostctl_ok(dir.path(), &["sources", "list"]);
+    assert!(
+        stdout.contains(&format!("platform: {url} (fetched ")),
+        "{stdout}"
+    );
+    let stdout = hostctl_ok(dir.path(), &["list"]);
+    assert!(
+        stdout.contains("platform/dev: 1 entries [remote]"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_cli_sources_update_survives_unreadable_cache() {
+    use std::io::{BufRead, BufReader, Write};
+
+    // Nothing listens on a port that was bound and released
+    let unreachable = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}/envs.yaml", listener.local_addr().unwrap())
+    };
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/envs.yaml", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let body = "environments:\n  dev:\n    entries:\n    - ip: 10.0.0.1\n      hostname: api.corp.local\n";
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && !line.trim().is_empty() {
+            line.clear();
+        }
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "mine"]);
+    let config_path = dir.path().join("hostctl.yaml");
+    let mut config = std::fs::read_to_string(&config_path).unwrap();
+    config.push_str(&format!(
+        "sources:\n  - name: broken\n    url: {unreachable}\n  - name: platform\n    url: {url}\n"
+    ));
+    std::fs::write(&config_path, config).unwrap();
+    let cache_dir = dir.path().join("cache").join("sources");
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    std::fs::write(cache_dir.join("broken.json"), "not json").unwrap();
+
+    let output = hostctl_in(dir.path(), &["sources", "update"]);
+    server.join().unwrap();
+    assert!(!output.status.success());
+    let stdout = str::from_utf8(&output.stdout).unwrap();
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stdout.contains("platform: updated (1 environment(s))"),
+        "{stdout}"
+    );
+    assert!(stderr.contains("broken: "), "{stderr}");
+    assert!(stderr.contains("no usable copy"), "{stderr}");
+    assert!(stderr.contains("Failed to update 1 source(s)."), "{stderr}");
+}
+
+#[test]
+fn test_cli_bundle_import_refuses_unsigned() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.json");
+    std::fs::write(
+        &path,
+        r#"{"format": "hostctl-bundle/1", "payload": "{\"exported_at\":\"2026-01-01T00:00:00Z\",\"environment\":{\"name\":\"x\",\"description\":null,\"entries\":[]}}"}"#,
+    )
+    .unwrap();
+
+    let output = hostctl_in(dir.path(), &["bundle", "import", path.to_str().unwrap()]);
+
+    assert!(!output.status.success());
+
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("not signed"));
+    let stdout = hostctl_ok(dir.path(), &["list"]);
+    assert!(stdout.contains("No environments configured."));
+}
+
+#[test]
+fn test_cli_bundle_import_refuses_dynamic_sources() {
+    let exporter = tempfile::tempdir().unwrap();
+    hostctl_ok(exporter.path(), &["add", "dev"]);
+    hostctl_ok(
+        exporter.path(),
+        &["add-entry", "dev", "cmd:echo 10.0.0.1", "api.dev"],
+    );
+    let path = exporter.path().join("dev.bundle.json");
+    hostctl_ok(
+        exporter.path(),
+        &["bundle", "export", "dev", "-o", path.to_str().unwrap()],
+    );
+
+    let importer = tempfile::tempdir().unwrap();
+    let output = hostctl_in(
+        importer.path(),
+        &[
+            "bundle",
+            "import",
+            path.to_str().unwrap(),
+            "--allow-untrusted",
+        ],
+    );
+    assert!(!output.status.success());
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("dynamic IP source for 'api.dev'"),
+        "{stderr}"
+    );
+    let stdout = hostctl_ok(importer.path(), &["list"]);
+    assert!(stdout.contains("No environments configured."));
+}
+
+#[test]
+fn test_cli_merge_driver() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |name: &str, content: &str| {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    };
+    let base = write("base", "environments:\n  dev:\n    entries: []\n");
+    let ours = write(
+        "ours",
+        "environments:\n  dev:\n    entries:\n    - ip: 10.0.0.1\n      hostname: api.local\n",
+    );
+    let theirs = write(
+        "theirs",
+        "environments:\n  dev:\n    entries:\n    - ip: 10.0.0.2\n      hostname: db.local\n",
+    );
+
+    let path = |path: &std::path::Path| path.to_str().unwrap().to_string();
+    hostctl_ok(
+        dir.path(),
+        &[
+            "merge-driver",
+            &path(&base),
+            &path(&ours),
+            &path(&theirs),
+            "config.yaml",
+        ],
+    );
+
+    let merged = std::fs::read_to_string(&ours).unwrap();
+    assert!(merged.contains("api.local"));
+    assert!(merged.contains("db.local"));
+}
+
+/// Test that a damaged config is reported with its location and can be repaired
+#[test]
+fn test_cli_config_repair() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("hostctl.yaml"),
+        "version: 1\nenvironments:\n  dev:\n    entries: []\n  qa:\n    entries: [\n",
+    )
+    .unwrap();
+
+    let output = hostctl_in(dir.path(), &["list"]);
+    assert!(!output.status.success());
+    let stderr = str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("hostctl.yaml at line 7"), "{stderr}");
+    assert!(stderr.contains("hostctl config repair"));
+
+    let stdout = hostctl_ok(dir.path(), &["config", "repair"]);
+    assert!(stdout.contains("Recovered: dev"));
+    assert!(stdout.contains("Lost: environment 'qa'"));
+
+    assert!(hostctl_ok(dir.path(), &["list"]).contains("dev"));
+    assert!(dir.path().join("last-good.yaml").exists());
+}
+
+/// Test the whole CLI against a root directory instead of the real system files
+#[test]
+fn test_cli_switch_below_root() {
+    let root = tempfile::tempdir().unwrap();
+    let hosts_path = root.path().join("etc").join("hosts");
+    std::fs::create_dir_all(hosts_path.parent().unwrap()).unwrap();
+    std::fs::write(&hosts_path, "127.0.0.1 localhost\n").unwrap();
+    let hostctl = |args: &[&str]| {
+        let output = Command::new("cargo")
+            .args(["run", "--", "--root"])
+            .arg(root.path())
+            .args(args)
+            .env_remove("HOSTCTL_CONFIG")
+            .env_remove("HOSTCTL_HOSTS_FILE")
+            .output()
+            .expect("Failed to execute command");
+        assert!(
+            output.status.success(),
+            "{args:?}: {}",
+            str::from_utf8(&output.stderr).unwrap()
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    hostctl(&["add", "dev"]);
+    hostctl(&["add-entry", "dev", "10.0.0.1", "api.local"]);
+    hostctl(&["switch", "dev"]);
+    assert!(hostctl(&["current"]).contains("dev"));
+
+    let hosts = std::fs::read_to_string(&hosts_path).unwrap();
+    assert!(hosts.starts_with("127.0.0.1 localhost\n"));
+    assert!(hosts.contains("10.0.0.1 api.local"));
+
+    // The system config is looked up below the root too
+    assert!(hostctl(&["config", "sources"]).contains(&root.path().display().to_string()));
+}
+
+/// Test switching the hosts files of targets independently of the system one
+#[test]
+fn test_cli_switch_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let devbox_path = dir.path().join("devbox").join("hosts");
+    std::fs::create_dir_all(devbox_path.parent().unwrap()).unwrap();
+    std::fs::write(&devbox_path, "127.0.0.1 devbox\n").unwrap();
+    std::fs::write(
+        dir.path().join("hostctl.yaml"),
+        "environments: {}\ntargets:\n  devbox: devbox/hosts\n",
+    )
+    .unwrap();
+
+    for args in [
+        &["add", "dev"][..],
+        &["add-entry", "dev", "10.0.0.1", "api.local"],
+        &["add", "stage"],
+        &["add-entry", "stage", "10.0.1.1", "api.local"],
+        &["switch", "stage"],
+        &["switch", "dev", "--target", "devbox"],
+    ] {
+        hostctl_ok(dir.path(), args);
+    }
+
+    let devbox = std::fs::read_to_string(&devbox_path).unwrap();
+    assert!(devbox.starts_with("127.0.0.1 devbox\n"));
+    assert!(devbox.contains("10.0.0.1 api.local"));
+    let hosts = std::fs::read_to_string(dir.path().join("hosts")).unwrap();
+    assert!(hosts.contains("10.0.1.1 api.local"));
+
+    let status = hostctl_ok(dir.path(), &["status"]);
+    assert!(status.contains("Active environment: stage"));
+    assert!(status.contains("devbox: dev"));
+
+    let output = hostctl_in(dir.path(), &["switch", "dev", "--target", "missing"]);
+    assert!(!output.status.success());
+    assert!(
+        str::from_utf8(&output.stderr)
+            .unwrap()
+            .contains("Target 'missing' not found")
+    );
+}
+
+/// Test switching the hosts file of a process in a private mount namespace
+///
+/// Skipped where unprivileged or without `unshare`.
+#[cfg(target_os = "linux")]
+#[test]
+fn test_cli_switch_process() {
+    use std::io::{BufRead, Write};
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir().unwrap();
+    let rootfs_etc = dir.path().join("rootfs").join("etc");
+    std::fs::create_dir_all(&rootfs_etc).unwrap();
+    std::fs::write(
+        rootfs_etc.join("hosts"),
+        "127.0.0.1 localhost\n# hostctl-test-rootfs\n",
+    )
+    .unwrap();
+
+    // The shell waits on its stdin with a builtin, so nothing is run from the new /etc
+    let script = format!(
+        "mount --bind '{}' /etc && echo ready && read line",
+        rootfs_etc.display()
+    );
+    let Ok(mut process) = Command::new("unshare")
+        .args(["--mount", "--propagation", "private", "sh", "-c", &script])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return;
+    };
+    let mut ready = String::new();
+    std::io::BufReader::new(process.stdout.take().unwrap())
+        .read_line(&mut ready)
+        .unwrap();
+    let process_hosts = format!("/proc/{}/root/etc/hosts", process.id());
+    let mounted = std::fs::read_to_string(&process_hosts)
+        .is_ok_and(|hosts| hosts.contains("hostctl-test-rootfs"));
+    if ready.trim() != "ready" || !mounted {
+        let _ = process.kill();
+        let _ = process.wait();
+        return;
+    }
+
+    hostctl_ok(dir.path(), &["add", "dev"]);
+    hostctl_ok(dir.path(), &["add-entry", "dev", "10.0.0.1", "api.local"]);
+    let pid = process.id().to_string();
+    hostctl_ok(dir.path(), &["switch", "dev", "--pid", &pid]);
+
+    let hosts = std::fs::read_to_string(rootfs_etc.join("hosts")).unwrap();
+    assert!(hosts.starts_with("127.0.0.1 localhost\n"));
+    assert!(hosts.contains("10.0.0.1 api.local"));
+    // The hosts file of this process and the active environment are left alone
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("hosts")).unwrap(),
+        "127.0.0.1 localhost\n"
+    );
+    assert!(hostctl_ok(dir.path(), &["status"]).contains("Active environment: (none)"));
+
+    process.stdin.take().unwrap().write_all(b"\n").unwrap();
+    process.wait().unwrap();
+
+    let output = hostctl_in(dir.path(), &["switch", "dev", "--pid", &pid]);
+    assert!(!output.status.success());
+}
+
+/// Test selecting the config and hosts files through environment variables
+#[test]
+fn test_cli_config_and_hosts_file_from_environment() {
+    // hostctl_in selects both files through HOSTCTL_CONFIG and HOSTCTL_HOSTS_FILE
+    let dir = tempfile::tempdir().unwrap();
+    hostctl_ok(dir.path(), &["add", "ci"]);
+    hostctl_ok(dir.path(), &["add-entry", "ci", "10.0.0.2", "db.local"]);
+    hostctl_ok(dir.path(), &["switch", "ci"]);
+
+    assert!(
+        std::fs::read_to_string(dir.path().join("hostctl.yaml"))
+            .unwrap()
+            .contains("ci:")
+    );
+    assert!(
+        std::fs::read_to_string(dir.path().join("hosts"))
+            .unwrap()
+            .contains("10.0.0.2 db.local")
+    );
+
+    // Flags take precedence over the environment
+    let other = dir.path().join("other.yaml");
+    let stdout = hostctl_ok(dir.path(), &["--config", other.to_str().unwrap(), "list"]);
+    assert!(!stdout.contains("ci"));
+}
+
+/// Test applying and clearing an environment on a hosts file outside /etc
+#[test]
+fn test_apply_environment_to_hosts_file() {
+    use hostctl::config::{Environment, HostEntry};
+    use hostctl::hosts::HostsManager;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hosts");
+    std::fs::write(&path, "127.0.0.1 localhost\n::1 localhost\n").unwrap();
+    let hosts = HostsManager::new(path.clone());
+
+    let mut env = Environment::new("dev".to_string());
+    env.add_entry(HostEntry::new(
+        "10.0.0.1".parse().unwrap(),
+        "api.local".to_string(),
+    ));
+    hosts.apply_environment(&env).unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.starts_with("127.0.0.1 localhost\n::1 localhost\n"));
+    assert!(content.contains("# ===== hostctl managed entries =====\n10.0.0.1 api.local\n"));
+
+    // Switching replaces the managed section instead of appending to it
+    let mut qa = Environment::new("qa".to_string());
+    qa.add_entry(HostEntry::new(
+        "10.0.0.2".parse().unwrap(),
+        "api.local".to_string(),
+    ));
+    hosts.apply_environment(&qa).unwrap();
+    let entries = hosts.read_current_hosts().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[2].ip.to_string(), "10.0.0.2");
+
+    hosts.clear_environment().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "127.0.0.1 localhost\n::1 localhost\n"
+    );
+    let backups = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("hosts.backup.")
+        })
+        .count();
+    assert!(backups >= 1);
+}